@@ -127,7 +127,9 @@ fn parse_lsn<I, E: ParseError<I>>(input: I) -> IResult<I, PageXLogRecPtr, E>
 where
     I: Input<Item = u8>,
 {
-    (le_u32, le_u32).map(PageXLogRecPtr::new).parse(input)
+    (le_u32, le_u32)
+        .map(|(xlogid, xrecoff)| PageXLogRecPtr::from_parts(xlogid, xrecoff))
+        .parse(input)
 }
 
 fn parse_pagesize<I, E: ParseError<I>>(input: I) -> IResult<I, u16, E>
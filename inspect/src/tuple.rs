@@ -132,7 +132,7 @@ where
 {
     match type_output {
         TypeOutput::Int4 => le_i32.map(TupleValue::Int4).parse(input),
-        default => todo!("Type not handled: {:?}", type_output),
+        _ => todo!("Type not handled: {:?}", type_output),
     }
 }
 
@@ -146,7 +146,7 @@ where
 {
     desc.attributes.iter().enumerate().map(|(idx, attr)| {
         if heap_tuple.t_bits.contains(idx) {
-            None
+            None::<TupleValue>
         } else {
             None
         }
@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use log::debug;
 
-use crate::xlog::block::{PageId, XLBData, XLBImage, BKPIMAGE_IS_COMPRESSED, BLCKSZ};
+use crate::xlog::block::{PageId, XLBData, XLBImage, DEFAULT_BLCKSZ};
 use crate::xlog::operation::heap::{HeapOperation, Insert};
 use crate::xlog::record::{Operation, RmgrId, XLogRecord};
 
@@ -12,11 +12,12 @@ pub struct ApplyError {
 }
 
 pub struct Page {
-    pub data: [u8; BLCKSZ as usize],
+    pub data: Vec<u8>,
 }
 
 pub struct PageMapping {
     pub pages: HashMap<PageId, Page>,
+    blcksz: u16,
 }
 
 impl Default for PageMapping {
@@ -28,7 +29,15 @@ impl Default for PageMapping {
 impl PageMapping {
     pub fn new() -> Self {
         let pages = HashMap::new();
-        Self { pages }
+        Self {
+            pages,
+            blcksz: DEFAULT_BLCKSZ,
+        }
+    }
+
+    /// Set the relation page size of restored full page images
+    pub fn set_blcksz(&mut self, blcksz: u16) {
+        self.blcksz = blcksz;
     }
 
     pub fn apply_xlog_record(&mut self, record: &XLogRecord) -> Result<(), ApplyError> {
@@ -57,27 +66,9 @@ impl PageMapping {
         };
 
         debug!("Restoring full page image");
-        if (image.bimg_info & BKPIMAGE_IS_COMPRESSED) != 0 {
-            todo!("COMPRESSION NOT IMPLEMENTED");
-        }
-
-        let mut data_vec = Vec::new();
-        let page_vec = if image.hole_length > 0 {
-            // We have a hole
-            // Copy data before the hole
-            data_vec.extend_from_slice(&image.bkp_image[..image.hole_offset as usize]);
-            // Fill the hole with 0
-            data_vec.extend(std::iter::repeat_n(0, image.hole_length as usize));
-            // Copy leftover data
-            data_vec.extend_from_slice(&image.bkp_image[image.hole_offset as usize..]);
-            &data_vec
-        } else {
-            // No hole, just copy the data content
-            &image.bkp_image
-        };
-
-        // TODO: Better check on vec size
-        let data: [u8; BLCKSZ as usize] = page_vec.as_slice().try_into().unwrap();
+        let data = image.restore_page(self.blcksz).map_err(|e| ApplyError {
+            message: format!("Can't restore image of {}: {}", page_id, e),
+        })?;
         self.pages.insert(page_id, Page { data });
         Ok(())
     }
@@ -94,7 +85,16 @@ impl PageMapping {
                 HeapOperation::Insert(insert) => self.apply_heap_insert(record, insert),
                 HeapOperation::Update(_update) => todo!(),
                 HeapOperation::Prune(_prune) => todo!(),
-                HeapOperation::Placeholder => Ok(()),
+                HeapOperation::Truncate(_)
+                | HeapOperation::HotUpdate(_)
+                | HeapOperation::Confirm(_)
+                | HeapOperation::Lock(_)
+                | HeapOperation::Inplace(_) => Err(ApplyError {
+                    message: format!(
+                        "Unsupported heap operation at {}: {}",
+                        record.lsn, heap_operation
+                    ),
+                }),
             },
             Operation::Heap2 => todo!(),
             Operation::Btree => todo!(),
@@ -2,6 +2,8 @@ use std::fmt;
 
 use nom::error::{ErrorKind, ParseError};
 
+use crate::xlog::record::RmgrId;
+
 #[derive(Debug)]
 pub enum XLogError<I: Sized> {
     /// No more data available
@@ -16,6 +18,8 @@ pub enum XLogError<I: Sized> {
     OutOfOrderBlock,
     InvalidForkNumber(u8),
     InvalidResourceManager(u8),
+    /// Resource manager and record type in xl_info it doesn't know
+    UnknownRecordInfo(RmgrId, u8),
     UnexpectedBlockDataLen(u16),
     IncorrectId(u8),
     IncorrectPageType,
@@ -24,11 +28,50 @@ pub enum XLogError<I: Sized> {
     IncorrectPaddingValue(Vec<u8>),
     IncorrectPaddingLength(usize),
     InvalidRecord(String),
+    InvalidContRecord(u32, usize),
 
     /// An error encountered during parsing
     NomParseError(I, ErrorKind),
 }
 
+impl XLogError<&[u8]> {
+    /// Copy the offending input so the error can outlive the parsed buffer
+    pub fn into_owned(self) -> XLogError<Vec<u8>> {
+        match self {
+            XLogError::Eof => XLogError::Eof,
+            XLogError::Placeholder => XLogError::Placeholder,
+            XLogError::InvalidPageHeader => XLogError::InvalidPageHeader,
+            XLogError::EmptyRecord => XLogError::EmptyRecord,
+            XLogError::EndBlock => XLogError::EndBlock,
+            XLogError::MissingBlockDataLen => XLogError::MissingBlockDataLen,
+            XLogError::InvalidBlockImageHole(hole_offset, hole_length, bimg_len) => {
+                XLogError::InvalidBlockImageHole(hole_offset, hole_length, bimg_len)
+            }
+            XLogError::InvalidBlockId(previous, current) => {
+                XLogError::InvalidBlockId(previous, current)
+            }
+            XLogError::OutOfOrderBlock => XLogError::OutOfOrderBlock,
+            XLogError::InvalidForkNumber(f) => XLogError::InvalidForkNumber(f),
+            XLogError::InvalidResourceManager(r) => XLogError::InvalidResourceManager(r),
+            XLogError::UnknownRecordInfo(rmid, info) => XLogError::UnknownRecordInfo(rmid, info),
+            XLogError::UnexpectedBlockDataLen(d) => XLogError::UnexpectedBlockDataLen(d),
+            XLogError::IncorrectId(u) => XLogError::IncorrectId(u),
+            XLogError::IncorrectPageType => XLogError::IncorrectPageType,
+            XLogError::InvalidDataLen(consumed, expected) => {
+                XLogError::InvalidDataLen(consumed, expected)
+            }
+            XLogError::LeftoverBytes(leftover) => XLogError::LeftoverBytes(leftover),
+            XLogError::IncorrectPaddingValue(padding) => XLogError::IncorrectPaddingValue(padding),
+            XLogError::IncorrectPaddingLength(length) => XLogError::IncorrectPaddingLength(length),
+            XLogError::InvalidRecord(e) => XLogError::InvalidRecord(e),
+            XLogError::InvalidContRecord(rem_len, expected) => {
+                XLogError::InvalidContRecord(rem_len, expected)
+            }
+            XLogError::NomParseError(i, kind) => XLogError::NomParseError(i.to_vec(), kind),
+        }
+    }
+}
+
 impl<I> ParseError<I> for XLogError<I> {
     fn from_error_kind(input: I, kind: ErrorKind) -> Self {
         XLogError::NomParseError(input, kind)
@@ -56,6 +99,9 @@ where
             XLogError::EmptyRecord => write!(f, "Empty record"),
             XLogError::InvalidForkNumber(u) => write!(f, "Invalid fork value: {}", u),
             XLogError::InvalidResourceManager(u) => write!(f, "Invalid resource manager: {}", u),
+            XLogError::UnknownRecordInfo(rmid, info) => {
+                write!(f, "Unknown {} record info 0x{:02X}", rmid, info)
+            }
             XLogError::EndBlock => write!(f, "End block"),
             XLogError::InvalidBlockImageHole(hole_offset, hole_length, bimg_len) => {
                 write!(
@@ -90,6 +136,11 @@ where
                 write!(f, "Incorrect padding length {}", length)
             }
             XLogError::InvalidRecord(e) => write!(f, "Invalid XLog Record {:?}", e),
+            XLogError::InvalidContRecord(rem_len, expected) => write!(
+                f,
+                "Invalid contrecord, remaining length {}, expected {}",
+                rem_len, expected
+            ),
             XLogError::NomParseError(i, e) => {
                 write!(f, "Internal parser error {:?}, input {:x?}", e, i)
             }
@@ -1,26 +1,198 @@
-use clap::Parser;
-use std::path::PathBuf;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use wal_analyzer::xlog::block::DEFAULT_BLCKSZ;
+use wal_analyzer::xlog::lsn::{TimelineID, XLogRecPtr};
 use wal_analyzer::xlog::reader::XLogReader;
+use wal_analyzer::xlog::record::XLogRecord;
+use wal_analyzer::xlog::source::{ArchiveDirectory, RestoreCommand, SegmentSource};
 
 /// A PostgreSQL XLOG analyzer CLI tool
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// Path to WAL segment to process
-    wal_segment: PathBuf,
+    /// First WAL segment to process, either a file name or a path, or - to
+    /// read WAL from stdin
+    start_segment: Option<PathBuf>,
 
-    /// Limit of records to process
+    /// Last WAL segment to process
+    end_segment: Option<String>,
+
+    /// Directory containing WAL segments
+    #[arg(short, long)]
+    path: Option<PathBuf>,
+
+    /// Fetch segments with this command instead of reading a directory, %f is
+    /// replaced by the file name and %p by the path to copy it to
+    #[arg(long)]
+    restore_command: Option<String>,
+
+    /// Start reading at this WAL location
+    #[arg(short, long)]
+    start: Option<XLogRecPtr>,
+
+    /// Stop reading at this WAL location
+    #[arg(short, long)]
+    end: Option<XLogRecPtr>,
+
+    /// Timeline to follow when starting from a WAL location
+    #[arg(short, long, default_value_t = 1)]
+    timeline: TimelineID,
+
+    /// Relation page size of the cluster, for builds with a non-default --with-blocksize
+    #[arg(long, default_value_t = DEFAULT_BLCKSZ)]
+    block_size: u16,
+
+    /// Keep waiting for new WAL after reaching the end of available WAL
+    #[arg(short, long)]
+    follow: bool,
+
+    /// Keep going after records with an invalid CRC instead of stopping
+    #[arg(long)]
+    continue_on_crc_error: bool,
+
+    /// Stop after processing this many records
     #[arg(short, long)]
     record_limit: Option<u64>,
+
+    /// Save full page images to this directory, decompressed and with their
+    /// hole restored, named like pg_waldump --save-fullpage does
+    #[arg(long)]
+    save_fullpage: Option<PathBuf>,
+}
+
+/// How often new WAL is looked for in follow mode
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Write the full page images of record to dir as
+/// TLI-LSNHI-LSNLO.SPC.DB.REL.BLK_FORK files
+fn save_fullpages(
+    dir: &Path,
+    tli: TimelineID,
+    record: &XLogRecord,
+    blcksz: u16,
+) -> Result<(), Box<dyn Error>> {
+    for block in &record.blocks {
+        let (Some(image), Some(page_id)) = (&block.image, &block.page_id) else {
+            continue;
+        };
+        let page = image.restore_page(blcksz)?;
+        let lsn = record.lsn.as_u64();
+        let filename = format!(
+            "{:08X}-{:08X}-{:08X}.{}.{}.{}.{}_{}",
+            tli,
+            lsn >> 32,
+            lsn as u32,
+            page_id.locator.spc_node,
+            page_id.locator.db_node,
+            page_id.locator.rel_node,
+            page_id.blockno,
+            page_id.fork.name()
+        );
+        fs::write(dir.join(filename), page)?;
+    }
+    Ok(())
+}
+
+/// Source of segments: the restore command when given, the directory otherwise
+fn segment_source(args: &Args, data_dir: PathBuf) -> Box<dyn SegmentSource> {
+    match &args.restore_command {
+        Some(command) => Box::new(
+            RestoreCommand::new(command.clone()).expect("Error creating restore directory"),
+        ),
+        None => Box::new(ArchiveDirectory::new(data_dir)),
+    }
 }
 
 fn main() {
     let args = Args::parse();
     env_logger::init();
 
-    let reader = XLogReader::new_from_filename(args.wal_segment).expect("Error building reader");
+    let mut reader = match (&args.start_segment, args.start) {
+        (Some(start_segment), start) if start_segment.as_os_str() == "-" => {
+            let mut reader =
+                XLogReader::from_segment_reader(std::io::stdin()).expect("Error reading stdin");
+            if let Some(start) = start {
+                reader.seek(start).expect("Error seeking to start location");
+            }
+            reader
+        }
+        (Some(start_segment), start) => {
+            let start_segment_name = start_segment
+                .file_name()
+                .and_then(|f| f.to_str())
+                .expect("Invalid start segment");
+            // Without an explicit path, segments are looked up next to the start segment
+            let data_dir = match &args.path {
+                Some(path) => path.clone(),
+                None => start_segment
+                    .parent()
+                    .map_or(PathBuf::from("."), |p| p.to_path_buf()),
+            };
+            let mut reader = XLogReader::from_source(
+                segment_source(&args, data_dir),
+                start_segment_name,
+                args.end_segment.as_deref(),
+            )
+            .expect("Error building reader");
+            if let Some(start) = start {
+                reader.seek(start).expect("Error seeking to start location");
+            }
+            reader
+        }
+        (None, Some(start)) => {
+            let data_dir = args.path.clone().unwrap_or(PathBuf::from("."));
+            XLogReader::from_source_at_lsn(segment_source(&args, data_dir), args.timeline, start)
+                .expect("Error building reader")
+        }
+        (None, None) => Args::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "either a start segment or a start location is required",
+            )
+            .exit(),
+    };
+    if let Some(end) = args.end {
+        reader.set_end_lsn(end);
+    }
+    reader.set_blcksz(args.block_size);
+    reader.set_continue_on_crc_error(args.continue_on_crc_error);
+    if args.follow {
+        reader.set_follow(Some(FOLLOW_POLL_INTERVAL));
+    }
+    if let Some(dir) = &args.save_fullpage {
+        fs::create_dir_all(dir).expect("Error creating full page image directory");
+    }
 
-    for record in reader {
-        print!("{}", record);
+    let mut error = None;
+    let mut processed = 0;
+    while args.record_limit.is_none_or(|limit| processed < limit) {
+        let Some(record) = reader.next() else {
+            break;
+        };
+        match record {
+            Ok(record) => {
+                processed += 1;
+                print!("{}", record);
+                if let Some(dir) = &args.save_fullpage {
+                    save_fullpages(dir, reader.current_tli(), &record, args.block_size)
+                        .expect("Error saving full page images");
+                }
+            }
+            Err(e) => {
+                error = Some(e);
+                break;
+            }
+        }
+    }
+    if let Some(e) = error {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+    if let Some(end_of_wal) = reader.end_of_wal() {
+        eprintln!("{}", end_of_wal);
     }
 }
@@ -1,16 +1,15 @@
+use std::borrow::Cow;
+
 use crate::error::XLogError;
-use crate::xlog::common::TransactionId;
-use crate::xlog::reader::XLogRecPtr;
+use crate::xlog::common::{RepOriginId, TransactionId};
+use crate::xlog::lsn::XLogRecPtr;
+use crate::xlog::page::WalVersion;
+use crate::xlog::pglz;
 use crate::xlog::record::RmgrId;
 use log::debug;
-use nom::branch::alt;
 use nom::bytes::complete::take;
-use nom::combinator::{map, verify};
-use nom::error::{context, ContextError, ParseError};
 use nom::number::complete::{le_u16, le_u32, le_u8};
-use nom::sequence::preceded;
 use nom::IResult;
-use nom::Parser;
 
 pub const BKPBLOCK_FORK_MASK: u8 = 0x0F;
 pub const BKPBLOCK_FLAG_MASK: u8 = 0xF0;
@@ -31,10 +30,21 @@ pub const BKPIMAGE_IS_COMPRESSED: u8 = 0x02;
 ///page image should be restored during replay
 pub const BKPIMAGE_APPLY: u8 = 0x04;
 
+// Since PostgreSQL 15, the compression method has its own flag and the
+// apply flag moved
+/// page image should be restored during replay (PG15+)
+pub const BKPIMAGE_APPLY_V15: u8 = 0x02;
+/// page image is compressed with pglz (PG15+)
+pub const BKPIMAGE_COMPRESS_PGLZ: u8 = 0x04;
+/// page image is compressed with lz4 (PG15+)
+pub const BKPIMAGE_COMPRESS_LZ4: u8 = 0x08;
+/// page image is compressed with zstd (PG15+)
+pub const BKPIMAGE_COMPRESS_ZSTD: u8 = 0x10;
+
 const XLR_MAX_BLOCK_ID: u8 = 32;
 
-// TODO: Make this configurable
-pub const BLCKSZ: u16 = 8192;
+/// Relation page size used when PostgreSQL is built with default options
+pub const DEFAULT_BLCKSZ: u16 = 8192;
 pub type BlockNumber = u32;
 
 #[derive(Debug, Clone, PartialEq, Hash, Eq, Copy)]
@@ -59,6 +69,18 @@ impl TryFrom<u8> for ForkNumber {
     }
 }
 
+impl ForkNumber {
+    /// Suffix of the fork's relation files, as in PostgreSQL's forkNames
+    pub fn name(&self) -> &'static str {
+        match self {
+            ForkNumber::Main => "main",
+            ForkNumber::Fsm => "fsm",
+            ForkNumber::VisibilityMap => "vm",
+            ForkNumber::Init => "init",
+        }
+    }
+}
+
 impl std::fmt::Display for ForkNumber {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         let s = match self {
@@ -101,24 +123,116 @@ impl std::fmt::Display for PageId {
     }
 }
 
+/// Compression method of a full page image, set by wal_compression
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ImageCompression {
+    #[default]
+    None,
+    Pglz,
+    Lz4,
+    Zstd,
+}
+
+impl std::fmt::Display for ImageCompression {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            ImageCompression::None => "none",
+            ImageCompression::Pglz => "pglz",
+            ImageCompression::Lz4 => "lz4",
+            ImageCompression::Zstd => "zstd",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImageError {
+    #[error("Error decompressing {0} page image: {1}")]
+    Decompression(ImageCompression, String),
+    #[error("Page image is {0} bytes without its hole, expected {1}")]
+    InvalidLength(usize, usize),
+    #[error("Invalid hole at offset {0} of length {1} for a {2} bytes page")]
+    InvalidHole(u16, u16, u16),
+}
+
+/// Full page image of a block. Image bytes are owned by default, B is a
+/// slice or a Cow for images borrowing the record's bytes.
 #[derive(Debug, Clone)]
-pub struct XLBImage {
+pub struct XLBImage<B = Vec<u8>> {
     /// has image that should be restored
     pub apply_image: bool,
     pub hole_offset: u16,
     pub hole_length: u16,
     pub bimg_len: u16,
     pub bimg_info: u8,
-    pub bkp_image: Vec<u8>,
+    pub compression: ImageCompression,
+    pub bkp_image: B,
 }
 
-impl std::fmt::Display for XLBImage {
+impl<B> XLBImage<B> {
+    /// Convert the image's bytes with f
+    pub fn map_bytes<C>(self, f: impl Fn(B) -> C) -> XLBImage<C> {
+        XLBImage {
+            apply_image: self.apply_image,
+            hole_offset: self.hole_offset,
+            hole_length: self.hole_length,
+            bimg_len: self.bimg_len,
+            bimg_info: self.bimg_info,
+            compression: self.compression,
+            bkp_image: f(self.bkp_image),
+        }
+    }
+}
+
+impl<B: AsRef<[u8]>> XLBImage<B> {
+    /// Rebuild the blcksz bytes page: decompress the image and fill its hole
+    /// with zeroes, as RestoreBlockImage does
+    pub fn restore_page(&self, blcksz: u16) -> Result<Vec<u8>, ImageError> {
+        let invalid_hole = || ImageError::InvalidHole(self.hole_offset, self.hole_length, blcksz);
+        let raw_len = blcksz
+            .checked_sub(self.hole_length)
+            .ok_or_else(invalid_hole)? as usize;
+        let image = self.bkp_image.as_ref();
+        let decompression_error = |e: String| ImageError::Decompression(self.compression, e);
+        let raw = match self.compression {
+            ImageCompression::None => Cow::Borrowed(image),
+            ImageCompression::Pglz => pglz::decompress(image, raw_len)
+                .map(Cow::Owned)
+                .ok_or_else(|| decompression_error(String::from("corrupted data")))?,
+            ImageCompression::Lz4 => lz4_flex::block::decompress(image, raw_len)
+                .map(Cow::Owned)
+                .map_err(|e| decompression_error(e.to_string()))?,
+            ImageCompression::Zstd => zstd::bulk::decompress(image, raw_len)
+                .map(Cow::Owned)
+                .map_err(|e| decompression_error(e.to_string()))?,
+        };
+        if raw.len() != raw_len {
+            return Err(ImageError::InvalidLength(raw.len(), raw_len));
+        }
+        let hole_offset = self.hole_offset as usize;
+        if hole_offset > raw_len {
+            return Err(invalid_hole());
+        }
+
+        let mut page = Vec::with_capacity(blcksz as usize);
+        page.extend_from_slice(&raw[..hole_offset]);
+        page.extend(std::iter::repeat_n(0, self.hole_length as usize));
+        page.extend_from_slice(&raw[hole_offset..]);
+        Ok(page)
+    }
+}
+
+impl<B> std::fmt::Display for XLBImage<B> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(
             f,
             "apply_image: {}, hole_offset: {}, hole_length: {}, len: {}, info: 0x{:X}",
             self.apply_image, self.hole_offset, self.hole_length, self.bimg_len, self.bimg_info
-        )
+        )?;
+        if self.compression != ImageCompression::None {
+            write!(f, ", compression: {}", self.compression)?;
+        }
+        Ok(())
     }
 }
 
@@ -187,8 +301,10 @@ pub struct XLogRecordBlockImageHeader {
     hole_length: Option<u16>,
 }
 
+/// A block reference of a record. Data is owned by default, B is a slice or
+/// a Cow for blocks borrowing the record's bytes.
 #[derive(Debug, Clone)]
-pub struct XLBData {
+pub struct XLBData<B = Vec<u8>> {
     pub blk_id: u8,
 
     // Identify the block this refers to
@@ -198,15 +314,30 @@ pub struct XLBData {
     pub flags: u8,
 
     // Information on full-page image, if any
-    pub image: Option<XLBImage>,
+    pub image: Option<XLBImage<B>>,
 
     // TODO: Probably redundant
     pub has_data: bool,
-    pub data_len: u16,
-    pub data: Option<Vec<u8>>,
+    pub data_len: u32,
+    pub data: Option<B>,
+}
+
+impl<B> XLBData<B> {
+    /// Convert the block's data and image bytes with f
+    pub fn map_bytes<C>(self, f: impl Fn(B) -> C) -> XLBData<C> {
+        XLBData {
+            blk_id: self.blk_id,
+            page_id: self.page_id,
+            flags: self.flags,
+            image: self.image.map(|image| image.map_bytes(&f)),
+            has_data: self.has_data,
+            data_len: self.data_len,
+            data: self.data.map(&f),
+        }
+    }
 }
 
-impl std::fmt::Display for XLBData {
+impl<B> std::fmt::Display for XLBData<B> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         let pageid_str = self
             .page_id
@@ -232,84 +363,34 @@ impl std::fmt::Display for XLBData {
     }
 }
 
-// fn ws<'a, O, E: ParseError<&'a str>, F: Parser<&'a str, Output = O, Error = E>>(
-//   f: F,
-// ) -> impl Parser<&'a str, Output = O, Error = E> {
-//   delimited(first: multispace0, second: f, third: multispace0)
-// }
-
-//fn byte<'a, E: ParseError<&'a [u8]>, F: Parser<&'a [u8], Output=u8, Error = E>>(value: u8)
-//-> impl Parser<&'a [u8], Output = u8, Error = E> {
-fn byte<'a, E: ParseError<&'a [u8]>>(value: u8) -> impl Parser<&'a [u8], Output = u8, Error = E> {
-    verify(le_u8, move |u| *u == value)
-}
+/// Parse the header of the record's main data: a one byte length in the
+/// short form, used for up to 255 bytes, or a four bytes one in the long
+/// form
+fn parse_main_data_block_header(i: &[u8]) -> IResult<&[u8], XLBData<&[u8]>, XLogError<&[u8]>> {
+    let (i, blk_id) = le_u8(i)?;
+    let (i, data_len) = match blk_id {
+        XLR_BLOCK_ID_DATA_SHORT => le_u8(i).map(|(i, x)| (i, u32::from(x)))?,
+        XLR_BLOCK_ID_DATA_LONG => le_u32(i)?,
+        // Not a main data header
+        _ => return Err(nom::Err::Error(XLogError::IncorrectId(blk_id))),
+    };
 
-// fn parse_short_data_block_header<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
-//     i: &'a [u8],
-// ) -> IResult<&'a [u8], XLBData, E> {
-//     let parse_short_length = le_u8.map(u16::from);
-//     let parse_long_length = le_u16;
-//
-//         preceded(byte(XLR_BLOCK_ID_DATA_SHORT), parse_short_length )
-//
-//         .map(|data_len| XLBData {
-//             blk_id: XLR_BLOCK_ID_DATA_SHORT,
-//             page_id: None,
-//             flags: 0,
-//             image: None,
-//             has_data: true,
-//             data_len,
-//             data: Some(vec![0; data_len as usize]),
-//         })
-//         .parse(i)
-// }
-
-fn parse_main_data_block_header<'a, E: ParseError<&'a [u8]> + ContextError<&'a [u8]>>(
-    i: &'a [u8],
-) -> IResult<&'a [u8], XLBData, E> {
-    //    let parse_short_length = le_u8.map(u16::from);
-    //    let parse_long_length = le_u16;
-
-    context(
-        "Xlog_Block",
-        alt((
-            preceded(
-                byte(XLR_BLOCK_ID_DATA_SHORT),
-                map(le_u8::<&'a [u8], E>, u16::from),
-            ),
-            preceded(byte(XLR_BLOCK_ID_DATA_LONG), le_u16),
-        )),
-    )
-    .parse(i);
-
-    todo!()
-    //    let (i, blk_id) = le_u8(i)?;
-    //    if blk_id < XLR_BLOCK_ID_DATA_LONG {
-    //        // Not a main block header, exit
-    //        return Err(nom::Err::Error(XLogError::IncorrectId(blk_id)));
-    //    }
-    //
-    //    let (i, data_len) = if blk_id == XLR_BLOCK_ID_DATA_SHORT {
-    //        le_u8(i).map(|(i, x)| (i, u16::from(x)))?
-    //    } else {
-    //        le_u16(i)?
-    //    };
-    //
-    //    let data = Some(vec![0; data_len as usize]);
-    //    let block_header = XLBData {
-    //        blk_id,
-    //        page_id: None,
-    //        flags: 0,
-    //        image: None,
-    //        has_data: true,
-    //        data_len,
-    //        data,
-    //    };
-    //    debug!("Parsed main block header {}", block_header);
-    //    Ok((i, block_header))
+    // Main data follows the blocks' data
+    let data: Option<&[u8]> = Some(&[]);
+    let block_header = XLBData {
+        blk_id,
+        page_id: None,
+        flags: 0,
+        image: None,
+        has_data: true,
+        data_len,
+        data,
+    };
+    debug!("Parsed main block header {}", block_header);
+    Ok((i, block_header))
 }
 
-fn parse_relfilenode(i: &[u8]) -> IResult<&[u8], RelFileLocator, XLogError<&[u8]>> {
+pub fn parse_relfilenode(i: &[u8]) -> IResult<&[u8], RelFileLocator, XLogError<&[u8]>> {
     let (i, spc_node) = le_u32(i)?;
     let (i, db_node) = le_u32(i)?;
     let (i, rel_node) = le_u32(i)?;
@@ -321,38 +402,72 @@ fn parse_relfilenode(i: &[u8]) -> IResult<&[u8], RelFileLocator, XLogError<&[u8]
     Ok((i, rnode))
 }
 
-fn parse_block_image(i: &[u8]) -> IResult<&[u8], XLBImage, XLogError<&[u8]>> {
+/// Decode bimg_info's apply flag and compression method, whose bits
+/// changed in PostgreSQL 15
+fn parse_bimg_info(version: WalVersion, bimg_info: u8) -> (bool, ImageCompression) {
+    if version < WalVersion::V15 {
+        let compression = if bimg_info & BKPIMAGE_IS_COMPRESSED != 0 {
+            ImageCompression::Pglz
+        } else {
+            ImageCompression::None
+        };
+        return (bimg_info & BKPIMAGE_APPLY != 0, compression);
+    }
+    let compression = if bimg_info & BKPIMAGE_COMPRESS_PGLZ != 0 {
+        ImageCompression::Pglz
+    } else if bimg_info & BKPIMAGE_COMPRESS_LZ4 != 0 {
+        ImageCompression::Lz4
+    } else if bimg_info & BKPIMAGE_COMPRESS_ZSTD != 0 {
+        ImageCompression::Zstd
+    } else {
+        ImageCompression::None
+    };
+    (bimg_info & BKPIMAGE_APPLY_V15 != 0, compression)
+}
+
+fn parse_block_image(
+    blcksz: u16,
+    version: WalVersion,
+    i: &[u8],
+) -> IResult<&[u8], XLBImage<&[u8]>, XLogError<&[u8]>> {
     let (i, bimg_len) = le_u16(i)?;
     let (i, hole_offset) = le_u16(i)?;
     let (i, bimg_info) = le_u8(i)?;
 
-    let apply_image = (bimg_info & BKPIMAGE_APPLY) != 0;
-    let is_compressed = (bimg_info & BKPIMAGE_IS_COMPRESSED) != 0;
+    let (apply_image, compression) = parse_bimg_info(version, bimg_info);
+    let is_compressed = compression != ImageCompression::None;
     let has_hole = (bimg_info & BKPIMAGE_HAS_HOLE) != 0;
-    let (i, hole_length) = if is_compressed {
-        if has_hole {
-            le_u16(i)?
-        } else {
-            (i, 0)
-        }
+    // Uncompressed images store the hole's length as the missing bytes
+    let (i, hole_length) = if !has_hole {
+        (i, 0)
+    } else if is_compressed {
+        le_u16(i)?
     } else {
-        (i, BLCKSZ - bimg_len)
+        (i, blcksz.saturating_sub(bimg_len))
     };
 
-    if has_hole && (hole_offset == 0 || hole_length == 0 || bimg_len == BLCKSZ) {
+    // Same sanity checks as DecodeXLogRecord
+    let invalid_hole = if has_hole {
+        hole_offset == 0 || hole_length == 0 || bimg_len == blcksz
+    } else {
+        hole_offset != 0 || (!is_compressed && bimg_len != blcksz)
+    };
+    if invalid_hole || (is_compressed && bimg_len == blcksz) {
         return Err(nom::Err::Error(XLogError::InvalidBlockImageHole(
             hole_offset,
             hole_length,
             bimg_len,
         )));
     }
-    let bkp_image = vec![0; bimg_len as usize];
+    // Image bytes follow the block headers
+    let bkp_image: &[u8] = &[];
     let xlb_image = XLBImage {
         apply_image,
         hole_offset,
         hole_length,
         bimg_len,
         bimg_info,
+        compression,
         bkp_image,
     };
     debug!("Parsed block image {:?}", xlb_image);
@@ -360,12 +475,14 @@ fn parse_block_image(i: &[u8]) -> IResult<&[u8], XLBImage, XLogError<&[u8]>> {
 }
 
 fn parse_data_block_header<'a>(
-    previous_block: Option<&XLBData>,
+    blcksz: u16,
+    version: WalVersion,
+    previous_block: Option<&XLBData<&'a [u8]>>,
     i: &'a [u8],
-) -> IResult<&'a [u8], XLBData, XLogError<&'a [u8]>> {
+) -> IResult<&'a [u8], XLBData<&'a [u8]>, XLogError<&'a [u8]>> {
     let (i, blk_id) = le_u8(i)?;
     if blk_id > XLR_MAX_BLOCK_ID {
-        return Err(nom::Err::Error(XLogError::EndBlock));
+        return Err(nom::Err::Error(XLogError::IncorrectId(blk_id)));
     }
 
     // We expect the block_id to be ordered, starting with 0
@@ -395,7 +512,7 @@ fn parse_data_block_header<'a>(
     }
 
     let (i, image) = if has_image {
-        parse_block_image(i).map(|(i, img)| (i, Some(img)))?
+        parse_block_image(blcksz, version, i).map(|(i, img)| (i, Some(img)))?
     } else {
         (i, None)
     };
@@ -420,40 +537,99 @@ fn parse_data_block_header<'a>(
         blockno,
         fork,
     });
-    let data = Some(vec![0; data_len as usize]);
+    // Block data follows the block headers
+    let data: Option<&[u8]> = Some(&[]);
     let block = XLBData {
         blk_id,
         page_id,
         flags,
         image,
         has_data,
-        data_len,
+        data_len: u32::from(data_len),
         data,
     };
     debug!("Parsed block header {}", block);
     Ok((i, block))
 }
 
-type BlockResult<'a> = (&'a [u8], Vec<XLBData>);
+/// Record-wide headers found among the block headers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RecordHeaders {
+    /// Replication origin of a change replayed by logical replication
+    pub record_origin: Option<RepOriginId>,
+    /// Top-level transaction of a subtransaction, set in the first record
+    /// the subtransaction writes
+    pub toplevel_xid: Option<TransactionId>,
+}
+
+/// The record's main data, its blocks with the main data being the last
+/// block when present, and its record-wide headers
+type BlockResult<'a, B> = (&'a [u8], Vec<XLBData<B>>, RecordHeaders);
+
+pub fn parse_blocks(
+    blcksz: u16,
+    version: WalVersion,
+    i: &[u8],
+) -> IResult<&[u8], BlockResult<'_, Vec<u8>>, XLogError<&[u8]>> {
+    let (i, (main_data, blocks, headers)) = parse_blocks_ref(blcksz, version, i)?;
+    let blocks = blocks
+        .into_iter()
+        .map(|block| block.map_bytes(<[u8]>::to_vec))
+        .collect();
+    Ok((i, (main_data, blocks, headers)))
+}
 
-pub fn parse_blocks(i: &[u8]) -> IResult<&[u8], BlockResult<'_>, XLogError<&[u8]>> {
+/// Parse a record's blocks, their data and images borrowing the input
+pub fn parse_blocks_ref<'a>(
+    blcksz: u16,
+    version: WalVersion,
+    i: &'a [u8],
+) -> IResult<&'a [u8], BlockResult<'a, &'a [u8]>, XLogError<&'a [u8]>> {
     let mut blocks = Vec::new();
+    let mut main_block = None;
+    let mut headers = RecordHeaders::default();
     let mut input = i;
-    loop {
-        match parse_data_block_header(blocks.last(), input) {
-            Ok((i, block)) => {
+    // Headers go on until only the images and data they announce are left
+    let mut datatotal = 0;
+    while input.len() > datatotal {
+        match input[0] {
+            XLR_BLOCK_ID_DATA_SHORT | XLR_BLOCK_ID_DATA_LONG => {
+                let (i, block) = parse_main_data_block_header(input)?;
+                datatotal += block.data_len as usize;
+                input = i;
+                // The main data header is always the last one
+                main_block = Some(block);
+                break;
+            }
+            XLR_BLOCK_ID_ORIGIN => {
+                let (i, record_origin) = le_u16(&input[1..])?;
+                debug!("Parsed record origin {}", record_origin);
+                headers.record_origin = Some(record_origin);
+                input = i;
+            }
+            XLR_BLOCK_ID_TOPLEVEL_XID => {
+                let (i, toplevel_xid) = le_u32(&input[1..])?;
+                debug!("Parsed top-level xid {}", toplevel_xid);
+                headers.toplevel_xid = Some(toplevel_xid);
+                input = i;
+            }
+            _ => {
+                let (i, block) = parse_data_block_header(blcksz, version, blocks.last(), input)?;
+                datatotal += block.data_len as usize;
+                if let Some(image) = &block.image {
+                    datatotal += image.bimg_len as usize;
+                }
                 blocks.push(block);
                 input = i;
             }
-            Err(nom::Err::Error(XLogError::EndBlock)) => break,
-            Err(e) => return Err(e),
         }
     }
-
-    let main_block_start = input;
-    //    let (i, main_block) = parse_main_data_block_header(main_block_start)?;
-    //    input = i;
-    //    blocks.push(main_block);
+    if input.len() != datatotal {
+        return Err(nom::Err::Error(XLogError::InvalidDataLen(
+            input.len(),
+            datatotal,
+        )));
+    }
 
     // We've reached the block's data
     for block in &mut blocks {
@@ -461,7 +637,7 @@ pub fn parse_blocks(i: &[u8]) -> IResult<&[u8], BlockResult<'_>, XLogError<&[u8]
         input = match &mut block.image {
             Some(image) => {
                 let (i, data) = take(image.bimg_len)(input)?;
-                image.bkp_image.copy_from_slice(data);
+                image.bkp_image = data;
                 i
             }
             None => input,
@@ -471,14 +647,21 @@ pub fn parse_blocks(i: &[u8]) -> IResult<&[u8], BlockResult<'_>, XLogError<&[u8]
         input = i;
         debug!("Data for block {:?}: {:X?}", block.page_id, data);
         if let Some(block_data) = block.data.as_mut() {
-            block_data.copy_from_slice(data);
+            *block_data = data;
         } else {
             return Err(nom::Err::Error(XLogError::EmptyRecord));
         }
     }
 
-    if !input.is_empty() {
-        return Err(nom::Err::Error(XLogError::LeftoverBytes(input.to_owned())));
+    // Main data comes last
+    let mut main_data: &[u8] = &[];
+    if let Some(mut block) = main_block {
+        let (i, data) = take(block.data_len)(input)?;
+        input = i;
+        debug!("Main data: {:X?}", data);
+        block.data = Some(data);
+        main_data = data;
+        blocks.push(block);
     }
-    Ok((input, (main_block_start, blocks)))
+    Ok((input, (main_data, blocks, headers)))
 }
@@ -1,3 +1,60 @@
 pub type TransactionId = u32;
 pub type OffsetNumber = u16;
 pub type Oid = u32;
+pub type RepOriginId = u16;
+/// Transaction ID with its epoch in the upper 32 bits
+pub type FullTransactionId = u64;
+pub type MultiXactId = u32;
+pub type MultiXactOffset = u32;
+
+/// Microseconds since 2000-01-01 00:00:00 UTC, PostgreSQL's epoch
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimestampTz(pub i64);
+
+impl TimestampTz {
+    /// Seconds between the Unix epoch and PostgreSQL's
+    const POSTGRES_EPOCH_UNIX: i64 = 946_684_800;
+
+    /// Timestamp of a pg_time_t, seconds since the Unix epoch
+    pub fn from_unix_time(secs: i64) -> Self {
+        Self((secs - Self::POSTGRES_EPOCH_UNIX) * 1_000_000)
+    }
+}
+
+impl std::fmt::Display for TimestampTz {
+    /// Format as UTC like timestamptz_to_str does
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.0 {
+            i64::MIN => return write!(f, "-infinity"),
+            i64::MAX => return write!(f, "infinity"),
+            _ => {}
+        }
+        let secs = self.0.div_euclid(1_000_000) + Self::POSTGRES_EPOCH_UNIX;
+        let micros = self.0.rem_euclid(1_000_000);
+        let (days, day_secs) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+
+        // Civil date from days since 1970-01-01, counting in 400 years eras
+        // starting on March 1st
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:06} UTC",
+            year,
+            month,
+            day,
+            day_secs / 3_600,
+            day_secs / 60 % 60,
+            day_secs % 60,
+            micros
+        )
+    }
+}
@@ -0,0 +1,324 @@
+use std::borrow::Cow;
+use std::ops::Range;
+
+use log::{debug, warn};
+
+use crate::error::XLogError;
+use crate::xlog::lsn::XLogRecPtr;
+use crate::xlog::page::{XLogPageHeader, XLP_FIRST_IS_CONTRECORD};
+use crate::xlog::reader::{maxalign, ReaderError};
+use crate::xlog::record::{
+    compute_record_crc, is_switch_record, XLOG_RECORD_CRC_OFFSET, XLOG_RECORD_HEADER_SIZE,
+    XLOG_RECORD_MAX_SIZE,
+};
+use crate::xlog::validate::{EndOfWal, EndOfWalReason, XLogValidator};
+
+/// Position of a reader in the records of WAL pages, shared by XLogReader
+/// and MappedRecords
+pub(crate) struct RecordCursor {
+    /// Start of the last record read
+    pub read_rec_ptr: XLogRecPtr,
+    /// End of the last record read
+    pub end_rec_ptr: XLogRecPtr,
+    /// Offset of the next record in the current page, None when the next
+    /// page has to be read first
+    pub page_offset: Option<usize>,
+    /// Stop reading at the first record ending after this LSN
+    pub end_lsn: Option<XLogRecPtr>,
+    /// Keep iterating over records after a CRC mismatch
+    pub continue_on_crc_error: bool,
+    pub validator: XLogValidator,
+    /// Set when the last read stopped at the end of valid WAL
+    pub end_of_wal: Option<EndOfWal>,
+    /// Iteration ended on an error the reader can't go past
+    pub stopped: bool,
+    pub wal_seg_size: u64,
+    pub xlog_blcksz: usize,
+}
+
+impl RecordCursor {
+    /// Cursor before the first record at start, a page boundary
+    pub fn new(start: XLogRecPtr, wal_seg_size: u64, xlog_blcksz: usize) -> Self {
+        Self {
+            read_rec_ptr: start,
+            end_rec_ptr: start,
+            page_offset: None,
+            end_lsn: None,
+            continue_on_crc_error: false,
+            validator: XLogValidator::default(),
+            end_of_wal: None,
+            stopped: false,
+            wal_seg_size,
+            xlog_blcksz,
+        }
+    }
+
+    /// Record the end of valid WAL after the last record read
+    pub fn reached_end_of_wal<T>(
+        &mut self,
+        reason: EndOfWalReason,
+    ) -> Result<Option<T>, ReaderError> {
+        self.end_of_wal = Some(EndOfWal {
+            lsn: self.end_rec_ptr,
+            reason,
+        });
+        Ok(None)
+    }
+}
+
+/// WAL pages read in LSN order, records being assembled from them by
+/// read_record. Records held in a single page are borrowed for 'p when the
+/// pages outlive the reader.
+pub(crate) trait WalPages<'p> {
+    fn cursor(&mut self) -> &mut RecordCursor;
+
+    /// Read and validate the page following the current one, moving to the
+    /// next segment at the end of a segment. Returns None when there's no
+    /// page left.
+    fn next_page(&mut self) -> Result<Option<XLogPageHeader>, ReaderError>;
+
+    /// Address and bytes of the current page
+    fn page(&self) -> (XLogRecPtr, &[u8]);
+
+    /// Bytes of a record held in range of the current page
+    fn record_in_page(&self, range: Range<usize>) -> Cow<'p, [u8]>;
+
+    /// Make next_page read the page at page_ptr
+    fn seek_page(&mut self, page_ptr: XLogRecPtr) -> Result<(), ReaderError>;
+
+    /// The rest of the current segment is unused, make next_page read the
+    /// first page of the next segment
+    fn skip_segment(&mut self);
+}
+
+/// Find the offset of the next record start, reading new pages if needed
+fn next_record_offset<'p>(pages: &mut impl WalPages<'p>) -> Result<Option<usize>, ReaderError> {
+    loop {
+        let cursor = pages.cursor();
+        let xlog_blcksz = cursor.xlog_blcksz;
+        if let Some(offset) = cursor.page_offset.filter(|o| *o < xlog_blcksz) {
+            return Ok(Some(offset));
+        }
+        let Some(page_header) = pages.next_page()? else {
+            return Ok(None);
+        };
+        let std = page_header.std();
+        let offset = if std.xlp_info & XLP_FIRST_IS_CONTRECORD != 0 {
+            // The page starts with the tail of a record we didn't read the
+            // beginning of, skip it. If the tail is longer than the page,
+            // the whole page is skipped.
+            maxalign(page_header.size() + std.xlp_rem_len as usize)
+        } else {
+            page_header.size()
+        };
+        debug!("Next record offset in new page: {}", offset);
+        pages.cursor().page_offset = Some(offset);
+    }
+}
+
+/// Read the bytes of the next record, reassembling the record's fragments
+/// when it crosses page boundaries. Returns None when the end of WAL is
+/// reached. The record's CRC is checked, a mismatch is reported as an error
+/// after moving past the record so reading can go on.
+fn read_next_record<'p>(
+    pages: &mut impl WalPages<'p>,
+) -> Result<Option<Cow<'p, [u8]>>, ReaderError> {
+    let Some(mut offset) = next_record_offset(pages)? else {
+        return pages
+            .cursor()
+            .reached_end_of_wal(EndOfWalReason::NoMoreSegment);
+    };
+    // Records are MAXALIGNed, xl_tot_len is always on the current page
+    let (page_ptr, page) = pages.page();
+    let read_rec_ptr = page_ptr + offset as u64;
+    let tot_len = u32::from_le_bytes(page[offset..offset + 4].try_into().unwrap()) as usize;
+    let cursor = pages.cursor();
+    let xlog_blcksz = cursor.xlog_blcksz;
+    if cursor.end_lsn.is_some_and(|end| read_rec_ptr >= end) {
+        return cursor.reached_end_of_wal(EndOfWalReason::EndLsnReached);
+    }
+    if tot_len == 0 {
+        // Zeroed space, no more records
+        return cursor.reached_end_of_wal(EndOfWalReason::ZeroLength);
+    }
+    if !(XLOG_RECORD_HEADER_SIZE..=XLOG_RECORD_MAX_SIZE).contains(&tot_len) {
+        let e = XLogError::InvalidRecord(format!("invalid record length {}", tot_len));
+        return Err(ReaderError::ParseError(read_rec_ptr, e, Vec::new()));
+    }
+
+    let record = if offset + tot_len <= xlog_blcksz {
+        offset += tot_len;
+        pages.record_in_page(offset - tot_len..offset)
+    } else {
+        // The record continues on the next pages and has to be copied
+        let mut record = Vec::with_capacity(tot_len);
+        record.extend_from_slice(&pages.page().1[offset..]);
+        while record.len() < tot_len {
+            let Some(page_header) = pages.next_page()? else {
+                return Err(ReaderError::ParseError(
+                    read_rec_ptr,
+                    XLogError::Eof,
+                    Vec::new(),
+                ));
+            };
+            let std = page_header.std();
+            let remaining = tot_len - record.len();
+            if std.xlp_info & XLP_FIRST_IS_CONTRECORD == 0 || std.xlp_rem_len as usize != remaining
+            {
+                let e = XLogError::InvalidContRecord(std.xlp_rem_len, remaining);
+                return Err(ReaderError::ParseError(read_rec_ptr, e, Vec::new()));
+            }
+            let start = page_header.size();
+            let len = remaining.min(xlog_blcksz - start);
+            record.extend_from_slice(&pages.page().1[start..start + len]);
+            offset = start + len;
+        }
+        Cow::Owned(record)
+    };
+
+    let end_rec_ptr = pages.page().0 + maxalign(offset) as u64;
+    let cursor = pages.cursor();
+    if cursor.end_lsn.is_some_and(|end| end_rec_ptr > end) {
+        return cursor.reached_end_of_wal(EndOfWalReason::EndLsnReached);
+    }
+    // Position and prev-link before the record, restored if it's torn
+    let last_read = (
+        cursor.read_rec_ptr,
+        cursor.end_rec_ptr,
+        cursor.validator.prev_rec_ptr(),
+    );
+    // xl_prev follows xl_tot_len and xl_xid. A stale record is checked before
+    // moving past it, the end of WAL is where it starts.
+    let xl_prev = XLogRecPtr::new(u64::from_le_bytes(record[8..16].try_into().unwrap()));
+    if let Err(e) = cursor.validator.check_record(read_rec_ptr, xl_prev) {
+        return Err(ReaderError::Validation(read_rec_ptr, e));
+    }
+    cursor.page_offset = Some(maxalign(offset));
+    cursor.read_rec_ptr = read_rec_ptr;
+    cursor.end_rec_ptr = end_rec_ptr;
+
+    let crc_bytes = record[XLOG_RECORD_CRC_OFFSET..XLOG_RECORD_HEADER_SIZE].try_into();
+    let expected_crc = u32::from_le_bytes(crc_bytes.unwrap());
+    let actual_crc = compute_record_crc(&record);
+    if expected_crc != actual_crc {
+        // A torn write leaves a bad CRC on the last record written, which is
+        // the end of WAL. Anything written after it means the record is
+        // corrupted.
+        if !is_followed_by_record(pages) {
+            let cursor = pages.cursor();
+            (cursor.read_rec_ptr, cursor.end_rec_ptr) = (last_read.0, last_read.1);
+            cursor.validator.set_prev_rec_ptr(last_read.2);
+            cursor.end_of_wal = Some(EndOfWal {
+                lsn: read_rec_ptr,
+                reason: EndOfWalReason::CrcMismatch(expected_crc, actual_crc),
+            });
+            return Ok(None);
+        }
+        return Err(ReaderError::CrcMismatch(
+            read_rec_ptr,
+            expected_crc,
+            actual_crc,
+        ));
+    }
+
+    if is_switch_record(&record) {
+        // The rest of the segment is unused, the next record starts on the
+        // next segment
+        let end = end_rec_ptr.as_u64().next_multiple_of(cursor.wal_seg_size);
+        cursor.end_rec_ptr = XLogRecPtr::new(end);
+        cursor.page_offset = None;
+        pages.skip_segment();
+    }
+    Ok(Some(record))
+}
+
+/// Whether something was written after the last record read, the next
+/// record having a length. Zeroes, and pages that are missing or left from
+/// a recycled segment, mean the last record read was the last one written.
+fn is_followed_by_record<'p>(pages: &mut impl WalPages<'p>) -> bool {
+    match next_record_offset(pages) {
+        Ok(Some(offset)) => pages.page().1[offset..offset + 4] != [0; 4],
+        Ok(None) => false,
+        // A page that isn't stale data can't be told apart from a record
+        Err(e) => e.end_of_wal_reason().is_none(),
+    }
+}
+
+/// Read the bytes of the next record like read_next_record does, telling
+/// where and why valid WAL ended when reading stops on stale data
+pub(crate) fn read_record<'p>(
+    pages: &mut impl WalPages<'p>,
+) -> Result<Option<Cow<'p, [u8]>>, ReaderError> {
+    pages.cursor().end_of_wal = None;
+    let res = read_next_record(pages);
+    if let Err(e) = &res {
+        if let Some(reason) = e.end_of_wal_reason() {
+            // Stale pages come after the last valid record
+            let cursor = pages.cursor();
+            cursor.end_of_wal = Some(EndOfWal {
+                lsn: cursor.end_rec_ptr,
+                reason,
+            });
+        }
+    }
+    res
+}
+
+/// Move to the first record starting at or after lsn. As lsn may point in
+/// the middle of a record, the page containing it is read from its start to
+/// find the next record boundary.
+pub(crate) fn seek<'p>(pages: &mut impl WalPages<'p>, lsn: XLogRecPtr) -> Result<(), ReaderError> {
+    let xlog_blcksz = pages.cursor().xlog_blcksz;
+    pages.seek_page(lsn.page_start(xlog_blcksz as u64))?;
+    let cursor = pages.cursor();
+    cursor.page_offset = None;
+    cursor.validator.reset_prev_rec_ptr();
+    // Until a record is read, the end of WAL is the requested position
+    cursor.end_rec_ptr = lsn;
+
+    // Skip records starting before the requested position
+    while let Some(offset) = next_record_offset(pages)? {
+        if pages.page().0 + offset as u64 >= lsn {
+            break;
+        }
+        match read_next_record(pages) {
+            Ok(Some(_)) => {}
+            Ok(None) => break,
+            // We're only looking for a record boundary
+            Err(ReaderError::CrcMismatch(..)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Bytes of the next record for an iterator, read with read. Records with
+/// an invalid CRC are returned as errors or skipped, iterating can go on
+/// after them. Any other error ends the iteration, as does reaching the end
+/// of WAL.
+pub(crate) fn next_record_bytes<'p, P: WalPages<'p>>(
+    pages: &mut P,
+    read: impl Fn(&mut P) -> Result<Option<Cow<'p, [u8]>>, ReaderError>,
+) -> Option<Result<Cow<'p, [u8]>, ReaderError>> {
+    if pages.cursor().stopped {
+        return None;
+    }
+    loop {
+        match read(pages) {
+            Ok(Some(record_bytes)) => return Some(Ok(record_bytes)),
+            Ok(None) => return None,
+            Err(e @ ReaderError::CrcMismatch(..)) if pages.cursor().continue_on_crc_error => {
+                warn!("Skipping record: {}", e);
+            }
+            Err(e @ ReaderError::CrcMismatch(..)) => return Some(Err(e)),
+            Err(e) => {
+                pages.cursor().stopped = true;
+                // Stale data after the last record isn't an error
+                if e.end_of_wal_reason().is_some() {
+                    return None;
+                }
+                return Some(Err(e));
+            }
+        }
+    }
+}
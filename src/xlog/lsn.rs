@@ -0,0 +1,232 @@
+use std::fmt;
+use std::ops::{Add, AddAssign};
+use std::str::FromStr;
+
+pub type TimelineID = u32;
+pub type XLogSegNo = u64;
+
+/// Records always start on a MAXALIGN boundary
+pub const MAXIMUM_ALIGNOF: u64 = 8;
+
+/// Position in the WAL stream, also known as LSN
+#[derive(Clone, Copy, Debug, Default, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct XLogRecPtr(u64);
+
+#[derive(Clone, Debug, Hash, Ord, PartialOrd, PartialEq, Eq, thiserror::Error)]
+pub enum InvalidLSN {
+    #[error("Invalid LSN Format '{0}'")]
+    Format(String),
+    #[error("Invalid hex value in '{0}': `{1}`")]
+    HexValue(String, String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("Invalid WAL segment file name '{0}'")]
+pub struct InvalidFileName(pub String);
+
+impl XLogRecPtr {
+    pub const INVALID: XLogRecPtr = XLogRecPtr(0);
+
+    pub const fn new(lsn: u64) -> Self {
+        Self(lsn)
+    }
+
+    /// Build an LSN from its high and low 32 bits, as stored in page headers
+    pub const fn from_parts(xlogid: u32, xrecoff: u32) -> Self {
+        Self(((xlogid as u64) << 32) | xrecoff as u64)
+    }
+
+    /// Build an LSN from a segment number and an offset within this segment
+    pub fn from_segno(segno: XLogSegNo, offset: u64, wal_seg_size: u64) -> Self {
+        Self(segno * wal_seg_size + offset)
+    }
+
+    pub const fn as_u64(&self) -> u64 {
+        self.0
+    }
+
+    pub fn is_valid(&self) -> bool {
+        *self != Self::INVALID
+    }
+
+    /// High 32 bits of the LSN
+    pub fn xlogid(&self) -> u32 {
+        (self.0 >> 32) as u32
+    }
+
+    /// Low 32 bits of the LSN
+    pub fn xrecoff(&self) -> u32 {
+        self.0 as u32
+    }
+
+    /// Segment containing this LSN
+    pub fn segno(&self, wal_seg_size: u64) -> XLogSegNo {
+        self.0 / wal_seg_size
+    }
+
+    /// Offset of the LSN within its segment
+    pub fn segment_offset(&self, wal_seg_size: u64) -> u64 {
+        self.0 % wal_seg_size
+    }
+
+    /// Name of the segment file containing this LSN
+    pub fn file_name(&self, tli: TimelineID, wal_seg_size: u64) -> String {
+        XLogFilePos::from_segno(tli, self.segno(wal_seg_size), wal_seg_size).to_string()
+    }
+
+    /// Offset of the LSN within its page
+    pub fn page_offset(&self, blcksz: u64) -> u64 {
+        self.0 % blcksz
+    }
+
+    /// Start of the page containing this LSN
+    pub fn page_start(&self, blcksz: u64) -> Self {
+        Self(self.0 - self.page_offset(blcksz))
+    }
+
+    pub fn is_page_boundary(&self, blcksz: u64) -> bool {
+        self.page_offset(blcksz) == 0
+    }
+
+    /// Round the LSN up to the next MAXALIGN boundary
+    pub fn maxalign(&self) -> Self {
+        Self(self.0.next_multiple_of(MAXIMUM_ALIGNOF))
+    }
+
+    /// Number of bytes from other to this LSN, None if other is after it
+    pub fn checked_sub(&self, other: XLogRecPtr) -> Option<u64> {
+        self.0.checked_sub(other.0)
+    }
+
+    /// Number of bytes from other to this LSN, 0 if other is after it
+    pub fn saturating_sub(&self, other: XLogRecPtr) -> u64 {
+        self.0.saturating_sub(other.0)
+    }
+}
+
+impl From<u64> for XLogRecPtr {
+    fn from(lsn: u64) -> Self {
+        Self(lsn)
+    }
+}
+
+impl From<XLogRecPtr> for u64 {
+    fn from(lsn: XLogRecPtr) -> Self {
+        lsn.0
+    }
+}
+
+impl Add<u64> for XLogRecPtr {
+    type Output = XLogRecPtr;
+
+    fn add(self, rhs: u64) -> Self::Output {
+        Self(self.0 + rhs)
+    }
+}
+
+impl AddAssign<u64> for XLogRecPtr {
+    fn add_assign(&mut self, rhs: u64) {
+        self.0 += rhs;
+    }
+}
+
+impl fmt::Display for XLogRecPtr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // format ourselves as a `ffffffff/ffffffff` string
+        write!(f, "{0:X}/{1:08X}", self.xlogid(), self.xrecoff())
+    }
+}
+
+impl FromStr for XLogRecPtr {
+    type Err = InvalidLSN;
+
+    fn from_str(lsn: &str) -> Result<Self, Self::Err> {
+        let Some((xlogid_str, xrecoff_str)) = lsn.split_once('/') else {
+            return Err(InvalidLSN::Format(lsn.to_string()));
+        };
+        let xlogid = match u32::from_str_radix(xlogid_str, 16) {
+            Ok(xlogid) => xlogid,
+            Err(e) => return Err(InvalidLSN::HexValue(lsn.to_string(), e.to_string())),
+        };
+        let xrecoff = match u32::from_str_radix(xrecoff_str, 16) {
+            Ok(xrecoff) => xrecoff,
+            Err(e) => return Err(InvalidLSN::HexValue(lsn.to_string(), e.to_string())),
+        };
+        Ok(Self::from_parts(xlogid, xrecoff))
+    }
+}
+
+impl TryFrom<&str> for XLogRecPtr {
+    type Error = InvalidLSN;
+
+    fn try_from(lsn: &str) -> Result<Self, Self::Error> {
+        lsn.parse()
+    }
+}
+
+/// Position of a segment file, as encoded in its name
+#[derive(Debug)]
+pub struct XLogFilePos {
+    pub tli: u32,
+    pub log: u32,
+    pub seg: u32,
+}
+
+/// Number of segments in a 4GB logical xlog file
+fn segments_per_xlog_id(wal_seg_size: u64) -> u64 {
+    0x1_0000_0000 / wal_seg_size
+}
+
+/// Segment size used when initdb's --wal-segsize isn't set
+pub const DEFAULT_WAL_SEG_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Segment sizes allowed by initdb's --wal-segsize: a power of two between
+/// 1MB and 1GB
+pub fn is_valid_wal_seg_size(wal_seg_size: u64) -> bool {
+    wal_seg_size.is_power_of_two() && (1 << 20..=1 << 30).contains(&wal_seg_size)
+}
+
+impl XLogFilePos {
+    pub fn from_segno(tli: TimelineID, segno: XLogSegNo, wal_seg_size: u64) -> Self {
+        let segs_per_xlog_id = segments_per_xlog_id(wal_seg_size);
+        Self {
+            tli,
+            log: (segno / segs_per_xlog_id) as u32,
+            seg: (segno % segs_per_xlog_id) as u32,
+        }
+    }
+
+    pub fn get_segno(&self, wal_seg_size: u64) -> XLogSegNo {
+        u64::from(self.log) * segments_per_xlog_id(wal_seg_size) + u64::from(self.seg)
+    }
+
+    pub fn get_xlog_rec_ptr(&self, wal_seg_size: u64) -> XLogRecPtr {
+        XLogRecPtr::from_segno(self.get_segno(wal_seg_size), 0, wal_seg_size)
+    }
+}
+
+impl fmt::Display for XLogFilePos {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:08X}{:08X}{:08X}", self.tli, self.log, self.seg)
+    }
+}
+
+/// Parse the position of a segment from its file name, the 24 hex digits
+/// it starts with
+pub fn parse_filename(fname: &str) -> Result<XLogFilePos, InvalidFileName> {
+    let invalid = || InvalidFileName(fname.to_string());
+    let digits = fname.as_bytes().get(..24).ok_or_else(invalid)?;
+    if !digits.iter().all(u8::is_ascii_hexdigit) {
+        return Err(invalid());
+    }
+    // Only ASCII hex digits are left, each part parses
+    let part = |range: std::ops::Range<usize>| {
+        let part = std::str::from_utf8(&digits[range]).unwrap();
+        u32::from_str_radix(part, 16).unwrap()
+    };
+    Ok(XLogFilePos {
+        tli: part(0..8),
+        log: part(8..16),
+        seg: part(16..24),
+    })
+}
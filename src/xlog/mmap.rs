@@ -0,0 +1,267 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use log::debug;
+use memmap2::Mmap;
+
+use crate::xlog::block::DEFAULT_BLCKSZ;
+use crate::xlog::cursor::{self, RecordCursor, WalPages};
+use crate::xlog::lsn::{parse_filename, XLogFilePos, XLogRecPtr, XLogSegNo};
+use crate::xlog::page::XLogPageHeader;
+use crate::xlog::reader::{
+    check_page, check_segment_file_size, decode_error, parse_segment_sizes, ReaderError,
+};
+use crate::xlog::record::{parse_xlog_record_ref, XLogRecordRef};
+use crate::xlog::segment::{find_segment_file, Compression};
+use crate::xlog::source::WalDirectory;
+use crate::xlog::timeline::{find_newest_timeline, read_timeline_history, timeline_of_segment};
+use crate::xlog::validate::EndOfWal;
+
+/// Consecutive WAL segments mapped in memory. Records read from them borrow
+/// their block data and images from the mappings, only records spanning
+/// pages are copied. Segments may be partial but not compressed, and must
+/// not be modified while mapped: map archived WAL or a stopped cluster's
+/// pg_wal.
+pub struct MappedWal {
+    segments: Vec<Mmap>,
+    /// Segment number of the first mapping
+    start_segno: XLogSegNo,
+    wal_seg_size: u64,
+    xlog_blcksz: usize,
+    blcksz: u16,
+}
+
+/// Map a plain or partial segment file
+fn map_segment(dir: &Path, name: &str) -> io::Result<Mmap> {
+    let path = find_segment_file(dir, name, &["", ".partial"])?;
+    let f = File::open(&path)?;
+    // SAFETY: segments are expected not to change while mapped, as stated
+    // in MappedWal's documentation
+    let map = unsafe { Mmap::map(&f)? };
+    let compression = Compression::from_magic(&map[..map.len().min(4)]);
+    if compression != Compression::None {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{:?} is compressed with {}, it can't be mapped",
+                path, compression
+            ),
+        ));
+    }
+    Ok(map)
+}
+
+impl MappedWal {
+    /// Map the segments of dir from start_segment to end_segment, or up to
+    /// the first missing segment without end_segment. Timeline switches are
+    /// followed like XLogReader does.
+    pub fn open(
+        dir: PathBuf,
+        start_segment: &str,
+        end_segment: Option<&str>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let source = WalDirectory::new(dir.clone());
+        let file_pos = parse_filename(start_segment)?;
+        let target_tli = match end_segment {
+            Some(end_segment) => parse_filename(end_segment)?.tli,
+            None => find_newest_timeline(&source, file_pos.tli)?,
+        };
+        let timeline_history = read_timeline_history(&source, target_tli)?;
+
+        let first = map_segment(&dir, start_segment)?;
+        let (wal_seg_size, xlog_blcksz) = parse_segment_sizes(&first, start_segment)?;
+        check_segment_file_size(start_segment, first.len() as u64, wal_seg_size)?;
+        let start_segno = file_pos.get_segno(wal_seg_size);
+        let end_segno = match end_segment {
+            Some(end_segment) => Some(parse_filename(end_segment)?.get_segno(wal_seg_size)),
+            None => None,
+        };
+
+        let mut segments = vec![first];
+        loop {
+            let segno = start_segno + segments.len() as u64;
+            if end_segno.is_some_and(|end_segno| segno > end_segno) {
+                break;
+            }
+            let tli =
+                timeline_of_segment(&timeline_history, segno, wal_seg_size).unwrap_or(file_pos.tli);
+            let name = XLogFilePos::from_segno(tli, segno, wal_seg_size).to_string();
+            match map_segment(&dir, &name) {
+                Ok(map) => {
+                    check_segment_file_size(&name, map.len() as u64, wal_seg_size)?;
+                    segments.push(map);
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound && end_segno.is_none() => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        debug!("Mapped {} segments from {}", segments.len(), start_segment);
+
+        Ok(Self {
+            segments,
+            start_segno,
+            wal_seg_size,
+            xlog_blcksz,
+            blcksz: DEFAULT_BLCKSZ,
+        })
+    }
+
+    /// Set the relation page size used to decode full page images
+    pub fn set_blcksz(&mut self, blcksz: u16) {
+        self.blcksz = blcksz;
+    }
+
+    pub fn wal_seg_size(&self) -> u64 {
+        self.wal_seg_size
+    }
+
+    /// Iterate over the records of the mapped segments
+    pub fn records(&self) -> MappedRecords<'_> {
+        let start = XLogRecPtr::from_segno(self.start_segno, 0, self.wal_seg_size);
+        MappedRecords {
+            wal: self,
+            cursor: RecordCursor::new(start, self.wal_seg_size, self.xlog_blcksz),
+            page_ptr: start,
+            page: &[],
+            next_page_ptr: start,
+        }
+    }
+
+    /// The page at page_ptr, None if it's not mapped
+    fn page(&self, page_ptr: XLogRecPtr) -> Option<&[u8]> {
+        let index = page_ptr
+            .segno(self.wal_seg_size)
+            .checked_sub(self.start_segno)?;
+        let segment = self.segments.get(index as usize)?;
+        let offset = page_ptr.segment_offset(self.wal_seg_size) as usize;
+        segment.get(offset..offset + self.xlog_blcksz)
+    }
+}
+
+/// Iterator over the records of a MappedWal, with the same semantics as
+/// XLogReader's iterator
+pub struct MappedRecords<'a> {
+    wal: &'a MappedWal,
+    /// Position among records and the state of their validation
+    cursor: RecordCursor,
+    /// Address of the current page
+    page_ptr: XLogRecPtr,
+    /// Current page, empty until the first page is read
+    page: &'a [u8],
+    /// Page read when the current one has no record start left
+    next_page_ptr: XLogRecPtr,
+}
+
+impl<'a> MappedRecords<'a> {
+    /// Stop reading at the first record ending after end
+    pub fn set_end_lsn(&mut self, end: XLogRecPtr) {
+        self.cursor.end_lsn = Some(end);
+    }
+
+    /// When set, iterating over records skips records with an invalid CRC
+    /// instead of stopping at the first one
+    pub fn set_continue_on_crc_error(&mut self, continue_on_crc_error: bool) {
+        self.cursor.continue_on_crc_error = continue_on_crc_error;
+    }
+
+    /// Move to the first record starting at or after lsn, see
+    /// XLogReader::seek
+    pub fn seek(&mut self, lsn: XLogRecPtr) -> Result<(), ReaderError> {
+        cursor::seek(self, lsn)
+    }
+
+    /// Start LSN of the last record read
+    pub fn read_rec_ptr(&self) -> XLogRecPtr {
+        self.cursor.read_rec_ptr
+    }
+
+    /// End LSN of the last record read, this is where the next record starts
+    pub fn end_rec_ptr(&self) -> XLogRecPtr {
+        self.cursor.end_rec_ptr
+    }
+
+    /// Where and why valid WAL ended, set when iteration stopped at the end
+    /// of WAL
+    pub fn end_of_wal(&self) -> Option<&EndOfWal> {
+        self.cursor.end_of_wal.as_ref()
+    }
+}
+
+impl<'a> WalPages<'a> for MappedRecords<'a> {
+    fn cursor(&mut self) -> &mut RecordCursor {
+        &mut self.cursor
+    }
+
+    fn next_page(&mut self) -> Result<Option<XLogPageHeader>, ReaderError> {
+        let page_ptr = self.next_page_ptr;
+        let Some(page) = self.wal.page(page_ptr) else {
+            return Ok(None);
+        };
+        let page_header = check_page(
+            &mut self.cursor.validator,
+            page,
+            page_ptr,
+            self.wal.wal_seg_size,
+        )?;
+        self.page_ptr = page_ptr;
+        self.page = page;
+        self.next_page_ptr = page_ptr + self.wal.xlog_blcksz as u64;
+        Ok(Some(page_header))
+    }
+
+    fn page(&self) -> (XLogRecPtr, &[u8]) {
+        (self.page_ptr, self.page)
+    }
+
+    /// Records held in a single page are borrowed from the mapping
+    fn record_in_page(&self, range: Range<usize>) -> Cow<'a, [u8]> {
+        Cow::Borrowed(&self.page[range])
+    }
+
+    fn seek_page(&mut self, page_ptr: XLogRecPtr) -> Result<(), ReaderError> {
+        self.next_page_ptr = page_ptr;
+        Ok(())
+    }
+
+    fn skip_segment(&mut self) {
+        let wal_seg_size = self.wal.wal_seg_size;
+        let segno = self.page_ptr.segno(wal_seg_size) + 1;
+        self.next_page_ptr = XLogRecPtr::from_segno(segno, 0, wal_seg_size);
+    }
+}
+
+impl<'a> Iterator for MappedRecords<'a> {
+    type Item = Result<XLogRecordRef<'a>, ReaderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record_bytes = match cursor::next_record_bytes(self, cursor::read_record)? {
+            Ok(record_bytes) => record_bytes,
+            Err(e) => return Some(Err(e)),
+        };
+        let version = self.cursor.validator.version();
+        let res = match &record_bytes {
+            Cow::Borrowed(bytes) => parse_xlog_record_ref(self.wal.blcksz, version, bytes)
+                .map(|(_, record)| record)
+                .map_err(decode_error),
+            Cow::Owned(bytes) => parse_xlog_record_ref(self.wal.blcksz, version, bytes)
+                .map(|(_, record)| record.map_bytes(|b| Cow::Owned(b.into_owned())))
+                .map_err(decode_error),
+        };
+        match res {
+            Ok(mut record) => {
+                record.lsn = self.cursor.read_rec_ptr;
+                record.end_lsn = self.cursor.end_rec_ptr;
+                Some(Ok(record))
+            }
+            Err(e) => Some(Err(ReaderError::ParseError(
+                self.cursor.read_rec_ptr,
+                e,
+                record_bytes.into_owned(),
+            ))),
+        }
+    }
+}
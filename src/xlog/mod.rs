@@ -1,6 +1,15 @@
 pub mod block;
 pub mod common;
+pub(crate) mod cursor;
+pub mod lsn;
+pub mod mmap;
 pub mod operation;
 pub mod page;
+pub mod parallel;
+pub mod pglz;
 pub mod reader;
 pub mod record;
+pub mod segment;
+pub mod source;
+pub mod timeline;
+pub mod validate;
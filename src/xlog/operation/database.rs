@@ -0,0 +1,145 @@
+use log::debug;
+use nom::{
+    number::complete::{le_i32, le_u32},
+    IResult,
+};
+
+use crate::{
+    error::XLogError,
+    xlog::{
+        common::Oid,
+        operation::parse_count,
+        page::WalVersion,
+        record::{Operation, RmgrId},
+    },
+};
+
+/// Copy of the template database's directory, XLOG_DBASE_CREATE before
+/// PG15
+pub const XLOG_DBASE_CREATE_FILE_COPY: u8 = 0x00;
+/// Creation of an empty directory filled by WAL logged pages, PG15+
+pub const XLOG_DBASE_CREATE_WAL_LOG: u8 = 0x10;
+pub const XLOG_DBASE_DROP: u8 = 0x20;
+/// XLOG_DBASE_DROP before PG15
+pub const XLOG_DBASE_DROP_V14: u8 = 0x10;
+
+#[derive(Clone, Debug)]
+pub struct DbaseCreateFileCopy {
+    pub db_id: Oid,
+    pub tablespace_id: Oid,
+    pub src_db_id: Oid,
+    pub src_tablespace_id: Oid,
+}
+
+#[derive(Clone, Debug)]
+pub struct DbaseCreateWalLog {
+    pub db_id: Oid,
+    pub tablespace_id: Oid,
+}
+
+/// Removal of a database's directory in each of its tablespaces
+#[derive(Clone, Debug)]
+pub struct DbaseDrop {
+    pub db_id: Oid,
+    pub tablespace_ids: Vec<Oid>,
+}
+
+#[derive(Clone, Debug)]
+pub enum DatabaseOperation {
+    CreateFileCopy(DbaseCreateFileCopy),
+    CreateWalLog(DbaseCreateWalLog),
+    Drop(DbaseDrop),
+}
+
+impl std::fmt::Display for DatabaseOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DatabaseOperation::CreateFileCopy(c) => write!(
+                f,
+                "CREATE_FILE_COPY copy dir {}/{} to {}/{}",
+                c.src_tablespace_id, c.src_db_id, c.tablespace_id, c.db_id
+            ),
+            DatabaseOperation::CreateWalLog(c) => write!(
+                f,
+                "CREATE_WAL_LOG create dir {}/{}",
+                c.tablespace_id, c.db_id
+            ),
+            DatabaseOperation::Drop(d) => {
+                write!(f, "DROP dir")?;
+                for tablespace_id in &d.tablespace_ids {
+                    write!(f, " {}/{}", tablespace_id, d.db_id)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+pub fn parse_dbase_create_file_copy(
+    i: &[u8],
+) -> IResult<&[u8], DbaseCreateFileCopy, XLogError<&[u8]>> {
+    let (i, db_id) = le_u32(i)?;
+    let (i, tablespace_id) = le_u32(i)?;
+    let (i, src_db_id) = le_u32(i)?;
+    let (i, src_tablespace_id) = le_u32(i)?;
+    let create = DbaseCreateFileCopy {
+        db_id,
+        tablespace_id,
+        src_db_id,
+        src_tablespace_id,
+    };
+    Ok((i, create))
+}
+
+pub fn parse_dbase_create_wal_log(i: &[u8]) -> IResult<&[u8], DbaseCreateWalLog, XLogError<&[u8]>> {
+    let (i, db_id) = le_u32(i)?;
+    let (i, tablespace_id) = le_u32(i)?;
+    Ok((
+        i,
+        DbaseCreateWalLog {
+            db_id,
+            tablespace_id,
+        },
+    ))
+}
+
+pub fn parse_dbase_drop(i: &[u8]) -> IResult<&[u8], DbaseDrop, XLogError<&[u8]>> {
+    let (i, db_id) = le_u32(i)?;
+    let (i, ntablespaces) = le_i32(i)?;
+    let (i, tablespace_ids) = parse_count(le_u32, ntablespaces, i)?;
+    Ok((
+        i,
+        DbaseDrop {
+            db_id,
+            tablespace_ids,
+        },
+    ))
+}
+
+pub fn parse_database_operation(
+    rmgr_info: u8,
+    version: WalVersion,
+    i: &[u8],
+) -> IResult<&[u8], Operation, XLogError<&[u8]>> {
+    let (i, database_operation) = match rmgr_info {
+        XLOG_DBASE_CREATE_FILE_COPY => parse_dbase_create_file_copy(i)
+            .map(|(i, c)| (i, DatabaseOperation::CreateFileCopy(c)))?,
+        XLOG_DBASE_DROP_V14 if version < WalVersion::V15 => {
+            parse_dbase_drop(i).map(|(i, d)| (i, DatabaseOperation::Drop(d)))?
+        }
+        XLOG_DBASE_CREATE_WAL_LOG => {
+            parse_dbase_create_wal_log(i).map(|(i, c)| (i, DatabaseOperation::CreateWalLog(c)))?
+        }
+        XLOG_DBASE_DROP if version >= WalVersion::V15 => {
+            parse_dbase_drop(i).map(|(i, d)| (i, DatabaseOperation::Drop(d)))?
+        }
+        info => {
+            return Err(nom::Err::Error(XLogError::UnknownRecordInfo(
+                RmgrId::Database,
+                info,
+            )))
+        }
+    };
+    debug!("Parsed Operation: {}", database_operation);
+    Ok((i, Operation::Database(database_operation)))
+}
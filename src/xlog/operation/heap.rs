@@ -1,17 +1,31 @@
 use log::debug;
 use nom::{
-    number::complete::{le_u16, le_u32, le_u8},
+    bytes::complete::take,
+    number::complete::{le_i32, le_u16, le_u32, le_u8},
     IResult,
 };
 
 use crate::{
     error::XLogError,
     xlog::{
-        common::{OffsetNumber, TransactionId},
-        record::Operation,
+        common::{OffsetNumber, Oid, TransactionId},
+        operation::parse_count,
+        record::{Operation, RmgrId},
     },
 };
 
+pub const XLOG_HEAP_INSERT: u8 = 0x00;
+pub const XLOG_HEAP_DELETE: u8 = 0x10;
+pub const XLOG_HEAP_UPDATE: u8 = 0x20;
+pub const XLOG_HEAP_TRUNCATE: u8 = 0x30;
+pub const XLOG_HEAP_HOT_UPDATE: u8 = 0x40;
+pub const XLOG_HEAP_CONFIRM: u8 = 0x50;
+pub const XLOG_HEAP_LOCK: u8 = 0x60;
+pub const XLOG_HEAP_INPLACE: u8 = 0x70;
+pub const XLOG_HEAP_OPMASK: u8 = 0x70;
+/// The record's page was initialized before the tuple was added to it
+pub const XLOG_HEAP_INIT_PAGE: u8 = 0x80;
+
 #[derive(Clone, Debug)]
 pub struct Infobits {
     pub xmax_is_multi: bool,
@@ -38,6 +52,8 @@ pub struct Delete {
 #[derive(Clone, Debug)]
 pub struct Insert {
     pub offnum: OffsetNumber,
+    /// XLOG_HEAP_INIT_PAGE was set
+    pub init_page: bool,
 
     /// Insert flags
     pub all_visible_cleared: bool,
@@ -64,6 +80,44 @@ pub struct Update {
 
     pub new_xmax: TransactionId,
     pub new_offnum: OffsetNumber,
+    /// XLOG_HEAP_INIT_PAGE was set, for the new tuple's page
+    pub init_page: bool,
+}
+
+/// TRUNCATE of relations, logged with wal_level logical for logical
+/// decoding
+#[derive(Clone, Debug)]
+pub struct Truncate {
+    pub db_id: Oid,
+    pub relids: Vec<Oid>,
+
+    /// Truncate flags
+    pub cascade: bool,
+    pub restart_seqs: bool,
+}
+
+/// Confirmation of a speculative insertion
+#[derive(Clone, Debug)]
+pub struct Confirm {
+    pub offnum: OffsetNumber,
+}
+
+/// Row lock taken by SELECT FOR UPDATE/SHARE or before an update
+#[derive(Clone, Debug)]
+pub struct Lock {
+    pub locking_xid: TransactionId,
+    pub offnum: OffsetNumber,
+    pub infobits: Infobits,
+
+    /// Lock flags
+    pub all_frozen_cleared: bool,
+}
+
+/// Update of a tuple in place, without a new version, e.g. of pg_class by
+/// VACUUM
+#[derive(Clone, Debug)]
+pub struct Inplace {
+    pub offnum: OffsetNumber,
 }
 
 #[derive(Clone, Debug)]
@@ -78,8 +132,12 @@ pub enum HeapOperation {
     Delete(Delete),
     Insert(Insert),
     Update(Update),
+    Truncate(Truncate),
+    HotUpdate(Update),
+    Confirm(Confirm),
+    Lock(Lock),
+    Inplace(Inplace),
     Prune(Prune),
-    Placeholder,
 }
 
 impl std::fmt::Display for HeapOperation {
@@ -88,8 +146,12 @@ impl std::fmt::Display for HeapOperation {
             HeapOperation::Delete(o) => write!(f, "{:?}", o),
             HeapOperation::Insert(o) => write!(f, "{:?}", o),
             HeapOperation::Update(o) => write!(f, "{:?}", o),
+            HeapOperation::Truncate(o) => write!(f, "{:?}", o),
+            HeapOperation::HotUpdate(o) => write!(f, "{:?}", o),
+            HeapOperation::Confirm(o) => write!(f, "{:?}", o),
+            HeapOperation::Lock(o) => write!(f, "{:?}", o),
+            HeapOperation::Inplace(o) => write!(f, "{:?}", o),
             HeapOperation::Prune(o) => write!(f, "{:?}", o),
-            HeapOperation::Placeholder => write!(f, "Placeholder"),
         }
     }
 }
@@ -125,7 +187,8 @@ pub fn parse_heap_delete(i: &[u8]) -> IResult<&[u8], HeapOperation, XLogError<&[
     Ok((i, HeapOperation::Delete(heap_delete)))
 }
 
-pub fn parse_heap_update(i: &[u8]) -> IResult<&[u8], HeapOperation, XLogError<&[u8]>> {
+/// xl_heap_update, shared by UPDATE and HOT_UPDATE
+pub fn parse_update(rmgr_info: u8, i: &[u8]) -> IResult<&[u8], Update, XLogError<&[u8]>> {
     let (i, old_xmax) = le_u32(i)?;
     let (i, old_offnum) = le_u16(i)?;
     let (i, old_infobits) = parse_infobits(i)?;
@@ -145,17 +208,30 @@ pub fn parse_heap_update(i: &[u8]) -> IResult<&[u8], HeapOperation, XLogError<&[
         suffix_from_old: flags & 0x20 != 0,
         new_xmax,
         new_offnum,
+        init_page: rmgr_info & XLOG_HEAP_INIT_PAGE != 0,
     };
 
+    Ok((i, heap_update))
+}
+
+pub fn parse_heap_update(
+    rmgr_info: u8,
+    i: &[u8],
+) -> IResult<&[u8], HeapOperation, XLogError<&[u8]>> {
+    let (i, heap_update) = parse_update(rmgr_info, i)?;
     Ok((i, HeapOperation::Update(heap_update)))
 }
 
-pub fn parse_heap_insert(i: &[u8]) -> IResult<&[u8], HeapOperation, XLogError<&[u8]>> {
+pub fn parse_heap_insert(
+    rmgr_info: u8,
+    i: &[u8],
+) -> IResult<&[u8], HeapOperation, XLogError<&[u8]>> {
     let (i, offnum) = le_u16(i)?;
     let (i, flags) = le_u8(i)?;
 
     let heap_insert = Insert {
         offnum,
+        init_page: rmgr_info & XLOG_HEAP_INIT_PAGE != 0,
         all_visible_cleared: flags & 0x01 != 0,
         last_in_multi: flags & 0x02 != 0,
         is_speculative: flags & 0x04 != 0,
@@ -181,34 +257,77 @@ pub fn parse_heap_prune(i: &[u8]) -> IResult<&[u8], HeapOperation, XLogError<&[u
     Ok((i, HeapOperation::Prune(heap_prune)))
 }
 
-pub fn parse_heap_truncate(_i: &[u8]) -> IResult<&[u8], HeapOperation, XLogError<&[u8]>> {
-    todo!("truncate");
+pub fn parse_heap_truncate(i: &[u8]) -> IResult<&[u8], HeapOperation, XLogError<&[u8]>> {
+    let (i, db_id) = le_u32(i)?;
+    let (i, nrelids) = le_i32(i)?;
+    let (i, flags) = le_u8(i)?;
+    let (i, _) = take(3usize)(i)?;
+    let (i, relids) = parse_count(le_u32, nrelids, i)?;
+
+    let heap_truncate = Truncate {
+        db_id,
+        relids,
+        cascade: flags & 0x01 != 0,
+        restart_seqs: flags & 0x02 != 0,
+    };
+
+    Ok((i, HeapOperation::Truncate(heap_truncate)))
 }
 
-pub fn parse_heap_hot_update(_i: &[u8]) -> IResult<&[u8], HeapOperation, XLogError<&[u8]>> {
-    todo!("hot update");
+pub fn parse_heap_hot_update(
+    rmgr_info: u8,
+    i: &[u8],
+) -> IResult<&[u8], HeapOperation, XLogError<&[u8]>> {
+    let (i, heap_update) = parse_update(rmgr_info, i)?;
+    Ok((i, HeapOperation::HotUpdate(heap_update)))
 }
 
-pub fn parse_heap_confirm(_i: &[u8]) -> IResult<&[u8], HeapOperation, XLogError<&[u8]>> {
-    todo!("hot update");
+pub fn parse_heap_confirm(i: &[u8]) -> IResult<&[u8], HeapOperation, XLogError<&[u8]>> {
+    let (i, offnum) = le_u16(i)?;
+    Ok((i, HeapOperation::Confirm(Confirm { offnum })))
+}
+
+pub fn parse_heap_lock(i: &[u8]) -> IResult<&[u8], HeapOperation, XLogError<&[u8]>> {
+    let (i, locking_xid) = le_u32(i)?;
+    let (i, offnum) = le_u16(i)?;
+    let (i, infobits) = parse_infobits(i)?;
+    let (i, flags) = le_u8(i)?;
+
+    let heap_lock = Lock {
+        locking_xid,
+        offnum,
+        infobits,
+        all_frozen_cleared: flags & 0x01 != 0,
+    };
+
+    Ok((i, HeapOperation::Lock(heap_lock)))
+}
+
+pub fn parse_heap_inplace(i: &[u8]) -> IResult<&[u8], HeapOperation, XLogError<&[u8]>> {
+    let (i, offnum) = le_u16(i)?;
+    Ok((i, HeapOperation::Inplace(Inplace { offnum })))
 }
 
 pub fn parse_heap_operation(
     rmgr_info: u8,
     i: &[u8],
 ) -> IResult<&[u8], Operation, XLogError<&[u8]>> {
-    const XLOG_HEAP_OPMASK: u8 = 0x70;
     let op = rmgr_info & XLOG_HEAP_OPMASK;
     let (i, heap_operation) = match op {
-        0x00 => parse_heap_insert(i)?,
-        0x10 => parse_heap_delete(i)?,
-        0x20 => parse_heap_update(i)?,
-        0x30 => parse_heap_truncate(i)?,
-        0x40 => parse_heap_hot_update(i)?,
-        0x50 => (i, HeapOperation::Placeholder),
-        0x60 => (i, HeapOperation::Placeholder),
-        0x70 => (i, HeapOperation::Placeholder),
-        _ => panic!("Unreachable"),
+        XLOG_HEAP_INSERT => parse_heap_insert(rmgr_info, i)?,
+        XLOG_HEAP_DELETE => parse_heap_delete(i)?,
+        XLOG_HEAP_UPDATE => parse_heap_update(rmgr_info, i)?,
+        XLOG_HEAP_TRUNCATE => parse_heap_truncate(i)?,
+        XLOG_HEAP_HOT_UPDATE => parse_heap_hot_update(rmgr_info, i)?,
+        XLOG_HEAP_CONFIRM => parse_heap_confirm(i)?,
+        XLOG_HEAP_LOCK => parse_heap_lock(i)?,
+        XLOG_HEAP_INPLACE => parse_heap_inplace(i)?,
+        _ => {
+            return Err(nom::Err::Error(XLogError::UnknownRecordInfo(
+                RmgrId::Heap,
+                rmgr_info,
+            )))
+        }
     };
     debug!("Parsed Operation: {}", heap_operation);
     Ok((i, Operation::Heap(heap_operation)))
@@ -1 +1,67 @@
+use nom::{
+    multi::count,
+    number::complete::{le_i32, le_i64, le_u8},
+    IResult, Parser,
+};
+
+use crate::{error::XLogError, xlog::common::TimestampTz};
+
+pub mod database;
 pub mod heap;
+pub mod standby;
+pub mod storage;
+pub mod tablespace;
+pub mod xact;
+pub mod xlog;
+
+pub fn parse_bool(i: &[u8]) -> IResult<&[u8], bool, XLogError<&[u8]>> {
+    let (i, b) = le_u8(i)?;
+    Ok((i, b != 0))
+}
+
+/// NUL terminated string
+pub fn parse_cstring(i: &[u8]) -> IResult<&[u8], String, XLogError<&[u8]>> {
+    let Some(len) = i.iter().position(|b| *b == 0) else {
+        return Err(nom::Err::Error(XLogError::InvalidRecord(String::from(
+            "unterminated string",
+        ))));
+    };
+    let s = String::from_utf8_lossy(&i[..len]).into_owned();
+    Ok((&i[len + 1..], s))
+}
+
+pub fn parse_timestamptz(i: &[u8]) -> IResult<&[u8], TimestampTz, XLogError<&[u8]>> {
+    let (i, ts) = le_i64(i)?;
+    Ok((i, TimestampTz(ts)))
+}
+
+/// Parse an int count followed by as many elements
+pub fn parse_array<'a, O, F>(f: F, i: &'a [u8]) -> IResult<&'a [u8], Vec<O>, XLogError<&'a [u8]>>
+where
+    F: Parser<&'a [u8], Output = O, Error = XLogError<&'a [u8]>>,
+{
+    let (i, n) = le_i32(i)?;
+    parse_count(f, n, i)
+}
+
+/// Parse n elements, n being a count read from the record
+pub fn parse_count<'a, O, F>(
+    f: F,
+    n: i32,
+    i: &'a [u8],
+) -> IResult<&'a [u8], Vec<O>, XLogError<&'a [u8]>>
+where
+    F: Parser<&'a [u8], Output = O, Error = XLogError<&'a [u8]>>,
+{
+    let n = usize::try_from(n)
+        .map_err(|_| nom::Err::Error(XLogError::InvalidRecord(format!("invalid count {}", n))))?;
+    // Don't let a corrupted count allocate more than the input could hold
+    if n > i.len() {
+        return Err(nom::Err::Error(XLogError::InvalidRecord(format!(
+            "count {} larger than the {} bytes left",
+            n,
+            i.len()
+        ))));
+    }
+    count(f, n).parse(i)
+}
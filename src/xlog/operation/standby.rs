@@ -0,0 +1,190 @@
+use log::debug;
+use nom::{
+    bytes::complete::take,
+    number::complete::{le_i32, le_u32},
+    IResult,
+};
+
+use crate::{
+    error::XLogError,
+    xlog::{
+        common::{Oid, TransactionId},
+        operation::{
+            parse_array, parse_bool, parse_count,
+            xact::{parse_invalidation_message, InvalidationMessage},
+        },
+        record::{Operation, RmgrId},
+    },
+};
+
+pub const XLOG_STANDBY_LOCK: u8 = 0x00;
+pub const XLOG_RUNNING_XACTS: u8 = 0x10;
+pub const XLOG_INVALIDATIONS: u8 = 0x20;
+
+/// AccessExclusiveLock held by a transaction, which a standby has to take
+/// too
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StandbyLock {
+    pub xid: TransactionId,
+    pub db_oid: Oid,
+    pub rel_oid: Oid,
+}
+
+/// Snapshot of the running transactions, used by a standby to start
+/// accepting queries
+#[derive(Clone, Debug)]
+pub struct RunningXacts {
+    /// Subtransactions weren't all logged, the snapshot is only valid once
+    /// the transactions running before it are over
+    pub subxid_overflow: bool,
+    pub next_xid: TransactionId,
+    pub oldest_running_xid: TransactionId,
+    pub latest_completed_xid: TransactionId,
+    pub xids: Vec<TransactionId>,
+    pub subxids: Vec<TransactionId>,
+}
+
+/// Invalidations of a transaction not logged by a commit record, e.g. in
+/// a transaction that didn't get an xid
+#[derive(Clone, Debug)]
+pub struct StandbyInvalidations {
+    pub db_id: Oid,
+    pub ts_id: Oid,
+    pub relcache_init_file_inval: bool,
+    pub msgs: Vec<InvalidationMessage>,
+}
+
+#[derive(Clone, Debug)]
+pub enum StandbyOperation {
+    Lock(Vec<StandbyLock>),
+    RunningXacts(RunningXacts),
+    Invalidations(StandbyInvalidations),
+}
+
+impl std::fmt::Display for StandbyOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StandbyOperation::Lock(locks) => {
+                write!(f, "LOCK")?;
+                for lock in locks {
+                    write!(
+                        f,
+                        " xid {} db {} rel {}",
+                        lock.xid, lock.db_oid, lock.rel_oid
+                    )?;
+                }
+                Ok(())
+            }
+            StandbyOperation::RunningXacts(r) => {
+                write!(
+                    f,
+                    "RUNNING_XACTS nextXid {} latestCompletedXid {} oldestRunningXid {}",
+                    r.next_xid, r.latest_completed_xid, r.oldest_running_xid
+                )?;
+                if !r.xids.is_empty() {
+                    write!(f, "; {} xacts:", r.xids.len())?;
+                    for xid in &r.xids {
+                        write!(f, " {}", xid)?;
+                    }
+                }
+                if r.subxid_overflow {
+                    write!(f, "; subxid overflowed")?;
+                }
+                if !r.subxids.is_empty() {
+                    write!(f, "; {} subxacts:", r.subxids.len())?;
+                    for xid in &r.subxids {
+                        write!(f, " {}", xid)?;
+                    }
+                }
+                Ok(())
+            }
+            StandbyOperation::Invalidations(inv) => {
+                let msgs: Vec<_> = inv.msgs.iter().map(|m| m.to_string()).collect();
+                write!(f, "INVALIDATIONS inval msgs: {}", msgs.join(" "))?;
+                if inv.relcache_init_file_inval {
+                    write!(
+                        f,
+                        "; relcache init file inval dbid {} tsid {}",
+                        inv.db_id, inv.ts_id
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+pub fn parse_standby_lock(i: &[u8]) -> IResult<&[u8], StandbyLock, XLogError<&[u8]>> {
+    let (i, xid) = le_u32(i)?;
+    let (i, db_oid) = le_u32(i)?;
+    let (i, rel_oid) = le_u32(i)?;
+    let lock = StandbyLock {
+        xid,
+        db_oid,
+        rel_oid,
+    };
+    Ok((i, lock))
+}
+
+pub fn parse_running_xacts(i: &[u8]) -> IResult<&[u8], RunningXacts, XLogError<&[u8]>> {
+    let (i, xcnt) = le_i32(i)?;
+    let (i, subxcnt) = le_i32(i)?;
+    let (i, subxid_overflow) = parse_bool(i)?;
+    let (i, _) = take(3usize)(i)?;
+    let (i, next_xid) = le_u32(i)?;
+    let (i, oldest_running_xid) = le_u32(i)?;
+    let (i, latest_completed_xid) = le_u32(i)?;
+    // Top-level xids followed by subxids
+    let (i, xids) = parse_count(le_u32, xcnt, i)?;
+    let (i, subxids) = parse_count(le_u32, subxcnt, i)?;
+    let running_xacts = RunningXacts {
+        subxid_overflow,
+        next_xid,
+        oldest_running_xid,
+        latest_completed_xid,
+        xids,
+        subxids,
+    };
+    Ok((i, running_xacts))
+}
+
+pub fn parse_standby_invalidations(
+    i: &[u8],
+) -> IResult<&[u8], StandbyInvalidations, XLogError<&[u8]>> {
+    let (i, db_id) = le_u32(i)?;
+    let (i, ts_id) = le_u32(i)?;
+    let (i, relcache_init_file_inval) = parse_bool(i)?;
+    let (i, _) = take(3usize)(i)?;
+    let (i, msgs) = parse_array(parse_invalidation_message, i)?;
+    let invalidations = StandbyInvalidations {
+        db_id,
+        ts_id,
+        relcache_init_file_inval,
+        msgs,
+    };
+    Ok((i, invalidations))
+}
+
+pub fn parse_standby_operation(
+    rmgr_info: u8,
+    i: &[u8],
+) -> IResult<&[u8], Operation, XLogError<&[u8]>> {
+    let (i, standby_operation) = match rmgr_info {
+        XLOG_STANDBY_LOCK => {
+            parse_array(parse_standby_lock, i).map(|(i, l)| (i, StandbyOperation::Lock(l)))?
+        }
+        XLOG_RUNNING_XACTS => {
+            parse_running_xacts(i).map(|(i, r)| (i, StandbyOperation::RunningXacts(r)))?
+        }
+        XLOG_INVALIDATIONS => parse_standby_invalidations(i)
+            .map(|(i, inv)| (i, StandbyOperation::Invalidations(inv)))?,
+        info => {
+            return Err(nom::Err::Error(XLogError::UnknownRecordInfo(
+                RmgrId::Standby,
+                info,
+            )))
+        }
+    };
+    debug!("Parsed Operation: {}", standby_operation);
+    Ok((i, Operation::Standby(standby_operation)))
+}
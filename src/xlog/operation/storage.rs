@@ -0,0 +1,125 @@
+use log::debug;
+use nom::{
+    number::complete::{le_i32, le_u32},
+    IResult,
+};
+
+use crate::{
+    error::XLogError,
+    xlog::{
+        block::{parse_relfilenode, BlockNumber, ForkNumber, RelFileLocator},
+        record::{Operation, RmgrId},
+    },
+};
+
+pub const XLOG_SMGR_CREATE: u8 = 0x10;
+pub const XLOG_SMGR_TRUNCATE: u8 = 0x20;
+
+pub const SMGR_TRUNCATE_HEAP: i32 = 0x0001;
+pub const SMGR_TRUNCATE_VM: i32 = 0x0002;
+pub const SMGR_TRUNCATE_FSM: i32 = 0x0004;
+pub const SMGR_TRUNCATE_ALL: i32 = SMGR_TRUNCATE_HEAP | SMGR_TRUNCATE_VM | SMGR_TRUNCATE_FSM;
+
+/// Creation of a fork's file
+#[derive(Clone, Debug)]
+pub struct SmgrCreate {
+    pub locator: RelFileLocator,
+    pub fork: ForkNumber,
+}
+
+/// Truncation of a relation's forks selected by flags
+#[derive(Clone, Debug)]
+pub struct SmgrTruncate {
+    /// Number of blocks left in the main fork
+    pub blkno: BlockNumber,
+    pub locator: RelFileLocator,
+    pub flags: i32,
+}
+
+impl SmgrTruncate {
+    /// Forks truncated, in fork number order
+    pub fn forks(&self) -> Vec<ForkNumber> {
+        [
+            (SMGR_TRUNCATE_HEAP, ForkNumber::Main),
+            (SMGR_TRUNCATE_FSM, ForkNumber::Fsm),
+            (SMGR_TRUNCATE_VM, ForkNumber::VisibilityMap),
+        ]
+        .into_iter()
+        .filter(|(flag, _)| self.flags & flag != 0)
+        .map(|(_, fork)| fork)
+        .collect()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum StorageOperation {
+    Create(SmgrCreate),
+    Truncate(SmgrTruncate),
+}
+
+impl std::fmt::Display for StorageOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StorageOperation::Create(c) => {
+                write!(f, "CREATE rel {} fork {}", c.locator, c.fork.name())
+            }
+            StorageOperation::Truncate(t) => write!(
+                f,
+                "TRUNCATE rel {} to {} blocks flags {}",
+                t.locator, t.blkno, t.flags
+            ),
+        }
+    }
+}
+
+pub fn parse_fork_number(i: &[u8]) -> IResult<&[u8], ForkNumber, XLogError<&[u8]>> {
+    let (i, fork) = le_i32(i)?;
+    let fork = u8::try_from(fork)
+        .ok()
+        .and_then(|fork| ForkNumber::try_from(fork).ok())
+        .ok_or_else(|| {
+            nom::Err::Error(XLogError::InvalidRecord(format!(
+                "invalid fork number {}",
+                fork
+            )))
+        })?;
+    Ok((i, fork))
+}
+
+pub fn parse_smgr_create(i: &[u8]) -> IResult<&[u8], SmgrCreate, XLogError<&[u8]>> {
+    let (i, locator) = parse_relfilenode(i)?;
+    let (i, fork) = parse_fork_number(i)?;
+    Ok((i, SmgrCreate { locator, fork }))
+}
+
+pub fn parse_smgr_truncate(i: &[u8]) -> IResult<&[u8], SmgrTruncate, XLogError<&[u8]>> {
+    let (i, blkno) = le_u32(i)?;
+    let (i, locator) = parse_relfilenode(i)?;
+    let (i, flags) = le_i32(i)?;
+    let truncate = SmgrTruncate {
+        blkno,
+        locator,
+        flags,
+    };
+    Ok((i, truncate))
+}
+
+pub fn parse_storage_operation(
+    rmgr_info: u8,
+    i: &[u8],
+) -> IResult<&[u8], Operation, XLogError<&[u8]>> {
+    let (i, storage_operation) = match rmgr_info {
+        XLOG_SMGR_CREATE => parse_smgr_create(i).map(|(i, c)| (i, StorageOperation::Create(c)))?,
+        XLOG_SMGR_TRUNCATE => {
+            parse_smgr_truncate(i).map(|(i, t)| (i, StorageOperation::Truncate(t)))?
+        }
+        info => {
+            return Err(nom::Err::Error(XLogError::UnknownRecordInfo(
+                RmgrId::Storage,
+                info,
+            )))
+        }
+    };
+    debug!("Parsed Operation: {}", storage_operation);
+    Ok((i, Operation::Storage(storage_operation)))
+}
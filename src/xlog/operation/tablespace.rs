@@ -0,0 +1,62 @@
+use log::debug;
+use nom::{number::complete::le_u32, IResult};
+
+use crate::{
+    error::XLogError,
+    xlog::{
+        common::Oid,
+        operation::parse_cstring,
+        record::{Operation, RmgrId},
+    },
+};
+
+pub const XLOG_TBLSPC_CREATE: u8 = 0x00;
+pub const XLOG_TBLSPC_DROP: u8 = 0x10;
+
+/// Creation of the pg_tblspc symlink to the tablespace's location
+#[derive(Clone, Debug)]
+pub struct TblspcCreate {
+    pub ts_id: Oid,
+    pub ts_path: String,
+}
+
+#[derive(Clone, Debug)]
+pub enum TablespaceOperation {
+    Create(TblspcCreate),
+    Drop(Oid),
+}
+
+impl std::fmt::Display for TablespaceOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TablespaceOperation::Create(c) => write!(f, "CREATE {} \"{}\"", c.ts_id, c.ts_path),
+            TablespaceOperation::Drop(ts_id) => write!(f, "DROP {}", ts_id),
+        }
+    }
+}
+
+pub fn parse_tblspc_create(i: &[u8]) -> IResult<&[u8], TblspcCreate, XLogError<&[u8]>> {
+    let (i, ts_id) = le_u32(i)?;
+    let (i, ts_path) = parse_cstring(i)?;
+    Ok((i, TblspcCreate { ts_id, ts_path }))
+}
+
+pub fn parse_tablespace_operation(
+    rmgr_info: u8,
+    i: &[u8],
+) -> IResult<&[u8], Operation, XLogError<&[u8]>> {
+    let (i, tablespace_operation) = match rmgr_info {
+        XLOG_TBLSPC_CREATE => {
+            parse_tblspc_create(i).map(|(i, c)| (i, TablespaceOperation::Create(c)))?
+        }
+        XLOG_TBLSPC_DROP => le_u32(i).map(|(i, ts_id)| (i, TablespaceOperation::Drop(ts_id)))?,
+        info => {
+            return Err(nom::Err::Error(XLogError::UnknownRecordInfo(
+                RmgrId::Tablespace,
+                info,
+            )))
+        }
+    };
+    debug!("Parsed Operation: {}", tablespace_operation);
+    Ok((i, Operation::Tablespace(tablespace_operation)))
+}
@@ -0,0 +1,481 @@
+use log::debug;
+use nom::{
+    bytes::complete::take,
+    number::complete::{le_i32, le_i8, le_u16, le_u32, le_u64},
+    IResult,
+};
+
+use crate::{
+    error::XLogError,
+    xlog::{
+        block::{parse_relfilenode, RelFileLocator},
+        common::{Oid, TimestampTz, TransactionId},
+        lsn::XLogRecPtr,
+        operation::{parse_array, parse_bool, parse_count, parse_cstring, parse_timestamptz},
+        page::WalVersion,
+        record::{Operation, RmgrId},
+    },
+};
+
+pub const XLOG_XACT_COMMIT: u8 = 0x00;
+pub const XLOG_XACT_PREPARE: u8 = 0x10;
+pub const XLOG_XACT_ABORT: u8 = 0x20;
+pub const XLOG_XACT_COMMIT_PREPARED: u8 = 0x30;
+pub const XLOG_XACT_ABORT_PREPARED: u8 = 0x40;
+pub const XLOG_XACT_ASSIGNMENT: u8 = 0x50;
+pub const XLOG_XACT_INVALIDATIONS: u8 = 0x60;
+pub const XLOG_XACT_OPMASK: u8 = 0x70;
+/// xl_xact_xinfo follows the completion time
+pub const XLOG_XACT_HAS_INFO: u8 = 0x80;
+
+pub const XACT_XINFO_HAS_DBINFO: u32 = 1 << 0;
+pub const XACT_XINFO_HAS_SUBXACTS: u32 = 1 << 1;
+pub const XACT_XINFO_HAS_RELFILENODES: u32 = 1 << 2;
+pub const XACT_XINFO_HAS_INVALS: u32 = 1 << 3;
+pub const XACT_XINFO_HAS_TWOPHASE: u32 = 1 << 4;
+pub const XACT_XINFO_HAS_ORIGIN: u32 = 1 << 5;
+pub const XACT_XINFO_HAS_AE_LOCKS: u32 = 1 << 6;
+pub const XACT_XINFO_HAS_GID: u32 = 1 << 7;
+/// PG15+
+pub const XACT_XINFO_HAS_DROPPED_STATS: u32 = 1 << 8;
+
+/// Sections of a prepared transaction's state are MAXALIGNed
+const MAXIMUM_ALIGNOF: usize = 8;
+
+/// Cache invalidation sent to other backends, SharedInvalidationMessage
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InvalidationMessage {
+    /// Entry of the catalog cache id with this hash value
+    Catcache {
+        id: i8,
+        db_id: Oid,
+        hash_value: u32,
+    },
+    /// Whole catalog cache of a catalog
+    Catalog {
+        db_id: Oid,
+        cat_id: Oid,
+    },
+    /// Relation cache entry, all of them for rel_id 0
+    Relcache {
+        db_id: Oid,
+        rel_id: Oid,
+    },
+    /// Size of a relation's storage
+    Smgr {
+        backend: i32,
+        locator: RelFileLocator,
+    },
+    Relmap {
+        db_id: Oid,
+    },
+    /// Catalog snapshot of a catalog
+    Snapshot {
+        db_id: Oid,
+        rel_id: Oid,
+    },
+    Unknown(i8),
+}
+
+impl std::fmt::Display for InvalidationMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            InvalidationMessage::Catcache { id, .. } => write!(f, "catcache {}", id),
+            InvalidationMessage::Catalog { cat_id, .. } => write!(f, "catalog {}", cat_id),
+            InvalidationMessage::Relcache { rel_id: 0, .. } => write!(f, "relcache all"),
+            InvalidationMessage::Relcache { rel_id, .. } => write!(f, "relcache {}", rel_id),
+            InvalidationMessage::Smgr { locator, .. } => write!(f, "smgr {}", locator),
+            InvalidationMessage::Relmap { db_id } => write!(f, "relmap db {}", db_id),
+            InvalidationMessage::Snapshot { rel_id, .. } => write!(f, "snapshot {}", rel_id),
+            InvalidationMessage::Unknown(id) => write!(f, "unrecognized id {}", id),
+        }
+    }
+}
+
+/// Statistics entry dropped with the objects of a transaction, PG15+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StatsItem {
+    pub kind: i32,
+    pub db_oid: Oid,
+    pub obj_oid: Oid,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct XactDbInfo {
+    pub db_id: Oid,
+    pub ts_id: Oid,
+}
+
+/// Position and time of a transaction replayed by logical replication on
+/// its origin
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct XactOrigin {
+    pub origin_lsn: XLogRecPtr,
+    pub origin_timestamp: TimestampTz,
+}
+
+/// Commit or abort of a transaction, prepared or not
+#[derive(Clone, Debug, Default)]
+pub struct XactCommit {
+    pub xact_time: TimestampTz,
+    /// XACT_XINFO_* and XACT_COMPLETION_* flags
+    pub xinfo: u32,
+    pub dbinfo: Option<XactDbInfo>,
+    pub subxacts: Vec<TransactionId>,
+    /// Relations whose files are removed
+    pub rels: Vec<RelFileLocator>,
+    pub dropped_stats: Vec<StatsItem>,
+    /// Only for commits
+    pub invalidations: Vec<InvalidationMessage>,
+    /// Prepared transaction committed or aborted
+    pub twophase_xid: Option<TransactionId>,
+    pub twophase_gid: Option<String>,
+    pub origin: Option<XactOrigin>,
+}
+
+/// Aborts carry the same parts as commits, without invalidations
+pub type XactAbort = XactCommit;
+
+impl std::fmt::Display for XactCommit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Some(twophase_xid) = self.twophase_xid {
+            write!(f, "{}: ", twophase_xid)?;
+        }
+        write!(f, "{}", self.xact_time)?;
+        if !self.rels.is_empty() {
+            let rels: Vec<_> = self.rels.iter().map(|r| r.to_string()).collect();
+            write!(f, "; rels: {}", rels.join(" "))?;
+        }
+        if !self.dropped_stats.is_empty() {
+            let stats: Vec<_> = self
+                .dropped_stats
+                .iter()
+                .map(|s| format!("{}/{}/{}", s.kind, s.db_oid, s.obj_oid))
+                .collect();
+            write!(f, "; dropped stats: {}", stats.join(" "))?;
+        }
+        if !self.subxacts.is_empty() {
+            write!(f, "; subxacts: {:?}", self.subxacts)?;
+        }
+        if !self.invalidations.is_empty() {
+            let msgs: Vec<_> = self.invalidations.iter().map(|m| m.to_string()).collect();
+            write!(f, "; inval msgs: {}", msgs.join(" "))?;
+        }
+        if let Some(gid) = &self.twophase_gid {
+            write!(f, "; gid {}", gid)?;
+        }
+        if let Some(origin) = &self.origin {
+            write!(
+                f,
+                "; origin: lsn {}, at {}",
+                origin.origin_lsn, origin.origin_timestamp
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// State of a transaction at PREPARE TRANSACTION, from its
+/// TwoPhaseFileHeader and the sections following it
+#[derive(Clone, Debug)]
+pub struct XactPrepare {
+    pub xid: TransactionId,
+    pub database: Oid,
+    pub prepared_at: TimestampTz,
+    pub owner: Oid,
+    pub gid: String,
+    pub subxacts: Vec<TransactionId>,
+    /// Relations to remove if the transaction commits
+    pub commit_rels: Vec<RelFileLocator>,
+    /// Relations to remove if the transaction aborts
+    pub abort_rels: Vec<RelFileLocator>,
+    pub commit_stats: Vec<StatsItem>,
+    pub abort_stats: Vec<StatsItem>,
+    pub invalidations: Vec<InvalidationMessage>,
+    pub init_file_inval: bool,
+    pub origin: XactOrigin,
+}
+
+/// Subtransactions assigned to a top-level transaction, logged every
+/// PGPROC_MAX_CACHED_SUBXIDS subtransactions
+#[derive(Clone, Debug)]
+pub struct XactAssignment {
+    pub xtop: TransactionId,
+    pub xsub: Vec<TransactionId>,
+}
+
+#[derive(Clone, Debug)]
+pub enum TransactionOperation {
+    Commit(XactCommit),
+    Prepare(XactPrepare),
+    Abort(XactAbort),
+    CommitPrepared(XactCommit),
+    AbortPrepared(XactAbort),
+    Assignment(XactAssignment),
+    /// Invalidations logged during the transaction, with wal_level logical
+    Invalidations(Vec<InvalidationMessage>),
+}
+
+impl std::fmt::Display for TransactionOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TransactionOperation::Commit(c) => write!(f, "COMMIT {}", c),
+            TransactionOperation::Prepare(p) => write!(
+                f,
+                "PREPARE gid {}: {}; subxacts: {}; inval msgs: {}",
+                p.gid,
+                p.prepared_at,
+                p.subxacts.len(),
+                p.invalidations.len()
+            ),
+            TransactionOperation::Abort(a) => write!(f, "ABORT {}", a),
+            TransactionOperation::CommitPrepared(c) => write!(f, "COMMIT_PREPARED {}", c),
+            TransactionOperation::AbortPrepared(a) => write!(f, "ABORT_PREPARED {}", a),
+            TransactionOperation::Assignment(a) => {
+                write!(f, "ASSIGNMENT xtop {}: subxacts: {:?}", a.xtop, a.xsub)
+            }
+            TransactionOperation::Invalidations(msgs) => {
+                let msgs: Vec<_> = msgs.iter().map(|m| m.to_string()).collect();
+                write!(f, "INVALIDATIONS inval msgs: {}", msgs.join(" "))
+            }
+        }
+    }
+}
+
+pub fn parse_invalidation_message(
+    i: &[u8],
+) -> IResult<&[u8], InvalidationMessage, XLogError<&[u8]>> {
+    // Union of 16 bytes structs starting with the message id
+    let (i, msg) = take(16usize)(i)?;
+    let (fields, id) = le_i8(msg)?;
+    let message = match id {
+        0.. => {
+            let (fields, _) = take(3usize)(fields)?;
+            let (fields, db_id) = le_u32(fields)?;
+            let (_, hash_value) = le_u32(fields)?;
+            InvalidationMessage::Catcache {
+                id,
+                db_id,
+                hash_value,
+            }
+        }
+        -3 => {
+            let (fields, backend_hi) = le_i8(fields)?;
+            let (fields, backend_lo) = le_u16(fields)?;
+            let (_, locator) = parse_relfilenode(fields)?;
+            InvalidationMessage::Smgr {
+                backend: (i32::from(backend_hi) << 16) | i32::from(backend_lo),
+                locator,
+            }
+        }
+        -1 | -2 | -4 | -5 => {
+            let (fields, _) = take(3usize)(fields)?;
+            let (fields, db_id) = le_u32(fields)?;
+            let (_, oid) = le_u32(fields)?;
+            match id {
+                -1 => InvalidationMessage::Catalog { db_id, cat_id: oid },
+                -2 => InvalidationMessage::Relcache { db_id, rel_id: oid },
+                -4 => InvalidationMessage::Relmap { db_id },
+                _ => InvalidationMessage::Snapshot { db_id, rel_id: oid },
+            }
+        }
+        _ => InvalidationMessage::Unknown(id),
+    };
+    Ok((i, message))
+}
+
+pub fn parse_stats_item(i: &[u8]) -> IResult<&[u8], StatsItem, XLogError<&[u8]>> {
+    let (i, kind) = le_i32(i)?;
+    let (i, db_oid) = le_u32(i)?;
+    let (i, obj_oid) = le_u32(i)?;
+    Ok((
+        i,
+        StatsItem {
+            kind,
+            db_oid,
+            obj_oid,
+        },
+    ))
+}
+
+fn parse_origin(i: &[u8]) -> IResult<&[u8], XactOrigin, XLogError<&[u8]>> {
+    let (i, origin_lsn) = le_u64(i)?;
+    let (i, origin_timestamp) = parse_timestamptz(i)?;
+    let origin = XactOrigin {
+        origin_lsn: XLogRecPtr::from(origin_lsn),
+        origin_timestamp,
+    };
+    Ok((i, origin))
+}
+
+/// Parse a commit or abort record, their optional parts announced by xinfo
+/// following in a fixed order, as ParseCommitRecord and ParseAbortRecord do
+pub fn parse_xact_commit(rmgr_info: u8, i: &[u8]) -> IResult<&[u8], XactCommit, XLogError<&[u8]>> {
+    let (i, xact_time) = parse_timestamptz(i)?;
+    let (mut i, xinfo) = if rmgr_info & XLOG_XACT_HAS_INFO != 0 {
+        le_u32(i)?
+    } else {
+        (i, 0)
+    };
+    let mut commit = XactCommit {
+        xact_time,
+        xinfo,
+        ..Default::default()
+    };
+
+    if xinfo & XACT_XINFO_HAS_DBINFO != 0 {
+        let (rest, db_id) = le_u32(i)?;
+        let (rest, ts_id) = le_u32(rest)?;
+        commit.dbinfo = Some(XactDbInfo { db_id, ts_id });
+        i = rest;
+    }
+    if xinfo & XACT_XINFO_HAS_SUBXACTS != 0 {
+        (i, commit.subxacts) = parse_array(le_u32, i)?;
+    }
+    if xinfo & XACT_XINFO_HAS_RELFILENODES != 0 {
+        (i, commit.rels) = parse_array(parse_relfilenode, i)?;
+    }
+    if xinfo & XACT_XINFO_HAS_DROPPED_STATS != 0 {
+        (i, commit.dropped_stats) = parse_array(parse_stats_item, i)?;
+    }
+    if xinfo & XACT_XINFO_HAS_INVALS != 0 {
+        (i, commit.invalidations) = parse_array(parse_invalidation_message, i)?;
+    }
+    if xinfo & XACT_XINFO_HAS_TWOPHASE != 0 {
+        let (rest, twophase_xid) = le_u32(i)?;
+        commit.twophase_xid = Some(twophase_xid);
+        i = rest;
+        if xinfo & XACT_XINFO_HAS_GID != 0 {
+            let (rest, gid) = parse_cstring(i)?;
+            commit.twophase_gid = Some(gid);
+            i = rest;
+        }
+    }
+    if xinfo & XACT_XINFO_HAS_ORIGIN != 0 {
+        let (rest, origin) = parse_origin(i)?;
+        commit.origin = Some(origin);
+        i = rest;
+    }
+    Ok((i, commit))
+}
+
+/// Skip to the next MAXALIGNed section, offset being the size of the
+/// section just read
+fn skip_padding(i: &[u8], offset: usize) -> IResult<&[u8], (), XLogError<&[u8]>> {
+    let (i, _) = take(offset.next_multiple_of(MAXIMUM_ALIGNOF) - offset)(i)?;
+    Ok((i, ()))
+}
+
+/// Parse n MAXALIGNed elements of size bytes
+fn parse_section<'a, O, F>(
+    f: F,
+    n: i32,
+    size: usize,
+    i: &'a [u8],
+) -> IResult<&'a [u8], Vec<O>, XLogError<&'a [u8]>>
+where
+    F: nom::Parser<&'a [u8], Output = O, Error = XLogError<&'a [u8]>>,
+{
+    let (i, elements) = parse_count(f, n, i)?;
+    let (i, _) = skip_padding(i, elements.len() * size)?;
+    Ok((i, elements))
+}
+
+/// Parse the TwoPhaseFileHeader and the state following it, see
+/// ParsePrepareRecord
+pub fn parse_xact_prepare(
+    version: WalVersion,
+    i: &[u8],
+) -> IResult<&[u8], XactPrepare, XLogError<&[u8]>> {
+    let header_start = i.len();
+    let (i, _magic) = le_u32(i)?;
+    let (i, _total_len) = le_u32(i)?;
+    let (i, xid) = le_u32(i)?;
+    let (i, database) = le_u32(i)?;
+    let (i, prepared_at) = parse_timestamptz(i)?;
+    let (i, owner) = le_u32(i)?;
+    let (i, nsubxacts) = le_i32(i)?;
+    let (i, ncommitrels) = le_i32(i)?;
+    let (i, nabortrels) = le_i32(i)?;
+    let (i, (ncommitstats, nabortstats)) = if version >= WalVersion::V15 {
+        let (i, ncommitstats) = le_i32(i)?;
+        let (i, nabortstats) = le_i32(i)?;
+        (i, (ncommitstats, nabortstats))
+    } else {
+        (i, (0, 0))
+    };
+    let (i, ninvalmsgs) = le_i32(i)?;
+    let (i, init_file_inval) = parse_bool(i)?;
+    let (i, _) = take(1usize)(i)?;
+    let (i, gidlen) = le_u16(i)?;
+    let (i, origin) = parse_origin(i)?;
+    let (i, _) = skip_padding(i, header_start - i.len())?;
+
+    let gidlen = usize::from(gidlen);
+    let (i, gid) = take(gidlen)(i)?;
+    let (_, gid) = parse_cstring(gid)?;
+    let (i, _) = skip_padding(i, gidlen)?;
+    let (i, subxacts) = parse_section(le_u32, nsubxacts, 4, i)?;
+    let (i, commit_rels) = parse_section(parse_relfilenode, ncommitrels, 12, i)?;
+    let (i, abort_rels) = parse_section(parse_relfilenode, nabortrels, 12, i)?;
+    let (i, commit_stats) = parse_section(parse_stats_item, ncommitstats, 12, i)?;
+    let (i, abort_stats) = parse_section(parse_stats_item, nabortstats, 12, i)?;
+    let (i, invalidations) = parse_section(parse_invalidation_message, ninvalmsgs, 16, i)?;
+
+    let prepare = XactPrepare {
+        xid,
+        database,
+        prepared_at,
+        owner,
+        gid,
+        subxacts,
+        commit_rels,
+        abort_rels,
+        commit_stats,
+        abort_stats,
+        invalidations,
+        init_file_inval,
+        origin,
+    };
+    Ok((i, prepare))
+}
+
+pub fn parse_xact_assignment(i: &[u8]) -> IResult<&[u8], XactAssignment, XLogError<&[u8]>> {
+    let (i, xtop) = le_u32(i)?;
+    let (i, xsub) = parse_array(le_u32, i)?;
+    Ok((i, XactAssignment { xtop, xsub }))
+}
+
+pub fn parse_xact_operation(
+    rmgr_info: u8,
+    version: WalVersion,
+    i: &[u8],
+) -> IResult<&[u8], Operation, XLogError<&[u8]>> {
+    let (i, xact_operation) =
+        match rmgr_info & XLOG_XACT_OPMASK {
+            XLOG_XACT_COMMIT => parse_xact_commit(rmgr_info, i)
+                .map(|(i, c)| (i, TransactionOperation::Commit(c)))?,
+            XLOG_XACT_PREPARE => parse_xact_prepare(version, i)
+                .map(|(i, p)| (i, TransactionOperation::Prepare(p)))?,
+            XLOG_XACT_ABORT => {
+                parse_xact_commit(rmgr_info, i).map(|(i, a)| (i, TransactionOperation::Abort(a)))?
+            }
+            XLOG_XACT_COMMIT_PREPARED => parse_xact_commit(rmgr_info, i)
+                .map(|(i, c)| (i, TransactionOperation::CommitPrepared(c)))?,
+            XLOG_XACT_ABORT_PREPARED => parse_xact_commit(rmgr_info, i)
+                .map(|(i, a)| (i, TransactionOperation::AbortPrepared(a)))?,
+            XLOG_XACT_ASSIGNMENT => {
+                parse_xact_assignment(i).map(|(i, a)| (i, TransactionOperation::Assignment(a)))?
+            }
+            XLOG_XACT_INVALIDATIONS => parse_array(parse_invalidation_message, i)
+                .map(|(i, msgs)| (i, TransactionOperation::Invalidations(msgs)))?,
+            _ => {
+                return Err(nom::Err::Error(XLogError::UnknownRecordInfo(
+                    RmgrId::Transaction,
+                    rmgr_info,
+                )))
+            }
+        };
+    debug!("Parsed Operation: {}", xact_operation);
+    Ok((i, Operation::Transaction(xact_operation)))
+}
@@ -0,0 +1,397 @@
+use log::debug;
+use nom::{
+    bytes::complete::take,
+    number::complete::{le_i32, le_i64, le_u32, le_u64},
+    IResult,
+};
+
+use crate::{
+    error::XLogError,
+    xlog::{
+        common::{
+            FullTransactionId, MultiXactId, MultiXactOffset, Oid, TimestampTz, TransactionId,
+        },
+        lsn::{TimelineID, XLogRecPtr},
+        operation::{parse_bool, parse_timestamptz},
+        page::WalVersion,
+        record::{Operation, RmgrId, XLOG_SWITCH},
+    },
+};
+
+pub const XLOG_CHECKPOINT_SHUTDOWN: u8 = 0x00;
+pub const XLOG_CHECKPOINT_ONLINE: u8 = 0x10;
+pub const XLOG_NOOP: u8 = 0x20;
+pub const XLOG_NEXTOID: u8 = 0x30;
+pub const XLOG_BACKUP_END: u8 = 0x50;
+pub const XLOG_PARAMETER_CHANGE: u8 = 0x60;
+pub const XLOG_RESTORE_POINT: u8 = 0x70;
+pub const XLOG_FPW_CHANGE: u8 = 0x80;
+pub const XLOG_END_OF_RECOVERY: u8 = 0x90;
+pub const XLOG_FPI_FOR_HINT: u8 = 0xa0;
+pub const XLOG_FPI: u8 = 0xb0;
+pub const XLOG_OVERWRITE_CONTRECORD: u8 = 0xd0;
+/// Start of a checkpoint's redo, PG17+
+pub const XLOG_CHECKPOINT_REDO: u8 = 0xe0;
+
+/// Size of the name of a restore point, MAXFNAMELEN
+const RESTORE_POINT_NAME_LEN: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WalLevel {
+    Minimal,
+    Replica,
+    Logical,
+}
+
+impl std::fmt::Display for WalLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            WalLevel::Minimal => "minimal",
+            WalLevel::Replica => "replica",
+            WalLevel::Logical => "logical",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CheckPoint {
+    /// Where to start replay from
+    pub redo: XLogRecPtr,
+    pub this_timeline_id: TimelineID,
+    pub prev_timeline_id: TimelineID,
+    pub full_page_writes: bool,
+    /// Only stored since PostgreSQL 17
+    pub wal_level: Option<WalLevel>,
+    pub next_xid: FullTransactionId,
+    pub next_oid: Oid,
+    pub next_multi: MultiXactId,
+    pub next_multi_offset: MultiXactOffset,
+    /// Cluster-wide minimum datfrozenxid and the database it's in
+    pub oldest_xid: TransactionId,
+    pub oldest_xid_db: Oid,
+    /// Cluster-wide minimum datminmxid and the database it's in
+    pub oldest_multi: MultiXactId,
+    pub oldest_multi_db: Oid,
+    pub time: TimestampTz,
+    pub oldest_commit_ts_xid: TransactionId,
+    pub newest_commit_ts_xid: TransactionId,
+    /// Oldest running xid when an online checkpoint started, 0 for
+    /// shutdown checkpoints
+    pub oldest_active_xid: TransactionId,
+}
+
+impl std::fmt::Display for CheckPoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "redo {}; tli {}; prev tli {}; fpw {}; ",
+            self.redo, self.this_timeline_id, self.prev_timeline_id, self.full_page_writes
+        )?;
+        if let Some(wal_level) = self.wal_level {
+            write!(f, "wal_level {}; ", wal_level)?;
+        }
+        write!(
+            f,
+            "xid {}:{}; oid {}; multi {}; offset {}; oldest xid {} in DB {}; oldest multi {} in DB {}; oldest/newest commit timestamp xid: {}/{}; oldest running xid {}; time {}",
+            self.next_xid >> 32,
+            self.next_xid as TransactionId,
+            self.next_oid,
+            self.next_multi,
+            self.next_multi_offset,
+            self.oldest_xid,
+            self.oldest_xid_db,
+            self.oldest_multi,
+            self.oldest_multi_db,
+            self.oldest_commit_ts_xid,
+            self.newest_commit_ts_xid,
+            self.oldest_active_xid,
+            self.time
+        )
+    }
+}
+
+/// Settings that can't be lower on a standby than on the primary, logged
+/// when they change
+#[derive(Clone, Debug)]
+pub struct ParameterChange {
+    pub max_connections: i32,
+    pub max_worker_processes: i32,
+    pub max_wal_senders: i32,
+    pub max_prepared_xacts: i32,
+    pub max_locks_per_xact: i32,
+    pub wal_level: WalLevel,
+    pub wal_log_hints: bool,
+    pub track_commit_timestamp: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct RestorePoint {
+    pub time: TimestampTz,
+    pub name: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct EndOfRecovery {
+    pub end_time: TimestampTz,
+    /// New timeline
+    pub this_timeline_id: TimelineID,
+    /// Timeline recovery ended on
+    pub prev_timeline_id: TimelineID,
+    /// Only stored since PostgreSQL 17
+    pub wal_level: Option<WalLevel>,
+}
+
+/// Record written where a record continued on a page that was never written
+/// got cut off
+#[derive(Clone, Debug)]
+pub struct OverwriteContrecord {
+    pub overwritten_lsn: XLogRecPtr,
+    pub overwrite_time: TimestampTz,
+}
+
+#[derive(Clone, Debug)]
+pub enum XlogOperation {
+    CheckpointShutdown(CheckPoint),
+    CheckpointOnline(CheckPoint),
+    Noop,
+    NextOid(Oid),
+    Switch,
+    /// Start of the backup that ended
+    BackupEnd(XLogRecPtr),
+    ParameterChange(ParameterChange),
+    RestorePoint(RestorePoint),
+    /// New value of full_page_writes
+    FpwChange(bool),
+    EndOfRecovery(EndOfRecovery),
+    /// Full page images written by a hint bit change, in the blocks
+    FpiForHint,
+    /// Full page images not tied to a change, in the blocks
+    Fpi,
+    OverwriteContrecord(OverwriteContrecord),
+    CheckpointRedo(WalLevel),
+}
+
+impl std::fmt::Display for XlogOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            XlogOperation::CheckpointShutdown(c) => write!(f, "CHECKPOINT_SHUTDOWN {}", c),
+            XlogOperation::CheckpointOnline(c) => write!(f, "CHECKPOINT_ONLINE {}", c),
+            XlogOperation::Noop => write!(f, "NOOP"),
+            XlogOperation::NextOid(oid) => write!(f, "NEXTOID {}", oid),
+            XlogOperation::Switch => write!(f, "SWITCH"),
+            XlogOperation::BackupEnd(start) => write!(f, "BACKUP_END {}", start),
+            XlogOperation::ParameterChange(p) => write!(
+                f,
+                "PARAMETER_CHANGE max_connections={} max_worker_processes={} max_wal_senders={} max_prepared_xacts={} max_locks_per_xact={} wal_level={} wal_log_hints={} track_commit_timestamp={}",
+                p.max_connections,
+                p.max_worker_processes,
+                p.max_wal_senders,
+                p.max_prepared_xacts,
+                p.max_locks_per_xact,
+                p.wal_level,
+                p.wal_log_hints,
+                p.track_commit_timestamp
+            ),
+            XlogOperation::RestorePoint(r) => {
+                write!(f, "RESTORE_POINT {}; time {}", r.name, r.time)
+            }
+            XlogOperation::FpwChange(fpw) => write!(f, "FPW_CHANGE {}", fpw),
+            XlogOperation::EndOfRecovery(e) => {
+                write!(
+                    f,
+                    "END_OF_RECOVERY tli {}; prev tli {}; time {}",
+                    e.this_timeline_id, e.prev_timeline_id, e.end_time
+                )?;
+                if let Some(wal_level) = e.wal_level {
+                    write!(f, "; wal_level {}", wal_level)?;
+                }
+                Ok(())
+            }
+            XlogOperation::FpiForHint => write!(f, "FPI_FOR_HINT"),
+            XlogOperation::Fpi => write!(f, "FPI"),
+            XlogOperation::OverwriteContrecord(o) => write!(
+                f,
+                "OVERWRITE_CONTRECORD lsn {}; time {}",
+                o.overwritten_lsn, o.overwrite_time
+            ),
+            XlogOperation::CheckpointRedo(wal_level) => {
+                write!(f, "CHECKPOINT_REDO wal_level {}", wal_level)
+            }
+        }
+    }
+}
+
+pub fn parse_wal_level(i: &[u8]) -> IResult<&[u8], WalLevel, XLogError<&[u8]>> {
+    let (i, wal_level) = le_i32(i)?;
+    let wal_level = match wal_level {
+        0 => WalLevel::Minimal,
+        1 => WalLevel::Replica,
+        2 => WalLevel::Logical,
+        l => {
+            return Err(nom::Err::Error(XLogError::InvalidRecord(format!(
+                "invalid wal_level {}",
+                l
+            ))))
+        }
+    };
+    Ok((i, wal_level))
+}
+
+pub fn parse_checkpoint(
+    version: WalVersion,
+    i: &[u8],
+) -> IResult<&[u8], CheckPoint, XLogError<&[u8]>> {
+    let (i, redo) = le_u64(i)?;
+    let (i, this_timeline_id) = le_u32(i)?;
+    let (i, prev_timeline_id) = le_u32(i)?;
+    let (i, full_page_writes) = parse_bool(i)?;
+    // nextXid is 8 bytes aligned, wal_level fills the padding since PG17
+    let (i, _) = take(3usize)(i)?;
+    let (i, wal_level) = if version >= WalVersion::V17 {
+        parse_wal_level(i).map(|(i, l)| (i, Some(l)))?
+    } else {
+        take(4usize)(i).map(|(i, _)| (i, None))?
+    };
+    let (i, next_xid) = le_u64(i)?;
+    let (i, next_oid) = le_u32(i)?;
+    let (i, next_multi) = le_u32(i)?;
+    let (i, next_multi_offset) = le_u32(i)?;
+    let (i, oldest_xid) = le_u32(i)?;
+    let (i, oldest_xid_db) = le_u32(i)?;
+    let (i, oldest_multi) = le_u32(i)?;
+    let (i, oldest_multi_db) = le_u32(i)?;
+    let (i, _) = take(4usize)(i)?;
+    let (i, time) = le_i64(i)?;
+    let (i, oldest_commit_ts_xid) = le_u32(i)?;
+    let (i, newest_commit_ts_xid) = le_u32(i)?;
+    let (i, oldest_active_xid) = le_u32(i)?;
+    let checkpoint = CheckPoint {
+        redo: XLogRecPtr::from(redo),
+        this_timeline_id,
+        prev_timeline_id,
+        full_page_writes,
+        wal_level,
+        next_xid,
+        next_oid,
+        next_multi,
+        next_multi_offset,
+        oldest_xid,
+        oldest_xid_db,
+        oldest_multi,
+        oldest_multi_db,
+        time: TimestampTz::from_unix_time(time),
+        oldest_commit_ts_xid,
+        newest_commit_ts_xid,
+        oldest_active_xid,
+    };
+    Ok((i, checkpoint))
+}
+
+pub fn parse_parameter_change(i: &[u8]) -> IResult<&[u8], ParameterChange, XLogError<&[u8]>> {
+    let (i, max_connections) = le_i32(i)?;
+    let (i, max_worker_processes) = le_i32(i)?;
+    let (i, max_wal_senders) = le_i32(i)?;
+    let (i, max_prepared_xacts) = le_i32(i)?;
+    let (i, max_locks_per_xact) = le_i32(i)?;
+    let (i, wal_level) = parse_wal_level(i)?;
+    let (i, wal_log_hints) = parse_bool(i)?;
+    let (i, track_commit_timestamp) = parse_bool(i)?;
+    let parameter_change = ParameterChange {
+        max_connections,
+        max_worker_processes,
+        max_wal_senders,
+        max_prepared_xacts,
+        max_locks_per_xact,
+        wal_level,
+        wal_log_hints,
+        track_commit_timestamp,
+    };
+    Ok((i, parameter_change))
+}
+
+pub fn parse_restore_point(i: &[u8]) -> IResult<&[u8], RestorePoint, XLogError<&[u8]>> {
+    let (i, time) = parse_timestamptz(i)?;
+    let (i, name) = take(RESTORE_POINT_NAME_LEN)(i)?;
+    // NUL terminated
+    let len = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+    let name = String::from_utf8_lossy(&name[..len]).into_owned();
+    Ok((i, RestorePoint { time, name }))
+}
+
+pub fn parse_end_of_recovery(
+    version: WalVersion,
+    i: &[u8],
+) -> IResult<&[u8], EndOfRecovery, XLogError<&[u8]>> {
+    let (i, end_time) = parse_timestamptz(i)?;
+    let (i, this_timeline_id) = le_u32(i)?;
+    let (i, prev_timeline_id) = le_u32(i)?;
+    let (i, wal_level) = if version >= WalVersion::V17 {
+        parse_wal_level(i).map(|(i, l)| (i, Some(l)))?
+    } else {
+        (i, None)
+    };
+    let end_of_recovery = EndOfRecovery {
+        end_time,
+        this_timeline_id,
+        prev_timeline_id,
+        wal_level,
+    };
+    Ok((i, end_of_recovery))
+}
+
+pub fn parse_overwrite_contrecord(
+    i: &[u8],
+) -> IResult<&[u8], OverwriteContrecord, XLogError<&[u8]>> {
+    let (i, overwritten_lsn) = le_u64(i)?;
+    let (i, overwrite_time) = parse_timestamptz(i)?;
+    let overwrite_contrecord = OverwriteContrecord {
+        overwritten_lsn: XLogRecPtr::from(overwritten_lsn),
+        overwrite_time,
+    };
+    Ok((i, overwrite_contrecord))
+}
+
+pub fn parse_xlog_operation(
+    rmgr_info: u8,
+    version: WalVersion,
+    i: &[u8],
+) -> IResult<&[u8], Operation, XLogError<&[u8]>> {
+    let (i, xlog_operation) =
+        match rmgr_info {
+            XLOG_CHECKPOINT_SHUTDOWN => parse_checkpoint(version, i)
+                .map(|(i, c)| (i, XlogOperation::CheckpointShutdown(c)))?,
+            XLOG_CHECKPOINT_ONLINE => parse_checkpoint(version, i)
+                .map(|(i, c)| (i, XlogOperation::CheckpointOnline(c)))?,
+            // Filler of arbitrary length
+            XLOG_NOOP => (&i[i.len()..], XlogOperation::Noop),
+            XLOG_NEXTOID => le_u32(i).map(|(i, oid)| (i, XlogOperation::NextOid(oid)))?,
+            XLOG_SWITCH => (i, XlogOperation::Switch),
+            XLOG_BACKUP_END => le_u64(i)
+                .map(|(i, start)| (i, XlogOperation::BackupEnd(XLogRecPtr::from(start))))?,
+            XLOG_PARAMETER_CHANGE => {
+                parse_parameter_change(i).map(|(i, p)| (i, XlogOperation::ParameterChange(p)))?
+            }
+            XLOG_RESTORE_POINT => {
+                parse_restore_point(i).map(|(i, r)| (i, XlogOperation::RestorePoint(r)))?
+            }
+            XLOG_FPW_CHANGE => parse_bool(i).map(|(i, fpw)| (i, XlogOperation::FpwChange(fpw)))?,
+            XLOG_END_OF_RECOVERY => parse_end_of_recovery(version, i)
+                .map(|(i, e)| (i, XlogOperation::EndOfRecovery(e)))?,
+            XLOG_FPI_FOR_HINT => (i, XlogOperation::FpiForHint),
+            XLOG_FPI => (i, XlogOperation::Fpi),
+            XLOG_OVERWRITE_CONTRECORD => parse_overwrite_contrecord(i)
+                .map(|(i, o)| (i, XlogOperation::OverwriteContrecord(o)))?,
+            XLOG_CHECKPOINT_REDO if version >= WalVersion::V17 => {
+                parse_wal_level(i).map(|(i, l)| (i, XlogOperation::CheckpointRedo(l)))?
+            }
+            info => {
+                return Err(nom::Err::Error(XLogError::UnknownRecordInfo(
+                    RmgrId::Xlog,
+                    info,
+                )))
+            }
+        };
+    debug!("Parsed Operation: {}", xlog_operation);
+    Ok((i, Operation::Xlog(xlog_operation)))
+}
@@ -3,7 +3,6 @@ use std::mem;
 use crate::error::XLogError;
 use crate::xlog::record::{consume_padding, parse_xlog_records, XLogRecord};
 use log::debug;
-use nom::combinator::map;
 use nom::multi::many1;
 use nom::number::complete::{le_u16, le_u32, le_u64};
 use nom::IResult;
@@ -20,7 +19,60 @@ pub const XLP_FIRST_IS_OVERWRITE_CONTRECORD: u16 = 0x0008;
 // All defined flag bits in xlp_info (used for validity checking of header)
 pub const XLP_ALL_FLAGS: u16 = 0x000F;
 
-const XLP_MAGIC: u16 = 0xd10d;
+pub const XLP_MAGIC: u16 = 0xd10d;
+
+/// Major version of the server that wrote the WAL, known from the magic
+/// number of page headers. Record formats differ between versions.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WalVersion {
+    #[default]
+    V14,
+    V15,
+    V16,
+    V17,
+}
+
+impl WalVersion {
+    pub fn from_magic(magic: u16) -> Option<Self> {
+        match magic {
+            XLP_MAGIC => Some(WalVersion::V14),
+            0xd110 => Some(WalVersion::V15),
+            0xd113 => Some(WalVersion::V16),
+            0xd116 => Some(WalVersion::V17),
+            _ => None,
+        }
+    }
+
+    pub fn magic(&self) -> u16 {
+        match self {
+            WalVersion::V14 => XLP_MAGIC,
+            WalVersion::V15 => 0xd110,
+            WalVersion::V16 => 0xd113,
+            WalVersion::V17 => 0xd116,
+        }
+    }
+}
+
+impl std::fmt::Display for WalVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            WalVersion::V14 => "14",
+            WalVersion::V15 => "15",
+            WalVersion::V16 => "16",
+            WalVersion::V17 => "17",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// WAL block size used when PostgreSQL is built with default options
+pub const DEFAULT_XLOG_BLCKSZ: usize = 8192;
+
+/// WAL block sizes allowed by --with-wal-blocksize: a power of two between
+/// 1kB and 64kB
+pub fn is_valid_xlog_blcksz(blcksz: usize) -> bool {
+    blcksz.is_power_of_two() && (1 << 10..=1 << 16).contains(&blcksz)
+}
 
 #[derive(Clone, Debug)]
 pub struct XLogShortPageHeader {
@@ -82,6 +134,29 @@ impl std::fmt::Display for XLogPageHeader {
     }
 }
 
+impl XLogPageHeader {
+    /// Standard header fields, present on both short and long headers
+    pub fn std(&self) -> &XLogShortPageHeader {
+        match self {
+            XLogPageHeader::Short(std) => std,
+            XLogPageHeader::Long(long) => &long.std,
+        }
+    }
+
+    /// Version of the server that wrote the page
+    pub fn version(&self) -> Option<WalVersion> {
+        WalVersion::from_magic(self.std().xlp_magic)
+    }
+
+    /// Size of the header on disk, record data starts right after
+    pub fn size(&self) -> usize {
+        match self {
+            XLogPageHeader::Short(_) => mem::size_of::<XLogShortPageHeader>(),
+            XLogPageHeader::Long(_) => mem::size_of::<XLogLongPageHeader>(),
+        }
+    }
+}
+
 pub struct XLogPageContent {
     pub page_header: XLogPageHeader,
     pub records: Vec<XLogRecord>,
@@ -108,7 +183,7 @@ pub fn parse_xlog_page_header(i: &[u8]) -> IResult<&[u8], XLogPageHeader, XLogEr
         )));
     }
     let (i, xlp_magic) = le_u16(i)?;
-    if xlp_magic != XLP_MAGIC {
+    if WalVersion::from_magic(xlp_magic).is_none() {
         return Err(nom::Err::Failure(XLogError::InvalidPageHeader));
     }
     let (i, xlp_info) = le_u16(i)?;
@@ -154,16 +229,22 @@ pub fn parse_xlog_page_header(i: &[u8]) -> IResult<&[u8], XLogPageHeader, XLogEr
     Ok((i, XLogPageHeader::from(page_header)))
 }
 
-pub fn parse_xlog_page(i: &[u8]) -> IResult<&[u8], XLogPageContent, XLogError<&[u8]>> {
-    map((parse_xlog_page_header, parse_xlog_records), |t| {
+pub fn parse_xlog_page(blcksz: u16, i: &[u8]) -> IResult<&[u8], XLogPageContent, XLogError<&[u8]>> {
+    let (i, page_header) = parse_xlog_page_header(i)?;
+    let version = page_header.version().unwrap_or_default();
+    let (i, records) = parse_xlog_records(blcksz, version, i)?;
+    Ok((
+        i,
         XLogPageContent {
-            page_header: t.0,
-            records: t.1,
-        }
-    })
-    .parse(i)
+            page_header,
+            records,
+        },
+    ))
 }
 
-pub fn parse_xlog_pages(i: &[u8]) -> IResult<&[u8], Vec<XLogPageContent>, XLogError<&[u8]>> {
-    many1(parse_xlog_page).parse(i)
+pub fn parse_xlog_pages(
+    blcksz: u16,
+    i: &[u8],
+) -> IResult<&[u8], Vec<XLogPageContent>, XLogError<&[u8]>> {
+    many1(|i| parse_xlog_page(blcksz, i)).parse(i)
 }
@@ -0,0 +1,225 @@
+use std::error::Error;
+use std::panic;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use log::debug;
+
+use crate::xlog::block::DEFAULT_BLCKSZ;
+use crate::xlog::lsn::{parse_filename, TimelineID, XLogFilePos, XLogRecPtr, XLogSegNo};
+use crate::xlog::reader::{read_segment_sizes, ReaderError, XLogReader};
+use crate::xlog::record::XLogRecord;
+use crate::xlog::source::SegmentSource;
+use crate::xlog::timeline::{find_newest_timeline, read_timeline_history, timeline_of_segment};
+use crate::xlog::validate::EndOfWal;
+
+/// Scan of consecutive WAL segments on a pool of worker threads, for stats
+/// and searches over many segments. Each segment is read by one worker
+/// from its first record boundary, skipping the tail of the record
+/// continued from the previous segment. That record is read by the worker
+/// of the segment it starts in, which reads on in the next segment to
+/// complete it. Results are returned in LSN order.
+///
+/// Workers open segments in any order, so the source can't be a stream.
+pub struct ParallelScan {
+    source: Arc<dyn SegmentSource>,
+    /// Timeline and segment number of the segments to scan, in LSN order
+    segments: Vec<(TimelineID, XLogSegNo)>,
+    end_segment: Option<String>,
+    target_tli: TimelineID,
+    wal_seg_size: u64,
+    workers: usize,
+    blcksz: u16,
+}
+
+impl ParallelScan {
+    /// Scan segments of source from start_segment to end_segment, following
+    /// timeline switches like XLogReader::from_source does. Without
+    /// end_segment, segments are scanned up to the first one missing from
+    /// the source's listing.
+    pub fn new(
+        source: Box<dyn SegmentSource>,
+        start_segment: &str,
+        end_segment: Option<&str>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let source: Arc<dyn SegmentSource> = source.into();
+        let file_pos = parse_filename(start_segment)?;
+        let target_tli = match end_segment {
+            Some(end_segment) => parse_filename(end_segment)?.tli,
+            None => find_newest_timeline(source.as_ref(), file_pos.tli)?,
+        };
+        let timeline_history = read_timeline_history(source.as_ref(), target_tli)?;
+        let (wal_seg_size, _) = read_segment_sizes(&mut source.open(start_segment)?)?;
+        let segment_tli = |segno| {
+            timeline_of_segment(&timeline_history, segno, wal_seg_size).unwrap_or(file_pos.tli)
+        };
+
+        let start_segno = file_pos.get_segno(wal_seg_size);
+        let end_segno = match end_segment {
+            Some(end_segment) => parse_filename(end_segment)?.get_segno(wal_seg_size),
+            None => {
+                let available = source.list_segments(wal_seg_size)?;
+                let mut segno = start_segno;
+                while available
+                    .binary_search(&(segment_tli(segno + 1), segno + 1))
+                    .is_ok()
+                {
+                    segno += 1;
+                }
+                segno
+            }
+        };
+        if end_segno < start_segno {
+            return Err(format!(
+                "End segment {} is before start segment {}",
+                end_segment.unwrap_or_default(),
+                start_segment
+            )
+            .into());
+        }
+        let segments = (start_segno..=end_segno)
+            .map(|segno| (segment_tli(segno), segno))
+            .collect();
+
+        Ok(Self {
+            source,
+            segments,
+            end_segment: end_segment.map(str::to_string),
+            target_tli,
+            wal_seg_size,
+            workers: thread::available_parallelism().map_or(1, |n| n.get()),
+            blcksz: DEFAULT_BLCKSZ,
+        })
+    }
+
+    /// Number of worker threads, the number of CPUs by default
+    pub fn set_workers(&mut self, workers: usize) {
+        self.workers = workers.max(1);
+    }
+
+    /// Set the relation page size used to decode full page images
+    pub fn set_blcksz(&mut self, blcksz: u16) {
+        self.blcksz = blcksz;
+    }
+
+    /// Number of segments to scan
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// Call f on the records of each segment and return its results in
+    /// segment order. Failing to open a segment fails the whole scan.
+    pub fn scan<T, F>(&self, f: F) -> Result<Vec<T>, ReaderError>
+    where
+        T: Send,
+        F: Fn(SegmentRecords) -> T + Sync,
+    {
+        let next_segment = AtomicUsize::new(0);
+        let workers = self.workers.min(self.segments.len());
+        debug!(
+            "Scanning {} segments with {} workers",
+            self.segments.len(),
+            workers
+        );
+        let mut results: Vec<(usize, Result<T, ReaderError>)> = thread::scope(|s| {
+            let handles: Vec<_> = (0..workers)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut results = Vec::new();
+                        loop {
+                            let index = next_segment.fetch_add(1, Ordering::Relaxed);
+                            let Some(&(tli, segno)) = self.segments.get(index) else {
+                                break;
+                            };
+                            results.push((index, self.segment_records(tli, segno).map(&f)));
+                        }
+                        results
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+                .collect()
+        });
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    /// Call f on every record and return the values it kept, in LSN order
+    pub fn filter_map<T, F>(&self, f: F) -> Result<Vec<T>, ReaderError>
+    where
+        T: Send,
+        F: Fn(Result<XLogRecord, ReaderError>) -> Option<T> + Sync,
+    {
+        let results = self.scan(|records| records.filter_map(&f).collect::<Vec<_>>())?;
+        Ok(results.into_iter().flatten().collect())
+    }
+
+    /// Records starting in segment segno of timeline tli
+    fn segment_records(
+        &self,
+        tli: TimelineID,
+        segno: XLogSegNo,
+    ) -> Result<SegmentRecords, ReaderError> {
+        let name = XLogFilePos::from_segno(tli, segno, self.wal_seg_size).to_string();
+        let source: Box<dyn SegmentSource> = Box::new(self.source.clone());
+        // Reading may go on up to end_segment to complete the last record
+        let mut reader = XLogReader::from_source_on_timeline(
+            source,
+            &name,
+            self.end_segment.as_deref(),
+            self.target_tli,
+        )
+        .map_err(|e| ReaderError::Open(name.clone(), e))?;
+        reader.set_blcksz(self.blcksz);
+        Ok(SegmentRecords {
+            reader,
+            name,
+            boundary: XLogRecPtr::from_segno(segno + 1, 0, self.wal_seg_size),
+            done: false,
+        })
+    }
+}
+
+/// Iterator over the records starting in one segment, with the same
+/// semantics as XLogReader's iterator
+pub struct SegmentRecords {
+    reader: XLogReader,
+    name: String,
+    /// Start of the next segment
+    boundary: XLogRecPtr,
+    done: bool,
+}
+
+impl SegmentRecords {
+    /// Name of the segment read
+    pub fn segment_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Where and why valid WAL ended, when it ended in this segment
+    pub fn end_of_wal(&self) -> Option<&EndOfWal> {
+        self.reader.end_of_wal()
+    }
+}
+
+impl Iterator for SegmentRecords {
+    type Item = Result<XLogRecord, ReaderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The next record starts where the last one ended
+        if self.done || self.reader.end_rec_ptr() >= self.boundary {
+            return None;
+        }
+        let item = self.reader.next();
+        // Records continued over a whole segment are only seen once we're
+        // past it
+        if self.reader.read_rec_ptr() >= self.boundary {
+            self.done = true;
+            return None;
+        }
+        item
+    }
+}
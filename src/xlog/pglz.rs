@@ -0,0 +1,86 @@
+//! Decompression of PostgreSQL's LZ family format, used for full page
+//! images with wal_compression set to pglz, see pg_lzcompress.c
+//!
+//! Compressed data is a sequence of control bytes each followed by up to 8
+//! items, one per bit from the lowest. A clear bit is a literal byte, a set
+//! bit a 2 or 3 bytes tag copying bytes already output: 4 bits of length
+//! minus 3, 12 bits of offset back from the current position and, for a
+//! length nibble of 15, an extra byte added to the length.
+
+/// Decompress source into rawsize bytes. Like pglz_decompress with
+/// check_complete, fail unless source is consumed exactly and fills rawsize
+/// bytes.
+pub fn decompress(source: &[u8], rawsize: usize) -> Option<Vec<u8>> {
+    let mut dest = Vec::with_capacity(rawsize);
+    let mut sp = 0;
+    while sp < source.len() && dest.len() < rawsize {
+        let mut ctrl = source[sp];
+        sp += 1;
+        for _ in 0..8 {
+            if sp >= source.len() || dest.len() >= rawsize {
+                break;
+            }
+            if ctrl & 1 != 0 {
+                let tag = source.get(sp..sp + 2)?;
+                let mut len = usize::from(tag[0] & 0x0f) + 3;
+                let off = (usize::from(tag[0] & 0xf0) << 4) | usize::from(tag[1]);
+                sp += 2;
+                if len == 18 {
+                    len += usize::from(*source.get(sp)?);
+                    sp += 1;
+                }
+                if off == 0 || off > dest.len() {
+                    return None;
+                }
+                // The copy can overlap its own output, a short offset
+                // repeating a pattern
+                let start = dest.len() - off;
+                for pos in start..start + len.min(rawsize - dest.len()) {
+                    dest.push(dest[pos]);
+                }
+            } else {
+                dest.push(source[sp]);
+                sp += 1;
+            }
+            ctrl >>= 1;
+        }
+    }
+    (dest.len() == rawsize && sp == source.len()).then_some(dest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decompress_literals_and_tags() {
+        // "abc", then a tag copying 6 bytes from 3 back, then "d", then a
+        // long tag copying 20 bytes from 1 back
+        let source = [
+            0b0010_1000,
+            b'a',
+            b'b',
+            b'c',
+            0x03,
+            0x03,
+            b'd',
+            0x0f,
+            0x01,
+            0x02,
+        ];
+        let mut expected = b"abcabcabcd".to_vec();
+        expected.extend_from_slice(&[b'd'; 20]);
+        assert_eq!(decompress(&source, expected.len()), Some(expected));
+    }
+
+    #[test]
+    fn test_decompress_invalid() {
+        let source = [0b0000_0001, 0x01, 0x02];
+        // Offset before the start of the output
+        assert_eq!(decompress(&source, 4), None);
+        // Output shorter than expected
+        assert_eq!(decompress(&[0x00, b'a', b'b'], 3), None);
+        // Data left once the output is full
+        assert_eq!(decompress(&[0x00, b'a', b'b'], 1), None);
+    }
+}
@@ -1,118 +1,733 @@
+use std::borrow::Cow;
 use std::error::Error;
-use std::fs::File;
 use std::io;
 use std::io::Read;
-use std::os::unix::fs::MetadataExt;
+use std::mem;
+use std::ops::Range;
 use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
 
-use nom::Finish;
+use log::{debug, warn};
 
 use crate::error::XLogError;
-use crate::xlog::page::{parse_xlog_page, XLogPageContent};
-use crate::xlog::record::XLogRecord;
+use crate::xlog::block::DEFAULT_BLCKSZ;
+use crate::xlog::cursor::{self, RecordCursor, WalPages};
+use crate::xlog::lsn::{
+    is_valid_wal_seg_size, parse_filename, TimelineID, XLogRecPtr, XLogSegNo, DEFAULT_WAL_SEG_SIZE,
+};
+use crate::xlog::page::{
+    is_valid_xlog_blcksz, parse_xlog_page_header, WalVersion, XLogLongPageHeader, XLogPageHeader,
+    DEFAULT_XLOG_BLCKSZ,
+};
+use crate::xlog::record::{parse_xlog_record, XLogRecord};
+use crate::xlog::segment::SegmentFile;
+use crate::xlog::source::{ArchiveDirectory, SegmentSource, StreamSource};
+use crate::xlog::timeline::{
+    find_newest_timeline, read_timeline_history, timeline_of_segment, TimeLineHistoryEntry,
+};
+use crate::xlog::validate::{EndOfWal, EndOfWalReason, ValidationError, XLogValidator};
 
-pub type XLogRecPtr = u64;
-pub type TimelineID = u32;
+/// Records always start on a MAXALIGN boundary
+pub(crate) fn maxalign(len: usize) -> usize {
+    (len + 7) & !7
+}
 
 #[derive(Debug)]
-pub enum ReaderError<I: Sized> {
+pub enum ReaderError {
     IoError(io::Error),
-    ParseError(XLogError<I>),
+    /// Segment can't be opened to start reading it: segment name and error
+    Open(String, Box<dyn Error + Send + Sync>),
+    /// Record or page at this LSN can't be parsed: error and offending bytes
+    ParseError(XLogRecPtr, XLogError<Vec<u8>>, Vec<u8>),
+    /// Record's CRC doesn't match its content: LSN, expected and actual CRC
+    CrcMismatch(XLogRecPtr, u32, u32),
+    /// WAL stops being valid at this LSN
+    Validation(XLogRecPtr, ValidationError),
 }
 
-impl<I> From<io::Error> for ReaderError<I> {
-    fn from(item: io::Error) -> Self {
-        ReaderError::<I>::IoError(item)
+impl ReaderError {
+    /// Errors caused by stale or zeroed data after the last valid record
+    /// mark the end of WAL rather than a corruption
+    pub fn end_of_wal_reason(&self) -> Option<EndOfWalReason> {
+        match self {
+            ReaderError::Validation(_, e) if !e.is_end_of_wal() => None,
+            ReaderError::Validation(_, ValidationError::UnexpectedPageAddr(expected, found)) => {
+                Some(EndOfWalReason::PageAddrMismatch(*expected, *found))
+            }
+            ReaderError::Validation(_, ValidationError::InvalidMagic(magic)) => {
+                Some(EndOfWalReason::BadMagic(*magic))
+            }
+            ReaderError::Validation(_, ValidationError::InvalidXlPrev(expected, found)) => {
+                Some(EndOfWalReason::InvalidXlPrev(*expected, *found))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ReaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ReaderError::IoError(e) => write!(f, "IO error: {}", e),
+            ReaderError::Open(name, e) => write!(f, "Can't read {}: {}", name, e),
+            ReaderError::ParseError(lsn, e, _) => write!(f, "Parse error at {}: {}", lsn, e),
+            ReaderError::CrcMismatch(lsn, expected, actual) => write!(
+                f,
+                "Incorrect resource manager data checksum in record at {}: expected 0x{:08X}, got 0x{:08X}",
+                lsn, expected, actual
+            ),
+            ReaderError::Validation(lsn, e) => write!(f, "Invalid WAL at {}: {}", lsn, e),
+        }
     }
 }
 
-impl<I> From<XLogError<I>> for ReaderError<I> {
-    fn from(item: XLogError<I>) -> Self {
-        ReaderError::ParseError(item)
+impl Error for ReaderError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ReaderError::IoError(e) => Some(e),
+            ReaderError::Open(_, e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ReaderError {
+    fn from(item: io::Error) -> Self {
+        ReaderError::IoError(item)
     }
 }
 
 pub struct XLogReader {
-    current_rec_ptr: XLogRecPtr,
+    /// Position among records and the state of their validation
+    cursor: RecordCursor,
+    /// Timeline of the segment currently read
     current_tli: TimelineID,
+    /// History of the timeline to follow, newest first
+    timeline_history: Vec<TimeLineHistoryEntry>,
 
-    data_dir: String,
-    wal_seg_size: u64,
-    f: File,
-    buffer: [u8; 8192],
-    page: Option<XLogPageContent>,
+    /// Where segments and history files are read from
+    source: Box<dyn SegmentSource>,
+    /// Size of relation pages, needed to decode full page images
+    blcksz: u16,
+    /// Segment currently read
+    current_segno: XLogSegNo,
+    /// Last segment to read. When not set, reading stops at the first
+    /// missing segment
+    end_segno: Option<XLogSegNo>,
+    /// Read position in the current segment
+    segment_offset: u64,
+    /// Address of the page in the buffer
+    page_ptr: XLogRecPtr,
+    /// When set, wait for new WAL at the end of WAL, polling at this interval
+    follow: Option<Duration>,
+    f: SegmentFile,
+    buffer: Vec<u8>,
 }
 
-#[derive(Debug)]
-pub struct XLogFilePos {
-    pub tli: u32,
-    pub log: u32,
-    pub seg: u32,
+impl XLogReader {
+    /// Read a single WAL segment, continuing into the following segments
+    /// of the same directory when they exist.
+    pub fn new_from_filename(walsegment: PathBuf) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let data_dir = match walsegment.parent() {
+            Some(parent) => parent.to_path_buf(),
+            None => PathBuf::from("."),
+        };
+        let start_segment = walsegment.file_name().and_then(|f| f.to_str());
+        let Some(start_segment) = start_segment else {
+            return Err(format!("Invalid WAL segment path {:?}", walsegment).into());
+        };
+        Self::new(data_dir, start_segment, None)
+    }
+
+    /// Read WAL segments from data_dir, starting at start_segment and
+    /// stopping after end_segment if provided. Segments may be partial or
+    /// compressed.
+    pub fn new(
+        data_dir: PathBuf,
+        start_segment: &str,
+        end_segment: Option<&str>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let source = Box::new(ArchiveDirectory::new(data_dir));
+        Self::from_source(source, start_segment, end_segment)
+    }
+
+    /// Read WAL segments from source, starting at start_segment and stopping
+    /// after end_segment if provided. Timeline switches are followed up to
+    /// end_segment's timeline, or the newest timeline found in source.
+    pub fn from_source(
+        source: Box<dyn SegmentSource>,
+        start_segment: &str,
+        end_segment: Option<&str>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let target_tli = match end_segment {
+            Some(end_segment) => parse_filename(end_segment)?.tli,
+            None => find_newest_timeline(source.as_ref(), parse_filename(start_segment)?.tli)?,
+        };
+        Self::from_source_on_timeline(source, start_segment, end_segment, target_tli)
+    }
+
+    /// Read WAL segments from source, starting at start_segment and
+    /// following target_tli's history.
+    pub fn from_source_on_timeline(
+        source: Box<dyn SegmentSource>,
+        start_segment: &str,
+        end_segment: Option<&str>,
+        target_tli: TimelineID,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let file_pos = parse_filename(start_segment)?;
+        let timeline_history = read_timeline_history(source.as_ref(), target_tli)?;
+        let in_history = |tli| timeline_history.iter().any(|e| e.tli == tli);
+        if !in_history(file_pos.tli) {
+            return Err(format!(
+                "Start segment {} is not in the history of timeline {}",
+                start_segment, target_tli
+            )
+            .into());
+        }
+        let mut f = source.open(start_segment)?;
+        let (wal_seg_size, xlog_blcksz) = read_segment_sizes(&mut f)?;
+        let current_segno = file_pos.get_segno(wal_seg_size);
+        let end_segno = match end_segment {
+            Some(end_segment) => {
+                let end_pos = parse_filename(end_segment)?;
+                if !in_history(end_pos.tli) {
+                    return Err(format!(
+                        "End segment {} is not in the history of timeline {}",
+                        end_segment, target_tli
+                    )
+                    .into());
+                }
+                let end_segno = end_pos.get_segno(wal_seg_size);
+                if end_segno < current_segno {
+                    return Err(format!(
+                        "End segment {} is before start segment {}",
+                        end_segment, start_segment
+                    )
+                    .into());
+                }
+                Some(end_segno)
+            }
+            None => None,
+        };
+        let mut reader = Self::with_segment(
+            source,
+            timeline_history,
+            f,
+            file_pos.tli,
+            current_segno,
+            wal_seg_size,
+            xlog_blcksz,
+        );
+        reader.end_segno = end_segno;
+        Ok(reader)
+    }
+
+    /// Reader positioned at the start of segment f
+    fn with_segment(
+        source: Box<dyn SegmentSource>,
+        timeline_history: Vec<TimeLineHistoryEntry>,
+        f: SegmentFile,
+        tli: TimelineID,
+        segno: XLogSegNo,
+        wal_seg_size: u64,
+        xlog_blcksz: usize,
+    ) -> Self {
+        let segment_ptr = XLogRecPtr::from_segno(segno, 0, wal_seg_size);
+        Self {
+            cursor: RecordCursor::new(segment_ptr, wal_seg_size, xlog_blcksz),
+            current_tli: tli,
+            timeline_history,
+            source,
+            blcksz: DEFAULT_BLCKSZ,
+            current_segno: segno,
+            end_segno: None,
+            segment_offset: 0,
+            page_ptr: segment_ptr,
+            follow: None,
+            f,
+            buffer: vec![0; xlog_blcksz],
+        }
+    }
+
+    /// Read WAL pages from r, its first byte being at start. start must be
+    /// on a page boundary. The WAL page size is read from the first page
+    /// when it has a long header and is the default one otherwise.
+    pub fn from_reader<R: Read + Send + 'static>(
+        r: R,
+        start: XLogRecPtr,
+        wal_seg_size: u64,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let (page_header, r) = peek_page_header(r)?;
+        Self::from_stream(r, &page_header, start, wal_seg_size)
+    }
+
+    /// Read WAL pages held in data, its first byte being at start
+    pub fn from_bytes(
+        data: &[u8],
+        start: XLogRecPtr,
+        wal_seg_size: u64,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Self::from_reader(io::Cursor::new(data.to_vec()), start, wal_seg_size)
+    }
+
+    /// Read WAL from r starting with a segment, like a segment piped on
+    /// stdin. The start LSN, segment size and timeline are read from the
+    /// segment's long page header.
+    pub fn from_segment_reader<R: Read + Send + 'static>(
+        r: R,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let (page_header, r) = peek_page_header(r)?;
+        let XLogPageHeader::Long(long) = &page_header else {
+            return Err("Missing long page header at the start of the stream".into());
+        };
+        let start = XLogRecPtr::new(long.std.xlp_pageaddr);
+        Self::from_stream(r, &page_header, start, u64::from(long.xlp_seg_size))
+    }
+
+    fn from_stream(
+        r: Box<dyn Read + Send>,
+        page_header: &XLogPageHeader,
+        start: XLogRecPtr,
+        wal_seg_size: u64,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if !is_valid_wal_seg_size(wal_seg_size) {
+            return Err(format!("Invalid segment size {}", wal_seg_size).into());
+        }
+        let xlog_blcksz = match page_header {
+            XLogPageHeader::Long(long) => {
+                if u64::from(long.xlp_seg_size) != wal_seg_size {
+                    return Err(format!(
+                        "Stream has a segment size of {}, expected {}",
+                        long.xlp_seg_size, wal_seg_size
+                    )
+                    .into());
+                }
+                let xlog_blcksz = long.xlp_xlog_blcksz as usize;
+                if !is_valid_xlog_blcksz(xlog_blcksz) {
+                    return Err(format!("Invalid WAL block size {} in stream", xlog_blcksz).into());
+                }
+                xlog_blcksz
+            }
+            XLogPageHeader::Short(_) => DEFAULT_XLOG_BLCKSZ,
+        };
+        if !start.is_page_boundary(xlog_blcksz as u64) {
+            return Err(format!("Stream start {} is not on a page boundary", start).into());
+        }
+
+        let tli = page_header.std().xlp_tli;
+        let source = StreamSource::new(r, start, wal_seg_size);
+        let segno = start.segno(wal_seg_size);
+        let f = source.open_segment(tli, segno, wal_seg_size)?;
+        let timeline_history = read_timeline_history(&source, tli)?;
+        let mut reader = Self::with_segment(
+            Box::new(source),
+            timeline_history,
+            f,
+            tli,
+            segno,
+            wal_seg_size,
+            xlog_blcksz,
+        );
+        reader.seek(start)?;
+        Ok(reader)
+    }
+
+    /// Read WAL segments from data_dir following timeline tli's history,
+    /// starting at the first record at or after start.
+    pub fn new_from_lsn(
+        data_dir: PathBuf,
+        tli: TimelineID,
+        start: XLogRecPtr,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Self::from_source_at_lsn(Box::new(ArchiveDirectory::new(data_dir)), tli, start)
+    }
+
+    /// Read WAL segments from source following timeline tli's history,
+    /// starting at the first record at or after start. The segment size is
+    /// read from the first segment listed, sources that can't be listed are
+    /// expected to use the default segment size.
+    pub fn from_source_at_lsn(
+        source: Box<dyn SegmentSource>,
+        tli: TimelineID,
+        start: XLogRecPtr,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let wal_seg_size = match source.list()?.first() {
+            Some(name) => read_segment_sizes(&mut source.open(name)?)?.0,
+            None => DEFAULT_WAL_SEG_SIZE,
+        };
+        let timeline_history = read_timeline_history(source.as_ref(), tli)?;
+        let start_tli =
+            timeline_of_segment(&timeline_history, start.segno(wal_seg_size), wal_seg_size)
+                .unwrap_or(tli);
+        let start_segment = start.file_name(start_tli, wal_seg_size);
+        let mut reader = Self::from_source_on_timeline(source, &start_segment, None, tli)?;
+        if reader.wal_seg_size() != wal_seg_size {
+            return Err(format!(
+                "Segment {} has a segment size of {}, expected {}",
+                start_segment,
+                reader.wal_seg_size(),
+                wal_seg_size
+            )
+            .into());
+        }
+        reader.seek(start)?;
+        Ok(reader)
+    }
+
+    /// Stop reading at the first record ending after end
+    pub fn set_end_lsn(&mut self, end: XLogRecPtr) {
+        self.cursor.end_lsn = Some(end);
+    }
+
+    /// Set the relation page size used to decode full page images, for
+    /// clusters built with a non-default --with-blocksize
+    pub fn set_blcksz(&mut self, blcksz: u16) {
+        self.blcksz = blcksz;
+    }
+
+    /// Segment size read from the first segment's long page header
+    pub fn wal_seg_size(&self) -> u64 {
+        self.cursor.wal_seg_size
+    }
+
+    /// WAL page size read from the first segment's long page header
+    pub fn xlog_blcksz(&self) -> usize {
+        self.cursor.xlog_blcksz
+    }
+
+    /// When set, iterating over records skips records with an invalid CRC
+    /// instead of stopping at the first one
+    pub fn set_continue_on_crc_error(&mut self, continue_on_crc_error: bool) {
+        self.cursor.continue_on_crc_error = continue_on_crc_error;
+    }
+
+    /// Tail WAL being written: instead of stopping at the end of WAL, wait
+    /// for the next record to be written, checking every poll_interval.
+    /// Reading goes on in the next segment as soon as it's created. Only an
+    /// end LSN stops the reader.
+    pub fn set_follow(&mut self, poll_interval: Option<Duration>) {
+        self.follow = poll_interval;
+    }
+
+    /// Move the reader to the first record starting at or after lsn. As lsn
+    /// may point in the middle of a record, the page containing it is read
+    /// from its start to find the next record boundary.
+    pub fn seek(&mut self, lsn: XLogRecPtr) -> Result<(), ReaderError> {
+        cursor::seek(self, lsn)
+    }
+
+    /// Start LSN of the last record read
+    pub fn read_rec_ptr(&self) -> XLogRecPtr {
+        self.cursor.read_rec_ptr
+    }
+
+    /// End LSN of the last record read, this is where the next record starts
+    pub fn end_rec_ptr(&self) -> XLogRecPtr {
+        self.cursor.end_rec_ptr
+    }
+
+    /// Where and why valid WAL ended, set when the last read returned no
+    /// record or failed on stale data
+    pub fn end_of_wal(&self) -> Option<&EndOfWal> {
+        self.cursor.end_of_wal.as_ref()
+    }
+
+    /// Timeline of the segment currently read
+    pub fn current_tli(&self) -> TimelineID {
+        self.current_tli
+    }
+
+    /// Timeline holding segno according to the followed timeline's history
+    fn segment_tli(&self, segno: XLogSegNo) -> TimelineID {
+        timeline_of_segment(&self.timeline_history, segno, self.cursor.wal_seg_size)
+            .unwrap_or(self.current_tli)
+    }
+
+    /// Switch to the next segment. Returns false when there's no segment
+    /// left to read.
+    fn open_next_segment(&mut self) -> Result<bool, ReaderError> {
+        let segno = self.current_segno + 1;
+        if self.end_segno.is_some_and(|end_segno| segno > end_segno) {
+            return Ok(false);
+        }
+        // Switch to the next timeline when the segment holds its switch point
+        let tli = self.segment_tli(segno);
+        debug!("Opening next segment {} of timeline {}", segno, tli);
+        self.f = match self
+            .source
+            .open_segment(tli, segno, self.cursor.wal_seg_size)
+        {
+            Ok(f) => f,
+            // Without an explicit end segment, a missing segment is the end of WAL
+            Err(e) if e.kind() == io::ErrorKind::NotFound && self.end_segno.is_none() => {
+                return Ok(false)
+            }
+            Err(e) => return Err(e.into()),
+        };
+        self.current_tli = tli;
+        self.current_segno = segno;
+        self.segment_offset = 0;
+        Ok(true)
+    }
+
+    /// Read the bytes of the next record, reassembling the record's fragments
+    /// when it crosses page boundaries. Returns None when the end of WAL is
+    /// reached, end_of_wal() then tells where and why. The record's CRC is
+    /// checked, a mismatch is reported as an error after moving past the
+    /// record so reading can go on.
+    pub fn read_record_bytes(&mut self) -> Result<Option<Vec<u8>>, ReaderError> {
+        loop {
+            let res = cursor::read_record(self).map(|r| r.map(Cow::into_owned));
+            let Some(poll_interval) = self.follow else {
+                return res;
+            };
+            // The end of WAL is where the server is writing: zeroes, pages or
+            // segments not written yet, or the last record partially written,
+            // in which case it's read again. Anything else is reported.
+            let next_rec_ptr = match &res {
+                Err(ReaderError::ParseError(lsn, XLogError::Eof, _)) => *lsn,
+                _ => match &self.cursor.end_of_wal {
+                    Some(EndOfWal {
+                        lsn,
+                        reason:
+                            EndOfWalReason::ZeroLength
+                            | EndOfWalReason::PageAddrMismatch(..)
+                            | EndOfWalReason::BadMagic(_)
+                            | EndOfWalReason::NoMoreSegment
+                            | EndOfWalReason::CrcMismatch(..),
+                    }) => *lsn,
+                    _ => return res,
+                },
+            };
+            debug!("Waiting for WAL at {}", next_rec_ptr);
+            self.wait_for_wal(next_rec_ptr, poll_interval)?;
+        }
+    }
+
+    /// Wait for lsn to be readable, going back to it every poll_interval.
+    /// The record read at lsn must still point to the last record read.
+    fn wait_for_wal(
+        &mut self,
+        lsn: XLogRecPtr,
+        poll_interval: Duration,
+    ) -> Result<(), ReaderError> {
+        let prev_rec_ptr = self.cursor.validator.prev_rec_ptr();
+        loop {
+            thread::sleep(poll_interval);
+            match self.seek(lsn) {
+                Ok(()) => {
+                    self.cursor.validator.set_prev_rec_ptr(prev_rec_ptr);
+                    return Ok(());
+                }
+                // The segment isn't created yet
+                Err(ReaderError::IoError(e)) if e.kind() == io::ErrorKind::NotFound => {}
+                // The page holding lsn isn't written yet
+                Err(e) if e.end_of_wal_reason().is_some() => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
 }
 
-impl XLogFilePos {
-    pub fn get_xlog_rec_ptr(&self, wal_seg_size: u64) -> XLogRecPtr {
-        u64::from(self.log) * wal_seg_size + u64::from(self.seg)
+impl WalPages<'static> for XLogReader {
+    fn cursor(&mut self) -> &mut RecordCursor {
+        &mut self.cursor
+    }
+
+    /// Read the next page in the buffer and parse its header, moving to the
+    /// next segment when the current one is fully read
+    fn next_page(&mut self) -> Result<Option<XLogPageHeader>, ReaderError> {
+        let wal_seg_size = self.cursor.wal_seg_size;
+        if self.segment_offset >= wal_seg_size && !self.open_next_segment()? {
+            return Ok(None);
+        }
+        match self.f.read_exact(&mut self.buffer) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        self.page_ptr =
+            XLogRecPtr::from_segno(self.current_segno, self.segment_offset, wal_seg_size);
+        self.segment_offset += self.cursor.xlog_blcksz as u64;
+        let page_header = check_page(
+            &mut self.cursor.validator,
+            &self.buffer,
+            self.page_ptr,
+            wal_seg_size,
+        )?;
+        Ok(Some(page_header))
+    }
+
+    fn page(&self) -> (XLogRecPtr, &[u8]) {
+        (self.page_ptr, &self.buffer)
+    }
+
+    /// The buffer is reused for the next page, records are copied
+    fn record_in_page(&self, range: Range<usize>) -> Cow<'static, [u8]> {
+        Cow::Owned(self.buffer[range].to_vec())
+    }
+
+    fn seek_page(&mut self, page_ptr: XLogRecPtr) -> Result<(), ReaderError> {
+        let wal_seg_size = self.cursor.wal_seg_size;
+        let segno = page_ptr.segno(wal_seg_size);
+        if segno != self.current_segno {
+            let tli = self.segment_tli(segno);
+            self.f = self.source.open_segment(tli, segno, wal_seg_size)?;
+            self.current_tli = tli;
+            self.current_segno = segno;
+        }
+        self.segment_offset = page_ptr.segment_offset(wal_seg_size);
+        self.f.seek_to(self.segment_offset)?;
+        Ok(())
+    }
+
+    fn skip_segment(&mut self) {
+        self.segment_offset = self.cursor.wal_seg_size;
     }
 }
 
-pub fn parse_filename(fname: &str) -> Result<XLogFilePos, std::num::ParseIntError> {
-    let tli = u32::from_str_radix(&fname[0..8], 16)?;
-    let log = u32::from_str_radix(&fname[8..16], 16)?;
-    let seg = u32::from_str_radix(&fname[16..24], 16)?;
-    Ok(XLogFilePos { tli, log, seg })
+/// Parse and validate the header of the page at page_ptr
+pub(crate) fn check_page(
+    validator: &mut XLogValidator,
+    page: &[u8],
+    page_ptr: XLogRecPtr,
+    wal_seg_size: u64,
+) -> Result<XLogPageHeader, ReaderError> {
+    let magic = u16::from_le_bytes([page[0], page[1]]);
+    if WalVersion::from_magic(magic).is_none() {
+        return Err(ReaderError::Validation(
+            page_ptr,
+            ValidationError::InvalidMagic(magic),
+        ));
+    }
+    let page_header = match parse_xlog_page_header(page) {
+        Ok((_, page_header)) => page_header,
+        Err(_) => {
+            return Err(ReaderError::ParseError(
+                page_ptr,
+                XLogError::InvalidPageHeader,
+                Vec::new(),
+            ))
+        }
+    };
+    if let Err(e) = validator.check_page_header(&page_header, page_ptr, wal_seg_size, page.len()) {
+        return Err(ReaderError::Validation(page_ptr, e));
+    }
+    Ok(page_header)
 }
 
-impl XLogReader {
-    pub fn new_from_filename(walsegment: PathBuf) -> Result<Self, Box<dyn Error>> {
-        let data_dir = String::from(walsegment.parent().unwrap().to_str().unwrap());
-        let file_pos = parse_filename(walsegment.file_name().unwrap().to_str().unwrap())?;
-        let f = File::open(walsegment)?;
-        let metadata = f.metadata()?;
-        let wal_seg_size = metadata.size();
-        let current_rec_ptr = file_pos.get_xlog_rec_ptr(wal_seg_size);
-        let buffer = [0; 8192];
-        let page = None;
-
-        Ok(Self {
-            current_rec_ptr,
-            current_tli: file_pos.tli,
-            data_dir,
-            wal_seg_size,
-            f,
-            buffer,
-            page,
-        })
+/// Error of a record that couldn't be decoded, owning the offending input
+pub(crate) fn decode_error(e: nom::Err<XLogError<&[u8]>>) -> XLogError<Vec<u8>> {
+    match e {
+        nom::Err::Error(e) | nom::Err::Failure(e) => e.into_owned(),
+        nom::Err::Incomplete(needed) => {
+            XLogError::InvalidRecord(format!("incomplete record, {:?}", needed))
+        }
     }
+}
+
+/// Read the header of r's first page, returning it with a reader still
+/// starting at the first byte
+fn peek_page_header<R: Read + Send + 'static>(
+    mut r: R,
+) -> Result<(XLogPageHeader, Box<dyn Read + Send>), Box<dyn Error + Send + Sync>> {
+    let mut header = vec![0; mem::size_of::<XLogLongPageHeader>()];
+    r.read_exact(&mut header)?;
+    let page_header = match parse_xlog_page_header(&header) {
+        Ok((_, page_header)) if page_header.version().is_some() => page_header,
+        _ => return Err("Missing page header at the start of the stream".into()),
+    };
+    Ok((page_header, Box::new(io::Cursor::new(header).chain(r))))
+}
 
-    fn xlog_ptr_to_walfile(&self, xlrp: XLogRecPtr) -> String {
-        let log = xlrp / self.wal_seg_size;
-        let seg = xlrp % self.wal_seg_size;
-        let wal_filename = format!("{}{}{}", self.current_tli, log, seg);
-        format!("{}/pg_wal/{}", self.data_dir, wal_filename)
+/// Read the segment size and WAL page size from the long page header
+/// starting the segment, and check them against the file's size when it
+/// isn't compressed
+pub(crate) fn read_segment_sizes(
+    f: &mut SegmentFile,
+) -> Result<(u64, usize), Box<dyn Error + Send + Sync>> {
+    let mut header = [0; mem::size_of::<XLogLongPageHeader>()];
+    f.read_exact(&mut header)?;
+    f.seek_to(0)?;
+    let (wal_seg_size, xlog_blcksz) = parse_segment_sizes(&header, f.name())?;
+    // Compressed segments can't be checked without decompressing them
+    if let Some(file_size) = f.raw_size()? {
+        check_segment_file_size(f.name(), file_size, wal_seg_size)?;
     }
+    Ok((wal_seg_size, xlog_blcksz))
+}
+
+/// Segment size and WAL page size from the long page header starting
+/// segment name
+pub(crate) fn parse_segment_sizes(
+    header: &[u8],
+    name: &str,
+) -> Result<(u64, usize), Box<dyn Error + Send + Sync>> {
+    let long = match parse_xlog_page_header(header) {
+        Ok((_, XLogPageHeader::Long(long))) => long,
+        _ => return Err(format!("Missing long page header at the start of {}", name).into()),
+    };
 
-    pub fn read_next_page(&mut self) -> Result<XLogPageContent, ReaderError<&[u8]>> {
-        self.f.read_exact(&mut self.buffer)?;
-        let (_i, r) = parse_xlog_page(&self.buffer).finish()?;
-        Ok(r)
+    let wal_seg_size = u64::from(long.xlp_seg_size);
+    if !is_valid_wal_seg_size(wal_seg_size) {
+        return Err(format!("Invalid segment size {} in {}", wal_seg_size, name).into());
     }
+    let xlog_blcksz = long.xlp_xlog_blcksz as usize;
+    if !is_valid_xlog_blcksz(xlog_blcksz) {
+        return Err(format!("Invalid WAL block size {} in {}", xlog_blcksz, name).into());
+    }
+    Ok((wal_seg_size, xlog_blcksz))
+}
 
-    pub fn pop_record(&mut self) -> Option<XLogRecord> {
-        self.page.as_mut().and_then(|p| p.records.pop())
+/// Check an uncompressed segment file isn't larger than its segment size
+pub(crate) fn check_segment_file_size(
+    name: &str,
+    file_size: u64,
+    wal_seg_size: u64,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if file_size > wal_seg_size {
+        return Err(format!(
+            "{} is {} bytes, larger than its segment size {}",
+            name, file_size, wal_seg_size
+        )
+        .into());
+    }
+    if file_size < wal_seg_size {
+        // Truncated copy, only the pages present are read
+        warn!(
+            "{} is {} bytes, smaller than its segment size {}",
+            name, file_size, wal_seg_size
+        );
     }
+    Ok(())
 }
 
 impl Iterator for XLogReader {
-    type Item = XLogRecord;
+    type Item = Result<XLogRecord, ReaderError>;
 
+    /// Records with an invalid CRC or that can't be decoded are returned as
+    /// errors, iterating can go on after them. Any other error ends the
+    /// iteration, as does reaching the end of WAL, end_of_wal() then tells
+    /// where and why.
     fn next(&mut self) -> Option<Self::Item> {
-        if self.page.as_ref().map_or(0, |p| p.records.len()) == 0 {
-            match self.read_next_page() {
-                Ok(page) => self.page = Some(page),
-                Err(_) => return None,
-            };
+        let read = |reader: &mut Self| reader.read_record_bytes().map(|r| r.map(Cow::Owned));
+        let record_bytes = match cursor::next_record_bytes(self, read)? {
+            Ok(record_bytes) => record_bytes.into_owned(),
+            Err(e) => return Some(Err(e)),
+        };
+        let cursor = &self.cursor;
+        match parse_xlog_record(self.blcksz, cursor.validator.version(), &record_bytes) {
+            Ok((_, mut record)) => {
+                record.lsn = cursor.read_rec_ptr;
+                record.end_lsn = cursor.end_rec_ptr;
+                Some(Ok(record))
+            }
+            Err(e) => Some(Err(ReaderError::ParseError(
+                cursor.read_rec_ptr,
+                decode_error(e),
+                record_bytes,
+            ))),
         }
-        self.pop_record()
     }
 }
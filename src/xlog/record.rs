@@ -1,7 +1,11 @@
+use std::borrow::Cow;
 use std::mem;
 
 use crate::error::XLogError;
-use crate::xlog::block::{parse_blocks, XLBData};
+use crate::xlog::block::{parse_blocks_ref, XLBData};
+use crate::xlog::common::{RepOriginId, TransactionId};
+use crate::xlog::lsn::XLogRecPtr;
+use crate::xlog::page::WalVersion;
 use log::debug;
 use nom::bytes::complete::take;
 use nom::multi;
@@ -9,9 +13,32 @@ use nom::number::complete::{le_u32, le_u64, le_u8};
 use nom::IResult;
 use nom::Parser;
 
+use super::operation::database::{parse_database_operation, DatabaseOperation};
 use super::operation::heap::{parse_heap_operation, HeapOperation};
-
-const XLOG_RECORD_HEADER_SIZE: usize = mem::size_of::<XLogRecordHeader>();
+use super::operation::standby::{parse_standby_operation, StandbyOperation};
+use super::operation::storage::{parse_storage_operation, StorageOperation};
+use super::operation::tablespace::{parse_tablespace_operation, TablespaceOperation};
+use super::operation::xact::{parse_xact_operation, TransactionOperation};
+use super::operation::xlog::{parse_xlog_operation, XlogOperation};
+
+pub const XLOG_RECORD_HEADER_SIZE: usize = mem::size_of::<XLogRecordHeader>();
+/// xl_crc is the last field of the record header
+pub const XLOG_RECORD_CRC_OFFSET: usize = XLOG_RECORD_HEADER_SIZE - 4;
+/// Largest record PostgreSQL writes, XLogRecordMaxSize. A longer
+/// xl_tot_len comes from garbage and is never allocated.
+pub const XLOG_RECORD_MAX_SIZE: usize = 1020 * 1024 * 1024;
+
+/// xl_info of the XLOG record switching to a new segment, the rest of the
+/// segment after it is unused
+pub const XLOG_SWITCH: u8 = 0x40;
+
+/// Check if the record's bytes hold an XLOG_SWITCH record
+pub fn is_switch_record(record: &[u8]) -> bool {
+    // xl_info and xl_rmid follow xl_tot_len, xl_xid and xl_prev
+    record.len() >= XLOG_RECORD_HEADER_SIZE
+        && RmgrId::try_from(record[17]) == Ok(RmgrId::Xlog)
+        && record[16] & 0xf0 == XLOG_SWITCH
+}
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum RmgrId {
@@ -103,15 +130,15 @@ impl std::fmt::Display for RmgrId {
 
 #[derive(Clone, Debug)]
 pub enum Operation {
-    Xlog,
-    Transaction,
-    Storage,
+    Xlog(XlogOperation),
+    Transaction(TransactionOperation),
+    Storage(StorageOperation),
     Clog,
-    Database,
-    Tablespace,
+    Database(DatabaseOperation),
+    Tablespace(TablespaceOperation),
     MultiXact,
     RelMap,
-    Standby,
+    Standby(StandbyOperation),
     Heap2,
     Heap(HeapOperation),
     Btree,
@@ -127,13 +154,71 @@ pub enum Operation {
     LogicalMsg,
 }
 
+impl std::fmt::Display for Operation {
+    /// Description of the decoded operation, empty for resource managers
+    /// that aren't decoded yet
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Operation::Xlog(o) => write!(f, "{}", o),
+            Operation::Transaction(o) => write!(f, "{}", o),
+            Operation::Storage(o) => write!(f, "{}", o),
+            Operation::Database(o) => write!(f, "{}", o),
+            Operation::Tablespace(o) => write!(f, "{}", o),
+            Operation::Standby(o) => write!(f, "{}", o),
+            Operation::Heap(o) => write!(f, "{}", o),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A decoded record. Block data and images are owned by default, B is a
+/// slice or a Cow for records borrowing the bytes they were decoded from.
 #[derive(Clone, Debug)]
-pub struct XLogRecord {
+pub struct XLogRecord<B = Vec<u8>> {
+    /// Start of the record, set by the reader
+    pub lsn: XLogRecPtr,
+    /// End of the record, set by the reader
+    pub end_lsn: XLogRecPtr,
     pub header: XLogRecordHeader,
-    pub blocks: Vec<XLBData>,
+    pub blocks: Vec<XLBData<B>>,
+    /// Replication origin of a change replayed by logical replication
+    pub record_origin: Option<RepOriginId>,
+    /// Top-level transaction of a subtransaction, set in the first record
+    /// the subtransaction writes
+    pub toplevel_xid: Option<TransactionId>,
     pub operation: Operation,
 }
 
+/// A record borrowing its block data and images from the WAL it was read
+/// from when possible, see MappedWal
+pub type XLogRecordRef<'a> = XLogRecord<Cow<'a, [u8]>>;
+
+impl<B> XLogRecord<B> {
+    /// Convert the bytes of the record's blocks with f
+    pub fn map_bytes<C>(self, f: impl Fn(B) -> C) -> XLogRecord<C> {
+        XLogRecord {
+            lsn: self.lsn,
+            end_lsn: self.end_lsn,
+            header: self.header,
+            blocks: self
+                .blocks
+                .into_iter()
+                .map(|block| block.map_bytes(&f))
+                .collect(),
+            record_origin: self.record_origin,
+            toplevel_xid: self.toplevel_xid,
+            operation: self.operation,
+        }
+    }
+}
+
+impl XLogRecordRef<'_> {
+    /// Copy the borrowed bytes to get an owned record
+    pub fn into_owned(self) -> XLogRecord {
+        self.map_bytes(Cow::into_owned)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct XLogRecordHeader {
     /// Total length of the record
@@ -141,7 +226,7 @@ pub struct XLogRecordHeader {
     /// Transaction ID
     pub xl_xid: u32,
     /// Pointer to previous record (LSN)
-    pub xl_prev: u64,
+    pub xl_prev: XLogRecPtr,
 
     // Info Mask
     pub special_rel_update: bool,
@@ -158,15 +243,30 @@ impl std::fmt::Display for XLogRecordHeader {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(
             f,
-            "rmgr: {}, len: {}, tx: {}, prev: 0x{:08X}",
+            "rmgr: {}, len: {}, tx: {}, prev: {}",
             self.xl_rmid, self.xl_tot_len, self.xl_xid, self.xl_prev
         )
     }
 }
 
-impl std::fmt::Display for XLogRecord {
+impl<B> std::fmt::Display for XLogRecord<B> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        writeln!(f, "{}", self.header)?;
+        write!(
+            f,
+            "lsn: {}, end_lsn: {}, {}",
+            self.lsn, self.end_lsn, self.header
+        )?;
+        if let Some(toplevel_xid) = self.toplevel_xid {
+            write!(f, ", toplevel_xid: {}", toplevel_xid)?;
+        }
+        if let Some(record_origin) = self.record_origin {
+            write!(f, ", origin: {}", record_origin)?;
+        }
+        writeln!(f)?;
+        let desc = self.operation.to_string();
+        if !desc.is_empty() {
+            writeln!(f, " desc: {}", desc)?;
+        }
         for block in &self.blocks {
             writeln!(f, " {}", block)?;
         }
@@ -191,6 +291,13 @@ pub fn consume_padding(i: &[u8], size: usize) -> IResult<&[u8], (), XLogError<&[
     Ok((i, ()))
 }
 
+/// Compute the CRC-32C of a complete record the way PostgreSQL does: the
+/// record's payload first, then the header fields preceding xl_crc.
+pub fn compute_record_crc(record: &[u8]) -> u32 {
+    let crc = crc32c::crc32c(&record[XLOG_RECORD_HEADER_SIZE..]);
+    crc32c::crc32c_append(crc, &record[..XLOG_RECORD_CRC_OFFSET])
+}
+
 fn parse_xlog_record_header(i: &[u8]) -> IResult<&[u8], XLogRecordHeader, XLogError<&[u8]>> {
     let header_size = XLOG_RECORD_HEADER_SIZE;
     if i.len() < header_size {
@@ -205,7 +312,7 @@ fn parse_xlog_record_header(i: &[u8]) -> IResult<&[u8], XLogRecordHeader, XLogEr
         return Err(nom::Err::Error(XLogError::EmptyRecord));
     }
     let (i, xl_xid) = le_u32(i)?;
-    let (i, xl_prev) = le_u64(i)?;
+    let (i, xl_prev) = le_u64(i).map(|(i, x)| (i, XLogRecPtr::from(x)))?;
     let (i, xl_info) = le_u8(i)?;
     // First 4 bits of xl_info is used by rmgr
     let rmgr_info = xl_info & 0xf0;
@@ -240,38 +347,63 @@ fn parse_xlog_record_header(i: &[u8]) -> IResult<&[u8], XLogRecordHeader, XLogEr
     Ok((i, record))
 }
 
-/// Parse record header, block headers and block contents
-pub fn parse_xlog_record(i: &[u8]) -> IResult<&[u8], XLogRecord, XLogError<&[u8]>> {
+/// Parse record header, block headers and block contents. blcksz is the
+/// relation page size, needed to decode full page images, and version the
+/// version of the server that wrote the record.
+pub fn parse_xlog_record(
+    blcksz: u16,
+    version: WalVersion,
+    i: &[u8],
+) -> IResult<&[u8], XLogRecord, XLogError<&[u8]>> {
+    let (i, record) = parse_xlog_record_slices(blcksz, version, i)?;
+    Ok((i, record.map_bytes(<[u8]>::to_vec)))
+}
+
+/// Parse a record without copying its block data and images
+pub fn parse_xlog_record_ref(
+    blcksz: u16,
+    version: WalVersion,
+    i: &[u8],
+) -> IResult<&[u8], XLogRecordRef<'_>, XLogError<&[u8]>> {
+    let (i, record) = parse_xlog_record_slices(blcksz, version, i)?;
+    Ok((i, record.map_bytes(Cow::Borrowed)))
+}
+
+fn parse_xlog_record_slices(
+    blcksz: u16,
+    version: WalVersion,
+    i: &[u8],
+) -> IResult<&[u8], XLogRecord<&[u8]>, XLogError<&[u8]>> {
     let (i, header) = parse_xlog_record_header(i)?;
 
     // Create a subslice with block headers and data
     let record_length = header.xl_tot_len as usize - XLOG_RECORD_HEADER_SIZE;
     let block_bytes = &i[..record_length];
-    let (_, (main_block_start, blocks)) = parse_blocks(block_bytes)?;
+    let (_, (main_data, blocks, block_headers)) = parse_blocks_ref(blcksz, version, block_bytes)?;
 
     let (_, operation) = match header.xl_rmid {
-        RmgrId::Xlog => (main_block_start, Operation::Xlog),
-        RmgrId::Transaction => (main_block_start, Operation::Transaction),
-        RmgrId::Storage => (main_block_start, Operation::Storage),
-        RmgrId::Clog => (main_block_start, Operation::Clog),
-        RmgrId::Database => (main_block_start, Operation::Database),
-        RmgrId::Tablespace => (main_block_start, Operation::Tablespace),
-        RmgrId::MultiXact => (main_block_start, Operation::MultiXact),
-        RmgrId::RelMap => (main_block_start, Operation::RelMap),
-        RmgrId::Standby => (main_block_start, Operation::Standby),
-        RmgrId::Heap => parse_heap_operation(header.rmgr_info, main_block_start)?,
-        RmgrId::Heap2 => (main_block_start, Operation::Heap2),
-        RmgrId::Btree => (main_block_start, Operation::Btree),
-        RmgrId::Hash => (main_block_start, Operation::Hash),
-        RmgrId::Gin => (main_block_start, Operation::Gin),
-        RmgrId::Gist => (main_block_start, Operation::Gist),
-        RmgrId::Sequence => (main_block_start, Operation::Sequence),
-        RmgrId::Spgist => (main_block_start, Operation::Spgist),
-        RmgrId::Brin => (main_block_start, Operation::Brin),
-        RmgrId::CommitTs => (main_block_start, Operation::CommitTs),
-        RmgrId::ReplicationOrigin => (main_block_start, Operation::ReplicationOrigin),
-        RmgrId::Generic => (main_block_start, Operation::Generic),
-        RmgrId::LogicalMsg => (main_block_start, Operation::LogicalMsg),
+        RmgrId::Xlog => parse_xlog_operation(header.rmgr_info, version, main_data)?,
+        RmgrId::Transaction => parse_xact_operation(header.rmgr_info, version, main_data)?,
+        RmgrId::Storage => parse_storage_operation(header.rmgr_info, main_data)?,
+        RmgrId::Clog => (main_data, Operation::Clog),
+        RmgrId::Database => parse_database_operation(header.rmgr_info, version, main_data)?,
+        RmgrId::Tablespace => parse_tablespace_operation(header.rmgr_info, main_data)?,
+        RmgrId::MultiXact => (main_data, Operation::MultiXact),
+        RmgrId::RelMap => (main_data, Operation::RelMap),
+        RmgrId::Standby => parse_standby_operation(header.rmgr_info, main_data)?,
+        RmgrId::Heap => parse_heap_operation(header.rmgr_info, main_data)?,
+        RmgrId::Heap2 => (main_data, Operation::Heap2),
+        RmgrId::Btree => (main_data, Operation::Btree),
+        RmgrId::Hash => (main_data, Operation::Hash),
+        RmgrId::Gin => (main_data, Operation::Gin),
+        RmgrId::Gist => (main_data, Operation::Gist),
+        RmgrId::Sequence => (main_data, Operation::Sequence),
+        RmgrId::Spgist => (main_data, Operation::Spgist),
+        RmgrId::Brin => (main_data, Operation::Brin),
+        RmgrId::CommitTs => (main_data, Operation::CommitTs),
+        RmgrId::ReplicationOrigin => (main_data, Operation::ReplicationOrigin),
+        RmgrId::Generic => (main_data, Operation::Generic),
+        RmgrId::LogicalMsg => (main_data, Operation::LogicalMsg),
     };
 
     // Padding needs to be consumed
@@ -280,15 +412,23 @@ pub fn parse_xlog_record(i: &[u8]) -> IResult<&[u8], XLogRecord, XLogError<&[u8]
     Ok((
         i,
         XLogRecord {
+            lsn: XLogRecPtr::INVALID,
+            end_lsn: XLogRecPtr::INVALID,
             header,
             blocks,
+            record_origin: block_headers.record_origin,
+            toplevel_xid: block_headers.toplevel_xid,
             operation,
         },
     ))
 }
 
-pub fn parse_xlog_records(i: &[u8]) -> IResult<&[u8], Vec<XLogRecord>, XLogError<&[u8]>> {
-    multi::many1(parse_xlog_record).parse(i)
+pub fn parse_xlog_records(
+    blcksz: u16,
+    version: WalVersion,
+    i: &[u8],
+) -> IResult<&[u8], Vec<XLogRecord>, XLogError<&[u8]>> {
+    multi::many1(|i| parse_xlog_record(blcksz, version, i)).parse(i)
 }
 
 #[cfg(test)]
@@ -296,6 +436,7 @@ mod tests {
     use nom::error::dbg_dmp;
 
     use super::*;
+    use crate::xlog::block::DEFAULT_BLCKSZ;
 
     #[cfg(test)]
     #[ctor::ctor]
@@ -326,7 +467,10 @@ mod tests {
         //  \x00\x00\x00\x00\x00\x00\x00\x00\x00\x48\xee\x0a\xea\x02\x00\x00\xea\x02\x00\x00\xe9\x02\x00\x00
         // rmgr: Standby     len (rec/tot):     50/    50, tx:          0, lsn: 0/04000028, prev 0/03004A00, desc: RUNNING_XACTS nextXid 746 latestCompletedXid 745 oldestRunningXid 746
         let input = b"\x32\x00\x00\x00\x00\x00\x00\x00\x00\x4a\x00\x03\x00\x00\x00\x00\x10\x08\x00\x00\xed\x8b\xfc\x2d\xff\x18\x00\x00\x00\x00\x00\x00\x00\x00\x00\x48\xee\x0a\xea\x02\x00\x00\xea\x02\x00\x00\xe9\x02\x00\x00\x00\x00\x00\x00\x00\x00";
-        let res = dbg_dmp(parse_xlog_record, "record")(input);
+        let res = dbg_dmp(
+            |i| parse_xlog_record(DEFAULT_BLCKSZ, WalVersion::default(), i),
+            "record",
+        )(input);
         assert!(res.is_ok(), "{:x?}", res);
 
         let (i, record) = res.unwrap();
@@ -341,12 +485,53 @@ mod tests {
         assert_eq!(block.data.as_ref().unwrap().len(), 0x18);
 
         assert!(i.is_empty(), "{:x?}", i);
+
+        let Operation::Standby(StandbyOperation::RunningXacts(running_xacts)) = &record.operation
+        else {
+            panic!("Unexpected operation {:?}", record.operation)
+        };
+        assert_eq!(running_xacts.next_xid, 746);
+        assert_eq!(running_xacts.oldest_running_xid, 746);
+        assert_eq!(running_xacts.latest_completed_xid, 745);
+        assert!(running_xacts.xids.is_empty());
+        assert!(running_xacts.subxids.is_empty());
+        assert!(!running_xacts.subxid_overflow);
+        assert_eq!(
+            record.operation.to_string(),
+            "RUNNING_XACTS nextXid 746 latestCompletedXid 745 oldestRunningXid 746"
+        );
+    }
+
+    #[test]
+    fn test_parse_subtransaction_record() {
+        // Heap INSERT written by subtransaction 750 of transaction 748,
+        // replayed from replication origin 1
+        let input = b"\x25\x00\x00\x00\xee\x02\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x0a\x00\x00\x00\x00\x00\x00\xfd\x01\x00\xfc\xec\x02\x00\x00\xff\x03\x01\x00\x08";
+        let (i, record) = parse_xlog_record(DEFAULT_BLCKSZ, WalVersion::default(), input).unwrap();
+        assert!(i.is_empty(), "{:x?}", i);
+        assert_eq!(record.header.xl_xid, 750);
+        assert_eq!(record.toplevel_xid, Some(748));
+        assert_eq!(record.record_origin, Some(1));
+        let Operation::Heap(HeapOperation::Insert(insert)) = record.operation else {
+            panic!("Unexpected operation")
+        };
+        assert_eq!(insert.offnum, 1);
+    }
+
+    #[test]
+    fn test_compute_record_crc() {
+        // RUNNING_XACTS record from test_parse_standby, xl_crc is 0x2dfc8bed
+        let input = b"\x32\x00\x00\x00\x00\x00\x00\x00\x00\x4a\x00\x03\x00\x00\x00\x00\x10\x08\x00\x00\xed\x8b\xfc\x2d\xff\x18\x00\x00\x00\x00\x00\x00\x00\x00\x00\x48\xee\x0a\xea\x02\x00\x00\xea\x02\x00\x00\xe9\x02\x00\x00";
+        assert_eq!(compute_record_crc(input), 0x2dfc8bed);
     }
 
     #[test]
     fn test_parse_fpw() {
         let input = b"\xe8\x00\x00\x00\xec\x02\x00\x00\x00\x01\x60\x01\x00\x00\x00\x00\x00\x0a\x00\x00\x7e\x34\x63\xfd\x00\x30\x0a\x00\xa8\x00\x28\x00\x05\x7f\x06\x00\x00\xb0\x32\x00\x00\x16\x40\x00\x00\x00\x00\x00\x00\xff\x03\x00\x00\x00\x00\x68\x00\x60\x01\x00\x00\x00\x00\x28\x00\x80\x1f\x00\x20\x04\x20\x00\x00\x00\x00\xe0\x9f\x38\x00\xc0\x9f\x38\x00\xa0\x9f\x38\x00\x80\x9f\x38\x00\xec\x02\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x04\x00\x04\x00\x01\x08\x18\x01\x01\x00\x00\x00\x00\x00\x00\x00\xeb\x02\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x03\x00\x04\x00\x01\x08\x18\x01\x01\x00\x00\x00\x00\x00\x00\x00\xea\x02\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x02\x00\x04\x00\x01\x08\x18\x01\x01\x00\x00\x00\x00\x00\x00\x00\xe8\x02\x00\x00\x00\x00\x00\x00\x03\x00\x00\x00\x00\x00\x00\x00\x01\x00\x04\x00\x01\x09\x18\x01\x01\x00\x00\x00\x00\x00\x00\x00\x04\x00\x01\x08\x18\x01\x01\x00\x00\x00\x04\x00\x08";
-        let res = dbg_dmp(parse_xlog_record, "record")(input);
+        let res = dbg_dmp(
+            |i| parse_xlog_record(DEFAULT_BLCKSZ, WalVersion::default(), i),
+            "record",
+        )(input);
         assert!(res.is_ok(), "{:x?}", res);
 
         let (i, record) = res.unwrap();
@@ -355,7 +540,7 @@ mod tests {
         let Operation::Heap(HeapOperation::Insert(insert)) = record.operation else {
             panic!("Unexpected operation")
         };
-        assert_eq!(insert.offnum, 1023);
+        assert_eq!(insert.offnum, 4);
 
         assert_eq!(record.blocks.len(), 2);
         let block = &record.blocks[0];
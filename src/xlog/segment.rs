@@ -0,0 +1,249 @@
+use std::fs::File;
+use std::io;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Suffixes a segment's file may have: partial, compressed or both
+pub const SEGMENT_SUFFIXES: [&str; 8] = [
+    "",
+    ".partial",
+    ".gz",
+    ".zst",
+    ".lz4",
+    ".partial.gz",
+    ".partial.zst",
+    ".partial.lz4",
+];
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const LZ4_MAGIC: [u8; 4] = [0x04, 0x22, 0x4d, 0x18];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Lz4,
+}
+
+impl Compression {
+    /// Compression expected from the file's extension
+    pub fn from_extension(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            Some("lz4") => Compression::Lz4,
+            _ => Compression::None,
+        }
+    }
+
+    /// Compression detected from the first bytes of the file
+    pub fn from_magic(magic: &[u8]) -> Self {
+        if magic.starts_with(&GZIP_MAGIC) {
+            Compression::Gzip
+        } else if magic.starts_with(&ZSTD_MAGIC) {
+            Compression::Zstd
+        } else if magic.starts_with(&LZ4_MAGIC) {
+            Compression::Lz4
+        } else {
+            Compression::None
+        }
+    }
+}
+
+impl std::fmt::Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+            Compression::Lz4 => "lz4",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Find the file holding a segment in dir, trying the name followed by
+/// each suffix
+pub fn find_segment_file(dir: &Path, name: &str, suffixes: &[&str]) -> io::Result<PathBuf> {
+    suffixes
+        .iter()
+        .map(|suffix| dir.join(format!("{}{}", name, suffix)))
+        .find(|path| path.is_file())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No file found for {} in {:?}", name, dir),
+            )
+        })
+}
+
+/// Check if a file name is a segment name, optionally followed by one of
+/// the accepted suffixes
+pub fn is_segment_file_name(file_name: &str) -> bool {
+    // Any name can be listed, byte 24 may be inside a character
+    let Some((digits, suffix)) = file_name.split_at_checked(24) else {
+        return false;
+    };
+    digits.bytes().all(|b| b.is_ascii_hexdigit()) && SEGMENT_SUFFIXES.contains(&suffix)
+}
+
+enum SegmentReader {
+    Raw(File),
+    Memory(Cursor<Arc<[u8]>>),
+    Compressed(Box<dyn Read + Send>),
+}
+
+/// Where a segment's content comes from, kept to reopen it
+#[derive(Clone)]
+enum SegmentOrigin {
+    File(PathBuf),
+    Memory(Arc<[u8]>),
+}
+
+/// A segment file, decompressed on the fly when compressed. Compressed
+/// segments are streamed, moving backward reopens the file.
+pub struct SegmentFile {
+    name: String,
+    origin: SegmentOrigin,
+    compression: Compression,
+    reader: SegmentReader,
+    /// Position in the decompressed segment
+    position: u64,
+}
+
+/// Detect the content's compression, checking it against the one expected
+/// from the file name
+fn detect_compression<R: Read + Seek>(r: &mut R, name: &str) -> io::Result<Compression> {
+    let mut magic = [0; 4];
+    let mut magic_len = 0;
+    while magic_len < magic.len() {
+        match r.read(&mut magic[magic_len..])? {
+            0 => break,
+            len => magic_len += len,
+        }
+    }
+    r.seek(SeekFrom::Start(0))?;
+    let compression = Compression::from_magic(&magic[..magic_len]);
+    let expected = Compression::from_extension(Path::new(name));
+    if expected != Compression::None && expected != compression {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is not a {} file", name, expected),
+        ));
+    }
+    Ok(compression)
+}
+
+fn decompress<R: Read + Send + 'static>(
+    r: R,
+    compression: Compression,
+) -> io::Result<Box<dyn Read + Send>> {
+    Ok(match compression {
+        Compression::None => Box::new(r),
+        Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(r)),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(r)?),
+        Compression::Lz4 => Box::new(lz4_flex::frame::FrameDecoder::new(r)),
+    })
+}
+
+impl SegmentFile {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let name = path.display().to_string();
+        Self::from_origin(name, SegmentOrigin::File(path.to_path_buf()))
+    }
+
+    /// Segment held in memory, its name's extension tells the expected
+    /// compression
+    pub fn from_bytes(name: &str, data: Arc<[u8]>) -> io::Result<Self> {
+        Self::from_origin(name.to_string(), SegmentOrigin::Memory(data))
+    }
+
+    fn from_origin(name: String, origin: SegmentOrigin) -> io::Result<Self> {
+        let (compression, reader) = match &origin {
+            SegmentOrigin::File(path) => {
+                let mut f = File::open(path)?;
+                let compression = detect_compression(&mut f, &name)?;
+                let reader = match compression {
+                    Compression::None => SegmentReader::Raw(f),
+                    _ => SegmentReader::Compressed(decompress(f, compression)?),
+                };
+                (compression, reader)
+            }
+            SegmentOrigin::Memory(data) => {
+                let mut cursor = Cursor::new(data.clone());
+                let compression = detect_compression(&mut cursor, &name)?;
+                let reader = match compression {
+                    Compression::None => SegmentReader::Memory(cursor),
+                    _ => SegmentReader::Compressed(decompress(cursor, compression)?),
+                };
+                (compression, reader)
+            }
+        };
+        Ok(Self {
+            name,
+            origin,
+            compression,
+            reader,
+            position: 0,
+        })
+    }
+
+    /// Name of the file, used in error messages
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Size of the stored content, only meaningful for uncompressed segments
+    pub fn raw_size(&self) -> io::Result<Option<u64>> {
+        match &self.reader {
+            SegmentReader::Raw(f) => Ok(Some(f.metadata()?.len())),
+            SegmentReader::Memory(cursor) => Ok(Some(cursor.get_ref().len() as u64)),
+            SegmentReader::Compressed(_) => Ok(None),
+        }
+    }
+
+    /// Move to offset in the decompressed segment
+    pub fn seek_to(&mut self, offset: u64) -> io::Result<()> {
+        match &mut self.reader {
+            SegmentReader::Raw(f) => {
+                f.seek(SeekFrom::Start(offset))?;
+                self.position = offset;
+                return Ok(());
+            }
+            SegmentReader::Memory(cursor) => {
+                cursor.set_position(offset);
+                self.position = offset;
+                return Ok(());
+            }
+            SegmentReader::Compressed(_) => {}
+        }
+        if offset < self.position {
+            *self = Self::from_origin(self.name.clone(), self.origin.clone())?;
+        }
+        let skip = offset - self.position;
+        let skipped = io::copy(&mut self.by_ref().take(skip), &mut io::sink())?;
+        if skipped < skip {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(())
+    }
+}
+
+impl Read for SegmentFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = match &mut self.reader {
+            SegmentReader::Raw(f) => f.read(buf)?,
+            SegmentReader::Memory(cursor) => cursor.read(buf)?,
+            SegmentReader::Compressed(reader) => reader.read(buf)?,
+        };
+        self.position += len as u64;
+        Ok(len)
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+use log::debug;
+
+use crate::xlog::lsn::{parse_filename, TimelineID, XLogFilePos, XLogRecPtr, XLogSegNo};
+use crate::xlog::segment::{
+    find_segment_file, is_segment_file_name, SegmentFile, SEGMENT_SUFFIXES,
+};
+
+/// Where WAL segments and timeline history files are read from
+pub trait SegmentSource: Send + Sync {
+    /// Open a segment or a history file by its name
+    fn open(&self, file_name: &str) -> io::Result<SegmentFile>;
+
+    /// Names of the available segment files, possibly with a suffix. Sources
+    /// that can't be listed return an empty list.
+    fn list(&self) -> io::Result<Vec<String>>;
+
+    /// Open segment segno of timeline tli
+    fn open_segment(
+        &self,
+        tli: TimelineID,
+        segno: XLogSegNo,
+        wal_seg_size: u64,
+    ) -> io::Result<SegmentFile> {
+        self.open(&XLogFilePos::from_segno(tli, segno, wal_seg_size).to_string())
+    }
+
+    /// Available segments as timeline and segment number, ordered
+    fn list_segments(&self, wal_seg_size: u64) -> io::Result<Vec<(TimelineID, XLogSegNo)>> {
+        let mut segments = Vec::new();
+        for name in self.list()? {
+            let file_pos =
+                parse_filename(&name).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            segments.push((file_pos.tli, file_pos.get_segno(wal_seg_size)));
+        }
+        segments.sort();
+        segments.dedup();
+        Ok(segments)
+    }
+}
+
+/// A source shared between readers, like the workers of a parallel scan
+impl<S: SegmentSource + ?Sized> SegmentSource for Arc<S> {
+    fn open(&self, file_name: &str) -> io::Result<SegmentFile> {
+        (**self).open(file_name)
+    }
+
+    fn list(&self) -> io::Result<Vec<String>> {
+        (**self).list()
+    }
+}
+
+/// List segment files of dir
+fn list_directory(dir: &Path) -> io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        if let Some(name) = entry?.file_name().to_str() {
+            if is_segment_file_name(name) {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// A cluster's pg_wal directory, holding plain and partial segments
+pub struct WalDirectory {
+    dir: PathBuf,
+}
+
+impl WalDirectory {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+impl SegmentSource for WalDirectory {
+    fn open(&self, file_name: &str) -> io::Result<SegmentFile> {
+        SegmentFile::open(&find_segment_file(&self.dir, file_name, &["", ".partial"])?)
+    }
+
+    fn list(&self) -> io::Result<Vec<String>> {
+        Ok(list_directory(&self.dir)?
+            .into_iter()
+            .filter(|name| name.len() == 24 || name.ends_with(".partial"))
+            .collect())
+    }
+}
+
+/// A flat archive directory, segments may be partial and compressed
+pub struct ArchiveDirectory {
+    dir: PathBuf,
+}
+
+impl ArchiveDirectory {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+impl SegmentSource for ArchiveDirectory {
+    fn open(&self, file_name: &str) -> io::Result<SegmentFile> {
+        SegmentFile::open(&find_segment_file(&self.dir, file_name, &SEGMENT_SUFFIXES)?)
+    }
+
+    fn list(&self) -> io::Result<Vec<String>> {
+        list_directory(&self.dir)
+    }
+}
+
+/// Segments and history files held in memory, indexed by file name
+#[derive(Default)]
+pub struct MemorySource {
+    files: BTreeMap<String, Arc<[u8]>>,
+}
+
+impl MemorySource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, file_name: &str, data: Vec<u8>) {
+        self.files.insert(file_name.to_string(), data.into());
+    }
+}
+
+impl SegmentSource for MemorySource {
+    fn open(&self, file_name: &str) -> io::Result<SegmentFile> {
+        match self.files.get(file_name) {
+            Some(data) => SegmentFile::from_bytes(file_name, data.clone()),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No file {} in memory", file_name),
+            )),
+        }
+    }
+
+    fn list(&self) -> io::Result<Vec<String>> {
+        Ok(self
+            .files
+            .keys()
+            .filter(|name| is_segment_file_name(name))
+            .cloned()
+            .collect())
+    }
+}
+
+/// Fetch files with a shell command, like PostgreSQL's restore_command: %f
+/// is replaced by the file name and %p by the path to copy it to. Restored
+/// files are loaded in memory and removed.
+pub struct RestoreCommand {
+    command: String,
+    restore_dir: tempfile::TempDir,
+}
+
+impl RestoreCommand {
+    pub fn new(command: String) -> io::Result<Self> {
+        let restore_dir = tempfile::tempdir()?;
+        Ok(Self {
+            command,
+            restore_dir,
+        })
+    }
+}
+
+impl SegmentSource for RestoreCommand {
+    fn open(&self, file_name: &str) -> io::Result<SegmentFile> {
+        let path = self.restore_dir.path().join(file_name);
+        let command = self
+            .command
+            .replace("%f", file_name)
+            .replace("%p", &path.to_string_lossy());
+        debug!("Restoring {} with '{}'", file_name, command);
+        let status = Command::new("sh").arg("-c").arg(&command).status()?;
+        // As for recovery, a failing command means the file isn't available
+        if !status.success() || !path.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Could not restore {}: '{}' {}", file_name, command, status),
+            ));
+        }
+        let data = std::fs::read(&path)?;
+        std::fs::remove_file(&path)?;
+        SegmentFile::from_bytes(file_name, data.into())
+    }
+
+    fn list(&self) -> io::Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+}
+
+struct StreamState {
+    reader: Box<dyn Read + Send>,
+    /// Position of the next byte to read from the stream
+    position: XLogRecPtr,
+    /// Last segment read from the stream
+    segment: Option<(XLogSegNo, Arc<[u8]>)>,
+}
+
+/// Segments read from a stream of consecutive WAL pages, like a segment
+/// piped on stdin. Only the last segment read is kept in memory, so
+/// segments can only be opened in increasing order. The stream has no
+/// history files and its segments match any timeline.
+pub struct StreamSource {
+    state: Mutex<StreamState>,
+    wal_seg_size: u64,
+}
+
+impl StreamSource {
+    /// Stream whose first byte is at start, segments being wal_seg_size bytes
+    pub fn new(reader: Box<dyn Read + Send>, start: XLogRecPtr, wal_seg_size: u64) -> Self {
+        Self {
+            state: Mutex::new(StreamState {
+                reader,
+                position: start,
+                segment: None,
+            }),
+            wal_seg_size,
+        }
+    }
+}
+
+impl StreamState {
+    /// Read the rest of the current segment from the stream. When the
+    /// stream starts in the middle of a segment, the missing beginning is
+    /// zeroed.
+    fn read_segment(&mut self, wal_seg_size: u64) -> io::Result<usize> {
+        let segno = self.position.segno(wal_seg_size);
+        let offset = self.position.segment_offset(wal_seg_size);
+        let mut data = vec![0; offset as usize];
+        let len = self
+            .reader
+            .by_ref()
+            .take(wal_seg_size - offset)
+            .read_to_end(&mut data)?;
+        self.position += len as u64;
+        self.segment = Some((segno, data.into()));
+        Ok(len)
+    }
+}
+
+impl SegmentSource for StreamSource {
+    fn open(&self, file_name: &str) -> io::Result<SegmentFile> {
+        let not_found = || {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No file {} in stream", file_name),
+            )
+        };
+        if !is_segment_file_name(file_name) {
+            return Err(not_found());
+        }
+        let segno = parse_filename(file_name)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+            .get_segno(self.wal_seg_size);
+        let mut state = self.state.lock().unwrap();
+        loop {
+            match &state.segment {
+                Some((current, data)) if *current == segno => {
+                    return SegmentFile::from_bytes(file_name, data.clone())
+                }
+                Some((current, _)) if *current > segno => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        format!("Can't go back to {} in a stream", file_name),
+                    ))
+                }
+                _ => {}
+            }
+            if state.read_segment(self.wal_seg_size)? == 0 {
+                return Err(not_found());
+            }
+        }
+    }
+
+    fn list(&self) -> io::Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+}
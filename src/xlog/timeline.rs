@@ -0,0 +1,133 @@
+use std::io;
+use std::io::Read;
+
+use crate::xlog::lsn::{InvalidLSN, TimelineID, XLogRecPtr, XLogSegNo};
+use crate::xlog::source::SegmentSource;
+
+/// A timeline and the WAL range it covers. begin is invalid for the first
+/// timeline and end is invalid for the latest one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeLineHistoryEntry {
+    pub tli: TimelineID,
+    pub begin: XLogRecPtr,
+    pub end: XLogRecPtr,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum InvalidTimelineHistory {
+    #[error("Error reading timeline history: {0}")]
+    Io(#[from] io::Error),
+    #[error("Syntax error in timeline history: '{0}'")]
+    Syntax(String),
+    #[error("Invalid switch point in timeline history: {0}")]
+    SwitchPoint(#[from] InvalidLSN),
+    #[error("Timeline IDs must be in increasing sequence: {0} after {1}")]
+    OutOfSequence(TimelineID, TimelineID),
+    #[error("Timeline IDs must be less than child timeline's ID {0}")]
+    NotAncestor(TimelineID),
+}
+
+/// Name of the history file of a timeline
+pub fn history_file_name(tli: TimelineID) -> String {
+    format!("{:08X}.history", tli)
+}
+
+/// Parse the content of tli's history file. Each line holds a parent
+/// timeline, the LSN where it switched to the next timeline and a reason.
+/// Entries are returned newest first, starting with tli itself.
+pub fn parse_timeline_history(
+    content: &str,
+    tli: TimelineID,
+) -> Result<Vec<TimeLineHistoryEntry>, InvalidTimelineHistory> {
+    let mut entries = Vec::new();
+    let mut begin = XLogRecPtr::INVALID;
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let (Some(parent_tli), Some(switchpoint)) = (fields.next(), fields.next()) else {
+            return Err(InvalidTimelineHistory::Syntax(line.to_string()));
+        };
+        let Ok(parent_tli) = parent_tli.parse::<TimelineID>() else {
+            return Err(InvalidTimelineHistory::Syntax(line.to_string()));
+        };
+        let end: XLogRecPtr = switchpoint.parse()?;
+        if let Some(previous) = entries.last().map(|e: &TimeLineHistoryEntry| e.tli) {
+            if parent_tli <= previous {
+                return Err(InvalidTimelineHistory::OutOfSequence(parent_tli, previous));
+            }
+        }
+        entries.push(TimeLineHistoryEntry {
+            tli: parent_tli,
+            begin,
+            end,
+        });
+        begin = end;
+    }
+    if entries.last().is_some_and(|e| e.tli >= tli) {
+        return Err(InvalidTimelineHistory::NotAncestor(tli));
+    }
+    entries.push(TimeLineHistoryEntry {
+        tli,
+        begin,
+        end: XLogRecPtr::INVALID,
+    });
+    entries.reverse();
+    Ok(entries)
+}
+
+/// Read tli's history from its file. Timeline 1 and timelines without
+/// history file have no parent.
+pub fn read_timeline_history(
+    source: &dyn SegmentSource,
+    tli: TimelineID,
+) -> Result<Vec<TimeLineHistoryEntry>, InvalidTimelineHistory> {
+    let mut content = String::new();
+    match source.open(&history_file_name(tli)) {
+        Ok(mut f) => {
+            f.read_to_string(&mut content)?;
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    };
+    parse_timeline_history(&content, tli)
+}
+
+/// Find the newest timeline having tli in its history. Like recovery,
+/// following timelines are probed until one doesn't have a history file.
+pub fn find_newest_timeline(
+    source: &dyn SegmentSource,
+    tli: TimelineID,
+) -> Result<TimelineID, InvalidTimelineHistory> {
+    let mut newest = tli;
+    let mut candidate = tli + 1;
+    loop {
+        match source.open(&history_file_name(candidate)) {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+            Err(e) => return Err(e.into()),
+        }
+        // Timelines forked from another branch are skipped
+        let history = read_timeline_history(source, candidate)?;
+        if history.iter().any(|e| e.tli == tli) {
+            newest = candidate;
+        }
+        candidate += 1;
+    }
+    Ok(newest)
+}
+
+/// Timeline to read a segment from. The segment holding a switch point
+/// is read from the new timeline, which has a copy of the WAL before it.
+pub fn timeline_of_segment(
+    history: &[TimeLineHistoryEntry],
+    segno: XLogSegNo,
+    wal_seg_size: u64,
+) -> Option<TimelineID> {
+    history
+        .iter()
+        .find(|e| !e.begin.is_valid() || e.begin.segno(wal_seg_size) <= segno)
+        .map(|e| e.tli)
+}
@@ -0,0 +1,261 @@
+use crate::xlog::lsn::{TimelineID, XLogRecPtr};
+use crate::xlog::page::{WalVersion, XLogPageHeader, XLP_ALL_FLAGS};
+
+#[derive(Debug)]
+pub enum ValidationError {
+    /// Page doesn't start with a known magic number, usually a zeroed page
+    InvalidMagic(u16),
+    /// Version of the first page read and version of the page found
+    VersionMismatch(WalVersion, WalVersion),
+    /// Unknown bits set in xlp_info
+    InvalidInfoBits(u16),
+    /// First page of a segment doesn't have a long header
+    MissingLongHeader,
+    /// Expected and found page address
+    UnexpectedPageAddr(XLogRecPtr, XLogRecPtr),
+    /// Previous and found timeline, a timeline can only increase
+    OutOfSequenceTimeline(TimelineID, TimelineID),
+    /// Expected and found system identifier
+    SystemIdMismatch(u64, u64),
+    /// Expected and found segment size
+    SegmentSizeMismatch(u64, u32),
+    /// Expected and found WAL block size
+    BlockSizeMismatch(usize, u32),
+    /// Expected and found xl_prev
+    InvalidXlPrev(XLogRecPtr, XLogRecPtr),
+}
+
+impl ValidationError {
+    /// Recycled segments keep the pages written at their previous position
+    /// in the WAL and preallocated segments are zero-filled. Finding a page
+    /// with an older address or without magic means we've reached the end
+    /// of valid WAL, not that the WAL is corrupted. Like PostgreSQL, a record
+    /// not pointing to the previous one is taken as a leftover from before
+    /// the segment was recycled.
+    pub fn is_end_of_wal(&self) -> bool {
+        match self {
+            ValidationError::UnexpectedPageAddr(expected, found) => found < expected,
+            ValidationError::InvalidMagic(_) | ValidationError::InvalidXlPrev(..) => true,
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ValidationError::InvalidMagic(magic) => {
+                write!(f, "Invalid magic number 0x{:04X}", magic)
+            }
+            ValidationError::VersionMismatch(expected, found) => write!(
+                f,
+                "Page written by PostgreSQL {}, expected {}",
+                found, expected
+            ),
+            ValidationError::InvalidInfoBits(info) => write!(f, "Invalid info bits 0x{:04X}", info),
+            ValidationError::MissingLongHeader => {
+                write!(f, "Missing long page header at segment start")
+            }
+            ValidationError::UnexpectedPageAddr(expected, found) => write!(
+                f,
+                "Unexpected page address {}, expected {}",
+                found, expected
+            ),
+            ValidationError::OutOfSequenceTimeline(previous, found) => write!(
+                f,
+                "Out-of-sequence timeline ID {} after {}",
+                found, previous
+            ),
+            ValidationError::SystemIdMismatch(expected, found) => {
+                write!(f, "System identifier is {}, expected {}", found, expected)
+            }
+            ValidationError::SegmentSizeMismatch(expected, found) => {
+                write!(f, "Segment size is {}, expected {}", found, expected)
+            }
+            ValidationError::BlockSizeMismatch(expected, found) => {
+                write!(f, "Block size is {}, expected {}", found, expected)
+            }
+            ValidationError::InvalidXlPrev(expected, found) => write!(
+                f,
+                "Record with incorrect prev-link {}, expected {}",
+                found, expected
+            ),
+        }
+    }
+}
+
+/// Why reading stopped without reaching a corrupted record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EndOfWalReason {
+    /// Zeroed space where the next record should start
+    ZeroLength,
+    /// Page left from a recycled segment: expected and found page address
+    PageAddrMismatch(XLogRecPtr, XLogRecPtr),
+    /// Page without a valid magic number
+    BadMagic(u16),
+    /// Stale record not pointing to the last record: expected and found
+    /// xl_prev
+    InvalidXlPrev(XLogRecPtr, XLogRecPtr),
+    /// Torn last record, nothing was written after it: expected and actual
+    /// CRC
+    CrcMismatch(u32, u32),
+    /// No segment left to read
+    NoMoreSegment,
+    /// The requested end LSN was reached
+    EndLsnReached,
+}
+
+impl std::fmt::Display for EndOfWalReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EndOfWalReason::ZeroLength => write!(f, "invalid record length 0"),
+            EndOfWalReason::PageAddrMismatch(expected, found) => write!(
+                f,
+                "unexpected page address {}, expected {}",
+                found, expected
+            ),
+            EndOfWalReason::BadMagic(magic) => write!(f, "invalid magic number 0x{:04X}", magic),
+            EndOfWalReason::InvalidXlPrev(expected, found) => write!(
+                f,
+                "record with incorrect prev-link {}, expected {}",
+                found, expected
+            ),
+            EndOfWalReason::CrcMismatch(expected, actual) => write!(
+                f,
+                "incorrect record checksum: expected 0x{:08X}, got 0x{:08X}",
+                expected, actual
+            ),
+            EndOfWalReason::NoMoreSegment => write!(f, "no more segment to read"),
+            EndOfWalReason::EndLsnReached => write!(f, "end location reached"),
+        }
+    }
+}
+
+/// Position where valid WAL ends and why
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndOfWal {
+    pub lsn: XLogRecPtr,
+    pub reason: EndOfWalReason,
+}
+
+impl std::fmt::Display for EndOfWal {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "End of WAL at {}: {}", self.lsn, self.reason)
+    }
+}
+
+/// Check that pages and records follow each other: page addresses match
+/// their position, timelines don't go backward, long headers are consistent
+/// and each record points to the previous one.
+#[derive(Debug, Default)]
+pub struct XLogValidator {
+    /// System identifier of the first long header read
+    system_id: Option<u64>,
+    /// Version of the first page read
+    version: Option<WalVersion>,
+    /// Timeline of the last page read
+    last_tli: Option<TimelineID>,
+    /// Start of the last record read, unknown when reading starts
+    prev_rec_ptr: Option<XLogRecPtr>,
+}
+
+impl XLogValidator {
+    pub fn check_page_header(
+        &mut self,
+        page_header: &XLogPageHeader,
+        page_ptr: XLogRecPtr,
+        wal_seg_size: u64,
+        blcksz: usize,
+    ) -> Result<(), ValidationError> {
+        let std = page_header.std();
+        if let Some(found) = page_header.version() {
+            let version = *self.version.get_or_insert(found);
+            if found != version {
+                return Err(ValidationError::VersionMismatch(version, found));
+            }
+        }
+        if std.xlp_info & !XLP_ALL_FLAGS != 0 {
+            return Err(ValidationError::InvalidInfoBits(std.xlp_info));
+        }
+
+        match page_header {
+            XLogPageHeader::Long(long) => {
+                let system_id = *self.system_id.get_or_insert(long.xlp_sysid);
+                if long.xlp_sysid != system_id {
+                    return Err(ValidationError::SystemIdMismatch(system_id, long.xlp_sysid));
+                }
+                if u64::from(long.xlp_seg_size) != wal_seg_size {
+                    return Err(ValidationError::SegmentSizeMismatch(
+                        wal_seg_size,
+                        long.xlp_seg_size,
+                    ));
+                }
+                if long.xlp_xlog_blcksz as usize != blcksz {
+                    return Err(ValidationError::BlockSizeMismatch(
+                        blcksz,
+                        long.xlp_xlog_blcksz,
+                    ));
+                }
+            }
+            XLogPageHeader::Short(_) if page_ptr.segment_offset(wal_seg_size) == 0 => {
+                return Err(ValidationError::MissingLongHeader);
+            }
+            XLogPageHeader::Short(_) => {}
+        }
+
+        let pageaddr = XLogRecPtr::from(std.xlp_pageaddr);
+        if pageaddr != page_ptr {
+            return Err(ValidationError::UnexpectedPageAddr(page_ptr, pageaddr));
+        }
+
+        if let Some(last_tli) = self.last_tli {
+            if std.xlp_tli < last_tli {
+                return Err(ValidationError::OutOfSequenceTimeline(
+                    last_tli,
+                    std.xlp_tli,
+                ));
+            }
+        }
+        self.last_tli = Some(std.xlp_tli);
+        Ok(())
+    }
+
+    /// Version of the WAL read so far, records are decoded with the oldest
+    /// supported format until a page has been checked
+    pub fn version(&self) -> WalVersion {
+        self.version.unwrap_or_default()
+    }
+
+    /// Check the record's xl_prev against the previous record read, the
+    /// previous record is kept when the check fails
+    pub fn check_record(
+        &mut self,
+        lsn: XLogRecPtr,
+        xl_prev: XLogRecPtr,
+    ) -> Result<(), ValidationError> {
+        match self.prev_rec_ptr {
+            Some(prev_rec_ptr) if prev_rec_ptr != xl_prev => {
+                Err(ValidationError::InvalidXlPrev(prev_rec_ptr, xl_prev))
+            }
+            _ => {
+                self.prev_rec_ptr = Some(lsn);
+                Ok(())
+            }
+        }
+    }
+
+    /// Start of the last record checked, the next record must point to it
+    pub fn prev_rec_ptr(&self) -> Option<XLogRecPtr> {
+        self.prev_rec_ptr
+    }
+
+    /// Set the record the next record must point to, None when unknown
+    pub fn set_prev_rec_ptr(&mut self, prev_rec_ptr: Option<XLogRecPtr>) {
+        self.prev_rec_ptr = prev_rec_ptr;
+    }
+
+    /// Forget the previous record, used when the reader jumps to a new position
+    pub fn reset_prev_rec_ptr(&mut self) {
+        self.prev_rec_ptr = None;
+    }
+}
@@ -1,4 +1,10 @@
-use wal_analyzer::xlog::block::{parse_blocks, ForkNumber, PageId, RelFileLocator};
+use wal_analyzer::error::XLogError;
+use wal_analyzer::xlog::block::{
+    parse_blocks, ForkNumber, ImageCompression, ImageError, PageId, RelFileLocator, BKPIMAGE_APPLY,
+    BKPIMAGE_APPLY_V15, BKPIMAGE_COMPRESS_LZ4, BKPIMAGE_COMPRESS_PGLZ, BKPIMAGE_COMPRESS_ZSTD,
+    BKPIMAGE_HAS_HOLE, BKPIMAGE_IS_COMPRESSED, DEFAULT_BLCKSZ, XLR_BLOCK_ID_DATA_LONG,
+};
+use wal_analyzer::xlog::page::WalVersion;
 
 #[cfg(test)]
 #[ctor::ctor]
@@ -30,10 +36,10 @@ fn test_parse_heap_block() {
     // rmgr: Heap        len (rec/tot):     59/    59, tx:        744, lsn: 0/01400028, prev 0/013FCC70, desc: INSERT+INIT off 1 flags 0x08, blkref #0: rel 1663/12976/16406 blk 0
     // let input = b"\x3b\x00\x00\x00\xe8\x02\x00\x00\x70\xcc\x3f\x01\x00\x00\x00\x00\x80\x0a\x00\x00\x25\xcb\x5b\xc0\x00\x60\x0a\x00\x7f\x06\x00\x00\xb0\x32\x00\x00\x16\x40\x00\x00\x00\x00\x00\x00\xff\x03\x04\x00\x01\x08\x18\x01\x01\x00\x00\x00\x01\x00\x08\x00\x00\x00\x00\x00";
     let input = b"\x00\x60\x0a\x00\x7f\x06\x00\x00\xb0\x32\x00\x00\x16\x40\x00\x00\x00\x00\x00\x00\xff\x03\x04\x00\x01\x08\x18\x01\x01\x00\x00\x00\x01\x00\x08";
-    let res = parse_blocks(input);
+    let res = parse_blocks(DEFAULT_BLCKSZ, WalVersion::default(), input);
     assert!(res.is_ok(), "{:?}", res);
 
-    let (i, (j, blocks)) = res.unwrap();
+    let (i, (j, blocks, _)) = res.unwrap();
     assert_eq!(blocks.len(), 2);
     let block = &blocks[0];
 
@@ -59,5 +65,272 @@ fn test_parse_heap_block() {
     assert_eq!(main_block.data_len, 3);
 
     assert!(i.is_empty(), "{:?}", i);
-    //    assert!(j.is_empty(), "{:?}", j);
+    assert_eq!(j, b"\x01\x00\x08");
+}
+
+#[test]
+fn test_parse_long_main_data() {
+    let main_data: Vec<u8> = (0..300).map(|x| x as u8).collect();
+    let mut input = vec![XLR_BLOCK_ID_DATA_LONG];
+    input.extend_from_slice(&300u32.to_le_bytes());
+    input.extend_from_slice(&main_data);
+
+    let (i, (data, blocks, _)) =
+        parse_blocks(DEFAULT_BLCKSZ, WalVersion::default(), &input).unwrap();
+    assert!(i.is_empty(), "{:?}", i);
+    assert_eq!(data, &main_data[..]);
+    assert_eq!(blocks.len(), 1);
+    assert_eq!(blocks[0].blk_id, XLR_BLOCK_ID_DATA_LONG);
+    assert_eq!(blocks[0].data_len, 300);
+    assert_eq!(blocks[0].data.as_deref(), Some(&main_data[..]));
+}
+
+#[test]
+fn test_parse_blocks_without_main_data() {
+    // Block data starting with what could be read as a block id
+    let input =
+        b"\x00\x20\x02\x00\x7f\x06\x00\x00\xb0\x32\x00\x00\x16\x40\x00\x00\x00\x00\x00\x00\x01\x02";
+    let (i, (data, blocks, _)) =
+        parse_blocks(DEFAULT_BLCKSZ, WalVersion::default(), input).unwrap();
+    assert!(i.is_empty(), "{:?}", i);
+    assert!(data.is_empty());
+    assert_eq!(blocks.len(), 1);
+    assert_eq!(blocks[0].data.as_deref(), Some(&b"\x01\x02"[..]));
+}
+
+#[test]
+fn test_parse_origin_and_toplevel_xid() {
+    //  origin: \xfd \x01\x00
+    //  top-level xid: \xfc \xec\x02\x00\x00
+    //  block 0 with 2 bytes of data
+    //  main data header: \xff \x03
+    let input = b"\xfd\x01\x00\xfc\xec\x02\x00\x00\x00\x20\x02\x00\x7f\x06\x00\x00\xb0\x32\x00\x00\x16\x40\x00\x00\x00\x00\x00\x00\xff\x03\x01\x02\x01\x00\x08";
+    let (i, (data, blocks, headers)) =
+        parse_blocks(DEFAULT_BLCKSZ, WalVersion::default(), input).unwrap();
+    assert!(i.is_empty(), "{:?}", i);
+    assert_eq!(headers.record_origin, Some(1));
+    assert_eq!(headers.toplevel_xid, Some(748));
+    assert_eq!(blocks.len(), 2);
+    assert_eq!(blocks[0].data.as_deref(), Some(&b"\x01\x02"[..]));
+    assert_eq!(data, b"\x01\x00\x08");
+}
+
+#[test]
+fn test_parse_truncated_main_data() {
+    // 3 bytes of main data announced, 2 present
+    let input = b"\xff\x03\x01\x00";
+    let res = parse_blocks(DEFAULT_BLCKSZ, WalVersion::default(), input);
+    assert!(
+        matches!(res, Err(nom::Err::Error(XLogError::InvalidDataLen(2, 3)))),
+        "{:?}",
+        res
+    );
+}
+
+#[test]
+fn test_parse_invalid_block_id() {
+    let input = b"\x30\x00\x00\x00";
+    let res = parse_blocks(DEFAULT_BLCKSZ, WalVersion::default(), input);
+    assert!(
+        matches!(res, Err(nom::Err::Error(XLogError::IncorrectId(0x30)))),
+        "{:?}",
+        res
+    );
+}
+
+const HOLE_OFFSET: u16 = 64;
+const HOLE_LENGTH: u16 = 7936;
+
+/// Page with a header, a hole and tuples at the end
+fn test_page() -> Vec<u8> {
+    let mut page = vec![0u8; DEFAULT_BLCKSZ as usize];
+    for (i, byte) in page[..HOLE_OFFSET as usize].iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    for (i, byte) in page[(HOLE_OFFSET + HOLE_LENGTH) as usize..]
+        .iter_mut()
+        .enumerate()
+    {
+        *byte = (i / 40) as u8;
+    }
+    page
+}
+
+/// test_page without its hole
+fn test_page_image() -> Vec<u8> {
+    let page = test_page();
+    let mut image = page[..HOLE_OFFSET as usize].to_vec();
+    image.extend_from_slice(&page[(HOLE_OFFSET + HOLE_LENGTH) as usize..]);
+    image
+}
+
+/// pglz compression encoding runs of the same byte as a literal followed
+/// by tags copying the previous byte
+fn pglz_compress(data: &[u8]) -> Vec<u8> {
+    let mut items: Vec<Vec<u8>> = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        items.push(vec![data[pos]]);
+        let run = data[pos + 1..]
+            .iter()
+            .take_while(|b| **b == data[pos])
+            .count();
+        pos += 1;
+        let mut left = run;
+        while left >= 3 {
+            let len = left.min(273);
+            if len >= 18 {
+                items.push(vec![0x0f, 0x01, (len - 18) as u8]);
+            } else {
+                items.push(vec![(len - 3) as u8, 0x01]);
+            }
+            left -= len;
+        }
+        pos += run - left;
+    }
+    let mut compressed = Vec::new();
+    for chunk in items.chunks(8) {
+        let ctrl = chunk.iter().enumerate().fold(0u8, |ctrl, (i, item)| {
+            ctrl | (u8::from(item.len() > 1) << i)
+        });
+        compressed.push(ctrl);
+        chunk.iter().for_each(|item| compressed.extend(item));
+    }
+    compressed
+}
+
+/// Block 0 of 1663/5/16384 with a full page image and no data
+fn image_block(bimg_info: u8, hole_length: Option<u16>, image: &[u8]) -> Vec<u8> {
+    let mut block = vec![0x00, 0x10, 0x00, 0x00];
+    block.extend_from_slice(&(image.len() as u16).to_le_bytes());
+    let hole_offset = if bimg_info & BKPIMAGE_HAS_HOLE != 0 {
+        HOLE_OFFSET
+    } else {
+        0
+    };
+    block.extend_from_slice(&hole_offset.to_le_bytes());
+    block.push(bimg_info);
+    if let Some(hole_length) = hole_length {
+        block.extend_from_slice(&hole_length.to_le_bytes());
+    }
+    for field in [1663u32, 5, 16384, 0] {
+        block.extend_from_slice(&field.to_le_bytes());
+    }
+    block.extend_from_slice(image);
+    block
+}
+
+#[test]
+fn test_restore_image_with_hole() {
+    let input = image_block(BKPIMAGE_HAS_HOLE | BKPIMAGE_APPLY, None, &test_page_image());
+    let (_, (_, blocks, _)) = parse_blocks(DEFAULT_BLCKSZ, WalVersion::V14, &input).unwrap();
+    let image = blocks[0].image.as_ref().unwrap();
+    assert!(image.apply_image);
+    assert_eq!(image.compression, ImageCompression::None);
+    assert_eq!(image.hole_length, HOLE_LENGTH);
+    assert_eq!(image.restore_page(DEFAULT_BLCKSZ).unwrap(), test_page());
+}
+
+#[test]
+fn test_restore_pglz_image() {
+    let compressed = pglz_compress(&test_page_image());
+    assert!(compressed.len() < test_page_image().len());
+    let input = image_block(
+        BKPIMAGE_HAS_HOLE | BKPIMAGE_IS_COMPRESSED | BKPIMAGE_APPLY,
+        Some(HOLE_LENGTH),
+        &compressed,
+    );
+    let (_, (_, blocks, _)) = parse_blocks(DEFAULT_BLCKSZ, WalVersion::V14, &input).unwrap();
+    let image = blocks[0].image.as_ref().unwrap();
+    assert!(image.apply_image);
+    assert_eq!(image.compression, ImageCompression::Pglz);
+    assert_eq!(image.restore_page(DEFAULT_BLCKSZ).unwrap(), test_page());
+}
+
+#[test]
+fn test_restore_pg15_compressed_images() {
+    let raw = test_page_image();
+    let methods = [
+        (
+            BKPIMAGE_COMPRESS_PGLZ,
+            ImageCompression::Pglz,
+            pglz_compress(&raw),
+        ),
+        (
+            BKPIMAGE_COMPRESS_LZ4,
+            ImageCompression::Lz4,
+            lz4_flex::block::compress(&raw),
+        ),
+        (
+            BKPIMAGE_COMPRESS_ZSTD,
+            ImageCompression::Zstd,
+            zstd::bulk::compress(&raw, 0).unwrap(),
+        ),
+    ];
+    for (flag, compression, compressed) in methods {
+        let input = image_block(
+            BKPIMAGE_HAS_HOLE | BKPIMAGE_APPLY_V15 | flag,
+            Some(HOLE_LENGTH),
+            &compressed,
+        );
+        let (_, (_, blocks, _)) = parse_blocks(DEFAULT_BLCKSZ, WalVersion::V15, &input).unwrap();
+        let image = blocks[0].image.as_ref().unwrap();
+        assert!(image.apply_image);
+        assert_eq!(image.compression, compression);
+        assert_eq!(image.hole_length, HOLE_LENGTH);
+        assert_eq!(image.restore_page(DEFAULT_BLCKSZ).unwrap(), test_page());
+    }
+}
+
+#[test]
+fn test_pg15_uncompressed_image() {
+    // BKPIMAGE_APPLY_V15 was the compressed flag before PG15
+    let page = test_page();
+    let input = image_block(BKPIMAGE_APPLY_V15, None, &page);
+    let (_, (_, blocks, _)) = parse_blocks(DEFAULT_BLCKSZ, WalVersion::V16, &input).unwrap();
+    let image = blocks[0].image.as_ref().unwrap();
+    assert!(image.apply_image);
+    assert_eq!(image.compression, ImageCompression::None);
+    assert_eq!(image.hole_length, 0);
+    assert_eq!(image.restore_page(DEFAULT_BLCKSZ).unwrap(), page);
+}
+
+#[test]
+fn test_restore_corrupted_image() {
+    let mut compressed = lz4_flex::block::compress(&test_page_image());
+    compressed.truncate(compressed.len() / 2);
+    let input = image_block(
+        BKPIMAGE_HAS_HOLE | BKPIMAGE_COMPRESS_LZ4,
+        Some(HOLE_LENGTH),
+        &compressed,
+    );
+    let (_, (_, blocks, _)) = parse_blocks(DEFAULT_BLCKSZ, WalVersion::V15, &input).unwrap();
+    let image = blocks[0].image.as_ref().unwrap();
+    assert!(!image.apply_image);
+    let res = image.restore_page(DEFAULT_BLCKSZ);
+    assert!(
+        matches!(
+            res,
+            Err(ImageError::Decompression(ImageCompression::Lz4, _))
+        ),
+        "{:?}",
+        res
+    );
+
+    let invalid_hole = |input: &[u8]| {
+        matches!(
+            parse_blocks(DEFAULT_BLCKSZ, WalVersion::V15, input),
+            Err(nom::Err::Error(XLogError::InvalidBlockImageHole(..)))
+        )
+    };
+    // Hole offset without a hole
+    let mut input = image_block(BKPIMAGE_APPLY_V15, None, &test_page());
+    input[6..8].copy_from_slice(&HOLE_OFFSET.to_le_bytes());
+    assert!(invalid_hole(&input));
+    // Compressed image as large as a page
+    let input = image_block(BKPIMAGE_COMPRESS_LZ4, None, &test_page());
+    assert!(invalid_hole(&input));
+    // Uncompressed image without a hole shorter than a page
+    let input = image_block(BKPIMAGE_APPLY_V15, None, &test_page_image());
+    assert!(invalid_hole(&input));
 }
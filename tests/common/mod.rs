@@ -0,0 +1,48 @@
+//! WAL page and record builders shared by the integration tests
+#![allow(dead_code)]
+
+use wal_analyzer::xlog::lsn::XLogRecPtr;
+use wal_analyzer::xlog::operation::xlog::XLOG_FPI;
+use wal_analyzer::xlog::page::XLP_LONG_HEADER;
+use wal_analyzer::xlog::record::compute_record_crc;
+
+pub const XLOG_BLCKSZ: usize = 8192;
+/// Smallest segment size allowed, files written by tests only hold their
+/// first pages
+pub const WAL_SEG_SIZE: u64 = 1024 * 1024;
+
+/// Write a page header at the start of page
+pub fn write_page_header(page: &mut [u8], xlp_info: u16, xlp_pageaddr: u64, xlp_rem_len: u32) {
+    page[0..2].copy_from_slice(&0xd10du16.to_le_bytes());
+    page[2..4].copy_from_slice(&xlp_info.to_le_bytes());
+    page[4..8].copy_from_slice(&1u32.to_le_bytes());
+    page[8..16].copy_from_slice(&xlp_pageaddr.to_le_bytes());
+    page[16..20].copy_from_slice(&xlp_rem_len.to_le_bytes());
+    if xlp_info & XLP_LONG_HEADER != 0 {
+        page[32..36].copy_from_slice(&(WAL_SEG_SIZE as u32).to_le_bytes());
+        page[36..40].copy_from_slice(&(XLOG_BLCKSZ as u32).to_le_bytes());
+    }
+}
+
+/// XLOG FPI record with a block of 1663/5/16384 holding each of blocks'
+/// data, block i being block number i, without main data
+pub fn block_record(xl_prev: XLogRecPtr, blocks: &[&[u8]]) -> Vec<u8> {
+    let mut record = vec![0u8; 24];
+    record[8..16].copy_from_slice(&xl_prev.as_u64().to_le_bytes());
+    record[16] = XLOG_FPI;
+    for (block_id, data) in blocks.iter().enumerate() {
+        record.extend_from_slice(&[block_id as u8, 0x20]);
+        record.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        for field in [1663u32, 5, 16384, block_id as u32] {
+            record.extend_from_slice(&field.to_le_bytes());
+        }
+    }
+    for data in blocks {
+        record.extend_from_slice(data);
+    }
+    let tot_len = record.len() as u32;
+    record[0..4].copy_from_slice(&tot_len.to_le_bytes());
+    let crc = compute_record_crc(&record);
+    record[20..24].copy_from_slice(&crc.to_le_bytes());
+    record
+}
@@ -0,0 +1,59 @@
+use wal_analyzer::xlog::lsn::{InvalidLSN, XLogRecPtr};
+
+#[test]
+fn test_parse_lsn() {
+    let lsn: XLogRecPtr = "16/B374D848".parse().unwrap();
+    assert_eq!(lsn, XLogRecPtr::new(0x16_B374_D848));
+    assert_eq!(lsn.xlogid(), 0x16);
+    assert_eq!(lsn.xrecoff(), 0xB374D848);
+    assert_eq!(lsn.to_string(), "16/B374D848");
+
+    let lsn: XLogRecPtr = "0/1592EA8".parse().unwrap();
+    assert_eq!(lsn.to_string(), "0/01592EA8");
+}
+
+#[test]
+fn test_parse_invalid_lsn() {
+    let res = "01592EA8".parse::<XLogRecPtr>();
+    assert!(matches!(res, Err(InvalidLSN::Format(_))), "{:?}", res);
+
+    let res = "0/XYZ".parse::<XLogRecPtr>();
+    assert!(matches!(res, Err(InvalidLSN::HexValue(_, _))), "{:?}", res);
+}
+
+#[test]
+fn test_lsn_segment() {
+    let wal_seg_size = 16 * 1024 * 1024;
+    let lsn: XLogRecPtr = "1/FF001234".parse().unwrap();
+    assert_eq!(lsn.segno(wal_seg_size), 0x1ff);
+    assert_eq!(lsn.segment_offset(wal_seg_size), 0x1234);
+    assert_eq!(lsn.file_name(1, wal_seg_size), "0000000100000001000000FF");
+    assert_eq!(XLogRecPtr::from_segno(0x1ff, 0x1234, wal_seg_size), lsn);
+
+    // Same position with 1GB segments
+    let wal_seg_size = 1024 * 1024 * 1024;
+    assert_eq!(lsn.file_name(2, wal_seg_size), "000000020000000100000003");
+}
+
+#[test]
+fn test_lsn_page() {
+    let lsn: XLogRecPtr = "0/1402013".parse().unwrap();
+    assert_eq!(lsn.page_offset(8192), 0x13);
+    assert_eq!(lsn.page_start(8192), "0/1402000".parse().unwrap());
+    assert!(!lsn.is_page_boundary(8192));
+    assert!(lsn.page_start(8192).is_page_boundary(8192));
+    assert_eq!(lsn.maxalign(), "0/1402018".parse().unwrap());
+}
+
+#[test]
+fn test_lsn_arithmetic() {
+    let start: XLogRecPtr = "0/FFFFFFF0".parse().unwrap();
+    let end = start + 0x20;
+    assert_eq!(end.to_string(), "1/00000010");
+    assert_eq!(end.checked_sub(start), Some(0x20));
+    assert_eq!(start.checked_sub(end), None);
+    assert_eq!(end.saturating_sub(start), 0x20);
+    assert_eq!(start.saturating_sub(end), 0);
+    assert!(start < end);
+    assert!(!XLogRecPtr::INVALID.is_valid());
+}
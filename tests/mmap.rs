@@ -0,0 +1,122 @@
+use std::borrow::Cow;
+use std::io::Write;
+use std::path::PathBuf;
+
+mod common;
+
+use common::{block_record, write_page_header, WAL_SEG_SIZE, XLOG_BLCKSZ};
+
+use wal_analyzer::xlog::lsn::XLogRecPtr;
+use wal_analyzer::xlog::mmap::MappedWal;
+use wal_analyzer::xlog::page::{XLP_FIRST_IS_CONTRECORD, XLP_LONG_HEADER};
+use wal_analyzer::xlog::reader::XLogReader;
+use wal_analyzer::xlog::validate::EndOfWalReason;
+
+#[test]
+fn test_mapped_records_borrow_data() {
+    let long_header_size = 40;
+    let short_header_size = 24;
+    let mut segment = vec![0u8; 2 * XLOG_BLCKSZ];
+    write_page_header(&mut segment, XLP_LONG_HEADER, WAL_SEG_SIZE, 0);
+
+    // The first record fits on the page, the second one continues on the
+    // next page
+    let first_lsn = XLogRecPtr::new(WAL_SEG_SIZE + long_header_size as u64);
+    let first = block_record(XLogRecPtr::INVALID, &[&[0xaa; 4]]);
+    let second_start = long_header_size + first.len();
+    let second = block_record(first_lsn, &[&[0xbb; XLOG_BLCKSZ]]);
+    let on_first_page = XLOG_BLCKSZ - second_start;
+    let remaining = second.len() - on_first_page;
+    segment[long_header_size..second_start].copy_from_slice(&first);
+    segment[second_start..XLOG_BLCKSZ].copy_from_slice(&second[..on_first_page]);
+    write_page_header(
+        &mut segment[XLOG_BLCKSZ..],
+        XLP_FIRST_IS_CONTRECORD,
+        WAL_SEG_SIZE + XLOG_BLCKSZ as u64,
+        remaining as u32,
+    );
+    let second_page_data = XLOG_BLCKSZ + short_header_size;
+    segment[second_page_data..second_page_data + remaining]
+        .copy_from_slice(&second[on_first_page..]);
+
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("000000010000000000000001"), &segment).unwrap();
+    let wal = MappedWal::open(dir.path().to_path_buf(), "000000010000000000000001", None).unwrap();
+    let mut records = wal.records();
+
+    let record = records.next().unwrap().unwrap();
+    assert_eq!(record.lsn, first_lsn);
+    let data = record.blocks[0].data.as_ref().unwrap();
+    assert!(matches!(data, Cow::Borrowed(_)));
+    assert_eq!(data.as_ref(), &[0xaa; 4]);
+
+    // Only the record spanning pages is copied
+    let record = records.next().unwrap().unwrap();
+    assert_eq!(record.lsn, first_lsn + first.len() as u64);
+    let data = record.blocks[0].data.as_ref().unwrap();
+    assert!(matches!(data, Cow::Owned(_)));
+    assert_eq!(data.as_ref(), &[0xbb; XLOG_BLCKSZ]);
+    let owned = record.into_owned();
+    assert_eq!(
+        owned.blocks[0].data.as_deref(),
+        Some(&[0xbb; XLOG_BLCKSZ][..])
+    );
+
+    assert!(records.next().is_none());
+    let end_of_wal = records.end_of_wal().unwrap();
+    assert_eq!(end_of_wal.reason, EndOfWalReason::ZeroLength);
+}
+
+#[test]
+fn test_mapped_records_match_reader() {
+    let wal = MappedWal::open(PathBuf::from("assets"), "000000010000000000000014", None).unwrap();
+    assert_eq!(wal.wal_seg_size(), WAL_SEG_SIZE);
+    let reader =
+        XLogReader::new_from_filename(PathBuf::from("assets/000000010000000000000014")).unwrap();
+
+    let mut count = 0;
+    for (mapped, read) in wal.records().zip(reader) {
+        match (mapped, read) {
+            (Ok(mapped), Ok(read)) => {
+                assert_eq!(mapped.lsn, read.lsn);
+                assert_eq!(mapped.end_lsn, read.end_lsn);
+            }
+            (Err(mapped), Err(read)) => assert_eq!(mapped.to_string(), read.to_string()),
+            (mapped, read) => panic!("Mismatch: {:?} {:?}", mapped, read),
+        }
+        count += 1;
+    }
+    assert_eq!(count, 26);
+}
+
+#[test]
+fn test_mapped_records_seek_and_end_lsn() {
+    let reader =
+        XLogReader::new_from_filename(PathBuf::from("assets/000000010000000000000014")).unwrap();
+    let read: Vec<_> = reader.filter_map(Result::ok).collect();
+
+    let wal = MappedWal::open(PathBuf::from("assets"), "000000010000000000000014", None).unwrap();
+    let mut records = wal.records();
+    // Seeking inside a record moves to the next one
+    records.seek(read[2].lsn + 1).unwrap();
+    records.set_end_lsn(read[5].end_lsn);
+    let lsns: Vec<_> = records.by_ref().map(|r| r.unwrap().lsn).collect();
+    assert_eq!(lsns, vec![read[3].lsn, read[4].lsn, read[5].lsn]);
+    let end_of_wal = records.end_of_wal().unwrap();
+    assert_eq!(end_of_wal.reason, EndOfWalReason::EndLsnReached);
+    assert_eq!(end_of_wal.lsn, read[5].end_lsn);
+}
+
+#[test]
+fn test_map_compressed_segment() {
+    let data = std::fs::read("assets/000000010000000000000014").unwrap();
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&data).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("000000010000000000000014"),
+        encoder.finish().unwrap(),
+    )
+    .unwrap();
+    assert!(MappedWal::open(dir.path().to_path_buf(), "000000010000000000000014", None).is_err());
+}
@@ -0,0 +1,676 @@
+use std::path::PathBuf;
+
+use wal_analyzer::error::XLogError;
+use wal_analyzer::xlog::block::{ForkNumber, RelFileLocator};
+use wal_analyzer::xlog::common::TimestampTz;
+use wal_analyzer::xlog::lsn::XLogRecPtr;
+use wal_analyzer::xlog::operation::database::{
+    parse_database_operation, DatabaseOperation, XLOG_DBASE_CREATE_FILE_COPY,
+    XLOG_DBASE_CREATE_WAL_LOG, XLOG_DBASE_DROP, XLOG_DBASE_DROP_V14,
+};
+use wal_analyzer::xlog::operation::heap::{
+    parse_heap_operation, HeapOperation, XLOG_HEAP_CONFIRM, XLOG_HEAP_HOT_UPDATE,
+    XLOG_HEAP_INIT_PAGE, XLOG_HEAP_INPLACE, XLOG_HEAP_LOCK, XLOG_HEAP_TRUNCATE, XLOG_HEAP_UPDATE,
+};
+use wal_analyzer::xlog::operation::standby::{
+    parse_standby_operation, StandbyLock, StandbyOperation, XLOG_INVALIDATIONS, XLOG_RUNNING_XACTS,
+    XLOG_STANDBY_LOCK,
+};
+use wal_analyzer::xlog::operation::storage::{
+    parse_storage_operation, StorageOperation, SMGR_TRUNCATE_ALL, SMGR_TRUNCATE_HEAP,
+    SMGR_TRUNCATE_VM, XLOG_SMGR_CREATE, XLOG_SMGR_TRUNCATE,
+};
+use wal_analyzer::xlog::operation::tablespace::{
+    parse_tablespace_operation, TablespaceOperation, XLOG_TBLSPC_CREATE, XLOG_TBLSPC_DROP,
+};
+use wal_analyzer::xlog::operation::xact::{
+    parse_xact_operation, InvalidationMessage, StatsItem, TransactionOperation, XactDbInfo,
+    XACT_XINFO_HAS_DBINFO, XACT_XINFO_HAS_DROPPED_STATS, XACT_XINFO_HAS_GID, XACT_XINFO_HAS_ORIGIN,
+    XACT_XINFO_HAS_SUBXACTS, XACT_XINFO_HAS_TWOPHASE, XLOG_XACT_ABORT, XLOG_XACT_ASSIGNMENT,
+    XLOG_XACT_COMMIT_PREPARED, XLOG_XACT_HAS_INFO, XLOG_XACT_PREPARE,
+};
+use wal_analyzer::xlog::operation::xlog::{
+    parse_xlog_operation, WalLevel, XlogOperation, XLOG_CHECKPOINT_REDO, XLOG_CHECKPOINT_SHUTDOWN,
+    XLOG_END_OF_RECOVERY, XLOG_PARAMETER_CHANGE, XLOG_RESTORE_POINT,
+};
+use wal_analyzer::xlog::page::WalVersion;
+use wal_analyzer::xlog::reader::XLogReader;
+use wal_analyzer::xlog::record::{Operation, RmgrId};
+
+fn xlog_operation(rmgr_info: u8, version: WalVersion, data: &[u8]) -> XlogOperation {
+    let (_, operation) = parse_xlog_operation(rmgr_info, version, data).unwrap();
+    match operation {
+        Operation::Xlog(operation) => operation,
+        operation => panic!("Unexpected operation {:?}", operation),
+    }
+}
+
+fn xact_operation(rmgr_info: u8, version: WalVersion, data: &[u8]) -> TransactionOperation {
+    let (_, operation) = parse_xact_operation(rmgr_info, version, data).unwrap();
+    match operation {
+        Operation::Transaction(operation) => operation,
+        operation => panic!("Unexpected operation {:?}", operation),
+    }
+}
+
+fn push_u32s(data: &mut Vec<u8>, fields: &[u32]) {
+    for field in fields {
+        data.extend_from_slice(&field.to_le_bytes());
+    }
+}
+
+/// CheckPoint struct, with wal_level in the padding after fullPageWrites
+fn checkpoint_bytes(wal_level: [u8; 4]) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(&0x1400028u64.to_le_bytes());
+    data.extend_from_slice(&2u32.to_le_bytes());
+    data.extend_from_slice(&1u32.to_le_bytes());
+    data.extend_from_slice(&[1, 0, 0, 0]);
+    data.extend_from_slice(&wal_level);
+    data.extend_from_slice(&((1u64 << 32) | 750).to_le_bytes());
+    for field in [24576u32, 2, 3, 726, 1, 1, 5, 0] {
+        data.extend_from_slice(&field.to_le_bytes());
+    }
+    data.extend_from_slice(&1_743_855_279i64.to_le_bytes());
+    for field in [0u32, 0, 0, 0] {
+        data.extend_from_slice(&field.to_le_bytes());
+    }
+    data
+}
+
+#[test]
+fn test_parse_online_checkpoint() {
+    let reader =
+        XLogReader::new_from_filename(PathBuf::from("assets/000000010000000000000014")).unwrap();
+    let record = reader
+        .filter_map(Result::ok)
+        .find(|record| record.header.xl_rmid == RmgrId::Xlog)
+        .unwrap();
+    let Operation::Xlog(XlogOperation::CheckpointOnline(checkpoint)) = record.operation else {
+        panic!("Unexpected operation {:?}", record.operation)
+    };
+    assert_eq!(checkpoint.redo, XLogRecPtr::new(0x014009D0));
+    assert_eq!(checkpoint.this_timeline_id, 1);
+    assert_eq!(checkpoint.prev_timeline_id, 1);
+    assert!(checkpoint.full_page_writes);
+    assert_eq!(checkpoint.wal_level, None);
+    assert_eq!(checkpoint.next_xid, 746);
+    assert_eq!(checkpoint.next_oid, 24576);
+    assert_eq!(checkpoint.oldest_xid, 726);
+    assert_eq!(checkpoint.oldest_xid_db, 1);
+    assert_eq!(checkpoint.oldest_active_xid, 746);
+    assert_eq!(
+        checkpoint.time.to_string(),
+        "2025-04-05 12:14:39.000000 UTC"
+    );
+}
+
+#[test]
+fn test_parse_pg17_checkpoint() {
+    let data = checkpoint_bytes(1i32.to_le_bytes());
+    let XlogOperation::CheckpointShutdown(checkpoint) =
+        xlog_operation(XLOG_CHECKPOINT_SHUTDOWN, WalVersion::V17, &data)
+    else {
+        panic!("Unexpected operation")
+    };
+    assert_eq!(checkpoint.wal_level, Some(WalLevel::Replica));
+    assert_eq!(checkpoint.this_timeline_id, 2);
+    assert_eq!(checkpoint.next_xid >> 32, 1);
+    assert_eq!(checkpoint.next_multi, 2);
+    assert_eq!(checkpoint.next_multi_offset, 3);
+    assert_eq!(checkpoint.oldest_multi_db, 5);
+    assert_eq!(checkpoint.time, TimestampTz::from_unix_time(1_743_855_279));
+
+    // Padding before PG17
+    let data = checkpoint_bytes([0x7f; 4]);
+    let XlogOperation::CheckpointShutdown(checkpoint) =
+        xlog_operation(XLOG_CHECKPOINT_SHUTDOWN, WalVersion::V16, &data)
+    else {
+        panic!("Unexpected operation")
+    };
+    assert_eq!(checkpoint.wal_level, None);
+    assert_eq!(checkpoint.next_oid, 24576);
+}
+
+#[test]
+fn test_parse_restore_point() {
+    let mut data = 0i64.to_le_bytes().to_vec();
+    let mut name = [0u8; 64];
+    name[..11].copy_from_slice(b"before_drop");
+    data.extend_from_slice(&name);
+    let XlogOperation::RestorePoint(restore_point) =
+        xlog_operation(XLOG_RESTORE_POINT, WalVersion::V14, &data)
+    else {
+        panic!("Unexpected operation")
+    };
+    assert_eq!(restore_point.name, "before_drop");
+    assert_eq!(
+        restore_point.time.to_string(),
+        "2000-01-01 00:00:00.000000 UTC"
+    );
+}
+
+#[test]
+fn test_parse_parameter_change() {
+    let mut data = Vec::new();
+    for field in [100i32, 8, 10, 0, 64, 2] {
+        data.extend_from_slice(&field.to_le_bytes());
+    }
+    data.extend_from_slice(&[1, 0, 0, 0]);
+    let (i, operation) =
+        parse_xlog_operation(XLOG_PARAMETER_CHANGE, WalVersion::V15, &data).unwrap();
+    // Trailing padding is left
+    assert_eq!(i.len(), 2);
+    let Operation::Xlog(XlogOperation::ParameterChange(parameters)) = operation else {
+        panic!("Unexpected operation")
+    };
+    assert_eq!(parameters.max_connections, 100);
+    assert_eq!(parameters.max_locks_per_xact, 64);
+    assert_eq!(parameters.wal_level, WalLevel::Logical);
+    assert!(parameters.wal_log_hints);
+    assert!(!parameters.track_commit_timestamp);
+}
+
+#[test]
+fn test_parse_end_of_recovery() {
+    let mut data = 774_000_000_123_456i64.to_le_bytes().to_vec();
+    data.extend_from_slice(&3u32.to_le_bytes());
+    data.extend_from_slice(&2u32.to_le_bytes());
+    let XlogOperation::EndOfRecovery(end_of_recovery) =
+        xlog_operation(XLOG_END_OF_RECOVERY, WalVersion::V14, &data)
+    else {
+        panic!("Unexpected operation")
+    };
+    assert_eq!(end_of_recovery.this_timeline_id, 3);
+    assert_eq!(end_of_recovery.prev_timeline_id, 2);
+    assert_eq!(
+        end_of_recovery.end_time.to_string(),
+        "2024-07-11 08:00:00.123456 UTC"
+    );
+}
+
+#[test]
+fn test_parse_unknown_xlog_info() {
+    // XLOG_CHECKPOINT_REDO only exists since PG17
+    let data = 1i32.to_le_bytes();
+    assert!(matches!(
+        xlog_operation(XLOG_CHECKPOINT_REDO, WalVersion::V17, &data),
+        XlogOperation::CheckpointRedo(WalLevel::Replica)
+    ));
+    let res = parse_xlog_operation(XLOG_CHECKPOINT_REDO, WalVersion::V16, &data);
+    assert!(
+        matches!(
+            res,
+            Err(nom::Err::Error(XLogError::UnknownRecordInfo(
+                RmgrId::Xlog,
+                0xe0
+            )))
+        ),
+        "{:?}",
+        res
+    );
+}
+
+#[test]
+fn test_parse_commit() {
+    let reader =
+        XLogReader::new_from_filename(PathBuf::from("assets/000000010000000000000014")).unwrap();
+    let operations: Vec<_> = reader
+        .filter_map(Result::ok)
+        .filter_map(|record| match record.operation {
+            Operation::Transaction(operation) => Some(operation),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(operations.len(), 4);
+
+    let TransactionOperation::Commit(commit) = &operations[0] else {
+        panic!("Unexpected operation {:?}", operations[0])
+    };
+    assert_eq!(
+        commit.xact_time.to_string(),
+        "2025-04-05 12:09:39.676567 UTC"
+    );
+    assert_eq!(
+        commit.rels,
+        vec![
+            RelFileLocator {
+                spc_node: 1663,
+                db_node: 12976,
+                rel_node: 16404
+            },
+            RelFileLocator {
+                spc_node: 1663,
+                db_node: 12976,
+                rel_node: 16396
+            },
+        ]
+    );
+    assert_eq!(commit.invalidations.len(), 8);
+    assert!(matches!(
+        commit.invalidations[4],
+        InvalidationMessage::Relcache {
+            db_id: 12976,
+            rel_id: 16390
+        }
+    ));
+    assert!(commit.twophase_xid.is_none());
+
+    let TransactionOperation::Invalidations(msgs) = &operations[1] else {
+        panic!("Unexpected operation {:?}", operations[1])
+    };
+    assert!(msgs
+        .iter()
+        .all(|msg| matches!(msg, InvalidationMessage::Catcache { id: 59, .. })));
+}
+
+#[test]
+fn test_parse_commit_prepared() {
+    let xinfo = XACT_XINFO_HAS_DBINFO
+        | XACT_XINFO_HAS_SUBXACTS
+        | XACT_XINFO_HAS_DROPPED_STATS
+        | XACT_XINFO_HAS_TWOPHASE
+        | XACT_XINFO_HAS_GID
+        | XACT_XINFO_HAS_ORIGIN;
+    let mut data = 1_000_000i64.to_le_bytes().to_vec();
+    // xinfo, dbinfo, subxacts, dropped stats and two-phase xid
+    push_u32s(
+        &mut data,
+        &[xinfo, 5, 1663, 2, 801, 802, 1, 2, 5, 16384, 800],
+    );
+    data.extend_from_slice(b"gid_1\0");
+    data.extend_from_slice(&0x3000028u64.to_le_bytes());
+    data.extend_from_slice(&2_000_000i64.to_le_bytes());
+
+    let TransactionOperation::CommitPrepared(commit) = xact_operation(
+        XLOG_XACT_COMMIT_PREPARED | XLOG_XACT_HAS_INFO,
+        WalVersion::V15,
+        &data,
+    ) else {
+        panic!("Unexpected operation")
+    };
+    assert_eq!(commit.xact_time, TimestampTz(1_000_000));
+    assert_eq!(
+        commit.dbinfo,
+        Some(XactDbInfo {
+            db_id: 5,
+            ts_id: 1663
+        })
+    );
+    assert_eq!(commit.subxacts, vec![801, 802]);
+    assert_eq!(
+        commit.dropped_stats,
+        vec![StatsItem {
+            kind: 2,
+            db_oid: 5,
+            obj_oid: 16384
+        }]
+    );
+    assert_eq!(commit.twophase_xid, Some(800));
+    assert_eq!(commit.twophase_gid.as_deref(), Some("gid_1"));
+    let origin = commit.origin.unwrap();
+    assert_eq!(origin.origin_lsn, XLogRecPtr::new(0x3000028));
+    assert_eq!(origin.origin_timestamp, TimestampTz(2_000_000));
+}
+
+#[test]
+fn test_parse_abort_without_info() {
+    let data = 42i64.to_le_bytes();
+    let TransactionOperation::Abort(abort) =
+        xact_operation(XLOG_XACT_ABORT, WalVersion::V14, &data)
+    else {
+        panic!("Unexpected operation")
+    };
+    assert_eq!(abort.xact_time, TimestampTz(42));
+    assert_eq!(abort.xinfo, 0);
+    assert!(abort.rels.is_empty());
+}
+
+#[test]
+fn test_parse_prepare() {
+    // TwoPhaseFileHeader of PG15+
+    let mut data = Vec::new();
+    push_u32s(&mut data, &[0x57F94534, 0, 800, 5]);
+    data.extend_from_slice(&3_000_000i64.to_le_bytes());
+    // owner, nsubxacts, ncommitrels, nabortrels, ncommitstats, nabortstats,
+    // ninvalmsgs
+    push_u32s(&mut data, &[10, 1, 0, 1, 0, 0, 1]);
+    data.extend_from_slice(&[1, 0]);
+    data.extend_from_slice(&6u16.to_le_bytes());
+    data.extend_from_slice(&[0; 16]);
+    // GID, subxact, abort rel and invalidation, MAXALIGNed
+    data.extend_from_slice(b"gid_1\0\0\0");
+    push_u32s(&mut data, &[801, 0, 1663, 5, 16384, 0]);
+    data.extend_from_slice(&[0xfe, 0, 0, 0]);
+    push_u32s(&mut data, &[5, 16384, 0]);
+
+    let TransactionOperation::Prepare(prepare) =
+        xact_operation(XLOG_XACT_PREPARE, WalVersion::V15, &data)
+    else {
+        panic!("Unexpected operation")
+    };
+    assert_eq!(prepare.xid, 800);
+    assert_eq!(prepare.database, 5);
+    assert_eq!(prepare.prepared_at, TimestampTz(3_000_000));
+    assert_eq!(prepare.owner, 10);
+    assert_eq!(prepare.gid, "gid_1");
+    assert_eq!(prepare.subxacts, vec![801]);
+    assert!(prepare.commit_rels.is_empty());
+    assert_eq!(prepare.abort_rels[0].rel_node, 16384);
+    assert!(prepare.init_file_inval);
+    assert_eq!(
+        prepare.invalidations,
+        vec![InvalidationMessage::Relcache {
+            db_id: 5,
+            rel_id: 16384
+        }]
+    );
+}
+
+#[test]
+fn test_parse_assignment() {
+    let mut data = Vec::new();
+    push_u32s(&mut data, &[800, 3, 801, 802, 803]);
+    let TransactionOperation::Assignment(assignment) =
+        xact_operation(XLOG_XACT_ASSIGNMENT, WalVersion::V14, &data)
+    else {
+        panic!("Unexpected operation")
+    };
+    assert_eq!(assignment.xtop, 800);
+    assert_eq!(assignment.xsub, vec![801, 802, 803]);
+
+    // Count larger than the record
+    data[4..8].copy_from_slice(&1000i32.to_le_bytes());
+    assert!(parse_xact_operation(XLOG_XACT_ASSIGNMENT, WalVersion::V14, &data).is_err());
+}
+
+#[test]
+fn test_parse_smgr_create() {
+    let mut data = Vec::new();
+    push_u32s(&mut data, &[1663, 5, 16384, 1]);
+    let (_, operation) = parse_storage_operation(XLOG_SMGR_CREATE, &data).unwrap();
+    let Operation::Storage(StorageOperation::Create(create)) = &operation else {
+        panic!("Unexpected operation {:?}", operation)
+    };
+    assert_eq!(
+        create.locator,
+        RelFileLocator {
+            spc_node: 1663,
+            db_node: 5,
+            rel_node: 16384
+        }
+    );
+    assert_eq!(create.fork, ForkNumber::Fsm);
+    assert_eq!(operation.to_string(), "CREATE rel 1663/5/16384 fork fsm");
+
+    // Fork numbers past INIT_FORKNUM are invalid
+    data[12..16].copy_from_slice(&4u32.to_le_bytes());
+    assert!(parse_storage_operation(XLOG_SMGR_CREATE, &data).is_err());
+}
+
+#[test]
+fn test_parse_smgr_truncate() {
+    let mut data = Vec::new();
+    push_u32s(&mut data, &[10, 1663, 5, 16384, SMGR_TRUNCATE_ALL as u32]);
+    let (_, operation) = parse_storage_operation(XLOG_SMGR_TRUNCATE, &data).unwrap();
+    let Operation::Storage(StorageOperation::Truncate(truncate)) = &operation else {
+        panic!("Unexpected operation {:?}", operation)
+    };
+    assert_eq!(truncate.blkno, 10);
+    assert_eq!(truncate.locator.rel_node, 16384);
+    assert_eq!(
+        truncate.forks(),
+        vec![ForkNumber::Main, ForkNumber::Fsm, ForkNumber::VisibilityMap]
+    );
+    assert_eq!(
+        operation.to_string(),
+        "TRUNCATE rel 1663/5/16384 to 10 blocks flags 7"
+    );
+
+    data[16..20].copy_from_slice(&((SMGR_TRUNCATE_HEAP | SMGR_TRUNCATE_VM) as u32).to_le_bytes());
+    let (_, operation) = parse_storage_operation(XLOG_SMGR_TRUNCATE, &data).unwrap();
+    let Operation::Storage(StorageOperation::Truncate(truncate)) = operation else {
+        panic!("Unexpected operation")
+    };
+    assert_eq!(
+        truncate.forks(),
+        vec![ForkNumber::Main, ForkNumber::VisibilityMap]
+    );
+}
+
+#[test]
+fn test_parse_database() {
+    let mut data = Vec::new();
+    push_u32s(&mut data, &[16384, 1663, 1, 1663]);
+    for version in [WalVersion::V14, WalVersion::V16] {
+        let (_, operation) =
+            parse_database_operation(XLOG_DBASE_CREATE_FILE_COPY, version, &data).unwrap();
+        let Operation::Database(DatabaseOperation::CreateFileCopy(create)) = &operation else {
+            panic!("Unexpected operation {:?}", operation)
+        };
+        assert_eq!(create.db_id, 16384);
+        assert_eq!(create.src_db_id, 1);
+        assert_eq!(
+            operation.to_string(),
+            "CREATE_FILE_COPY copy dir 1663/1 to 1663/16384"
+        );
+    }
+
+    let (_, operation) =
+        parse_database_operation(XLOG_DBASE_CREATE_WAL_LOG, WalVersion::V15, &data[..8]).unwrap();
+    let Operation::Database(DatabaseOperation::CreateWalLog(create)) = &operation else {
+        panic!("Unexpected operation {:?}", operation)
+    };
+    assert_eq!(create.db_id, 16384);
+    assert_eq!(create.tablespace_id, 1663);
+
+    // The drop record took the WAL_LOG opcode before PG15
+    let mut data = Vec::new();
+    push_u32s(&mut data, &[16384, 2, 1663, 16390]);
+    for (info, version) in [
+        (XLOG_DBASE_DROP_V14, WalVersion::V14),
+        (XLOG_DBASE_DROP, WalVersion::V17),
+    ] {
+        let (_, operation) = parse_database_operation(info, version, &data).unwrap();
+        let Operation::Database(DatabaseOperation::Drop(drop)) = &operation else {
+            panic!("Unexpected operation {:?}", operation)
+        };
+        assert_eq!(drop.db_id, 16384);
+        assert_eq!(drop.tablespace_ids, vec![1663, 16390]);
+        assert_eq!(operation.to_string(), "DROP dir 1663/16384 16390/16384");
+    }
+    assert!(matches!(
+        parse_database_operation(XLOG_DBASE_DROP, WalVersion::V14, &data),
+        Err(nom::Err::Error(XLogError::UnknownRecordInfo(
+            RmgrId::Database,
+            XLOG_DBASE_DROP
+        )))
+    ));
+}
+
+#[test]
+fn test_parse_tablespace() {
+    let mut data = 16390u32.to_le_bytes().to_vec();
+    data.extend_from_slice(b"/mnt/fast\0");
+    let (_, operation) = parse_tablespace_operation(XLOG_TBLSPC_CREATE, &data).unwrap();
+    let Operation::Tablespace(TablespaceOperation::Create(create)) = &operation else {
+        panic!("Unexpected operation {:?}", operation)
+    };
+    assert_eq!(create.ts_id, 16390);
+    assert_eq!(create.ts_path, "/mnt/fast");
+    assert_eq!(operation.to_string(), "CREATE 16390 \"/mnt/fast\"");
+
+    let (_, operation) = parse_tablespace_operation(XLOG_TBLSPC_DROP, &data[..4]).unwrap();
+    assert!(matches!(
+        operation,
+        Operation::Tablespace(TablespaceOperation::Drop(16390))
+    ));
+}
+
+#[test]
+fn test_parse_standby_lock() {
+    let mut data = Vec::new();
+    push_u32s(&mut data, &[2, 750, 5, 16384, 751, 5, 16390]);
+    let (_, operation) = parse_standby_operation(XLOG_STANDBY_LOCK, &data).unwrap();
+    let Operation::Standby(StandbyOperation::Lock(locks)) = &operation else {
+        panic!("Unexpected operation {:?}", operation)
+    };
+    assert_eq!(
+        locks[1],
+        StandbyLock {
+            xid: 751,
+            db_oid: 5,
+            rel_oid: 16390
+        }
+    );
+    assert_eq!(
+        operation.to_string(),
+        "LOCK xid 750 db 5 rel 16384 xid 751 db 5 rel 16390"
+    );
+}
+
+#[test]
+fn test_parse_running_xacts() {
+    let mut data = Vec::new();
+    push_u32s(&mut data, &[2, 1, 1, 760, 750, 759, 750, 755, 756]);
+    let (_, operation) = parse_standby_operation(XLOG_RUNNING_XACTS, &data).unwrap();
+    let Operation::Standby(StandbyOperation::RunningXacts(running_xacts)) = &operation else {
+        panic!("Unexpected operation {:?}", operation)
+    };
+    assert!(running_xacts.subxid_overflow);
+    assert_eq!(running_xacts.next_xid, 760);
+    assert_eq!(running_xacts.oldest_running_xid, 750);
+    assert_eq!(running_xacts.latest_completed_xid, 759);
+    assert_eq!(running_xacts.xids, vec![750, 755]);
+    assert_eq!(running_xacts.subxids, vec![756]);
+    assert_eq!(
+        operation.to_string(),
+        "RUNNING_XACTS nextXid 760 latestCompletedXid 759 oldestRunningXid 750; 2 xacts: 750 755; subxid overflowed; 1 subxacts: 756"
+    );
+
+    // More xids than the record holds
+    data[0..4].copy_from_slice(&8u32.to_le_bytes());
+    assert!(parse_standby_operation(XLOG_RUNNING_XACTS, &data).is_err());
+}
+
+#[test]
+fn test_parse_standby_invalidations() {
+    let mut data = Vec::new();
+    push_u32s(&mut data, &[5, 1663, 1, 1]);
+    // Relcache invalidation of relation 16384
+    data.extend_from_slice(&[0xfe, 0, 0, 0]);
+    push_u32s(&mut data, &[5, 16384, 0]);
+    let (_, operation) = parse_standby_operation(XLOG_INVALIDATIONS, &data).unwrap();
+    let Operation::Standby(StandbyOperation::Invalidations(invalidations)) = &operation else {
+        panic!("Unexpected operation {:?}", operation)
+    };
+    assert_eq!(invalidations.db_id, 5);
+    assert_eq!(invalidations.ts_id, 1663);
+    assert!(invalidations.relcache_init_file_inval);
+    assert_eq!(
+        invalidations.msgs,
+        vec![InvalidationMessage::Relcache {
+            db_id: 5,
+            rel_id: 16384
+        }]
+    );
+    assert_eq!(
+        operation.to_string(),
+        "INVALIDATIONS inval msgs: relcache 16384; relcache init file inval dbid 5 tsid 1663"
+    );
+}
+
+fn heap_operation(rmgr_info: u8, data: &[u8]) -> HeapOperation {
+    let (_, operation) = parse_heap_operation(rmgr_info, data).unwrap();
+    match operation {
+        Operation::Heap(operation) => operation,
+        operation => panic!("Unexpected operation {:?}", operation),
+    }
+}
+
+#[test]
+fn test_parse_heap_hot_update() {
+    // old_xmax, old_offnum, old_infobits, flags, new_xmax, new_offnum
+    let mut data = 750u32.to_le_bytes().to_vec();
+    data.extend_from_slice(&[3, 0, 0x10, 0x28]);
+    data.extend_from_slice(&0u32.to_le_bytes());
+    data.extend_from_slice(&[4, 0]);
+
+    let HeapOperation::HotUpdate(update) = heap_operation(XLOG_HEAP_HOT_UPDATE, &data) else {
+        panic!("Unexpected operation")
+    };
+    assert_eq!(update.old_xmax, 750);
+    assert_eq!(update.old_offnum, 3);
+    assert!(update.old_infobits.keys_updated);
+    assert!(update.contains_new_tuple);
+    assert!(update.suffix_from_old);
+    assert_eq!(update.new_offnum, 4);
+    assert!(!update.init_page);
+
+    let HeapOperation::HotUpdate(update) =
+        heap_operation(XLOG_HEAP_HOT_UPDATE | XLOG_HEAP_INIT_PAGE, &data)
+    else {
+        panic!("Unexpected operation")
+    };
+    assert!(update.init_page);
+    let HeapOperation::Update(update) =
+        heap_operation(XLOG_HEAP_UPDATE | XLOG_HEAP_INIT_PAGE, &data)
+    else {
+        panic!("Unexpected operation")
+    };
+    assert!(update.init_page);
+}
+
+#[test]
+fn test_parse_heap_truncate() {
+    let mut data = Vec::new();
+    push_u32s(&mut data, &[5, 2, 0x03, 16384, 16390]);
+    let HeapOperation::Truncate(truncate) = heap_operation(XLOG_HEAP_TRUNCATE, &data) else {
+        panic!("Unexpected operation")
+    };
+    assert_eq!(truncate.db_id, 5);
+    assert_eq!(truncate.relids, vec![16384, 16390]);
+    assert!(truncate.cascade);
+    assert!(truncate.restart_seqs);
+
+    // More relids than the record holds
+    data[4..8].copy_from_slice(&3u32.to_le_bytes());
+    assert!(parse_heap_operation(XLOG_HEAP_TRUNCATE, &data).is_err());
+}
+
+#[test]
+fn test_parse_heap_confirm_lock_inplace() {
+    let HeapOperation::Confirm(confirm) = heap_operation(XLOG_HEAP_CONFIRM, &[7, 0]) else {
+        panic!("Unexpected operation")
+    };
+    assert_eq!(confirm.offnum, 7);
+
+    // locking_xid, offnum, infobits_set, flags
+    let mut data = 751u32.to_le_bytes().to_vec();
+    data.extend_from_slice(&[2, 0, 0x06, 0x01]);
+    let HeapOperation::Lock(lock) = heap_operation(XLOG_HEAP_LOCK, &data) else {
+        panic!("Unexpected operation")
+    };
+    assert_eq!(lock.locking_xid, 751);
+    assert_eq!(lock.offnum, 2);
+    assert!(lock.infobits.xmax_lock_only);
+    assert!(lock.infobits.xmax_excl_lock);
+    assert!(!lock.infobits.xmax_is_multi);
+    assert!(lock.all_frozen_cleared);
+
+    let HeapOperation::Inplace(inplace) = heap_operation(XLOG_HEAP_INPLACE, &[9, 0]) else {
+        panic!("Unexpected operation")
+    };
+    assert_eq!(inplace.offnum, 9);
+
+    let reader =
+        XLogReader::new_from_filename(PathBuf::from("assets/000000010000000000000014")).unwrap();
+    let inplace: Vec<_> = reader
+        .filter_map(Result::ok)
+        .filter_map(|record| match record.operation {
+            Operation::Heap(HeapOperation::Inplace(inplace)) => Some(inplace.offnum),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(inplace, vec![13, 14]);
+}
@@ -1,5 +1,8 @@
 use wal_analyzer::{
-    xlog::page::{parse_xlog_page_header, XLogPageHeader, XLP_BKP_REMOVABLE, XLP_LONG_HEADER},
+    xlog::block::DEFAULT_BLCKSZ,
+    xlog::page::{
+        parse_xlog_page_header, WalVersion, XLogPageHeader, XLP_BKP_REMOVABLE, XLP_LONG_HEADER,
+    },
     xlog::record::{parse_xlog_record, RmgrId},
 };
 
@@ -94,6 +97,8 @@ fn test_page_with_two_records() {
     let (i, page_header) = parse_xlog_page_header(input).unwrap();
     assert_eq!(i.len(), 160);
 
+    let version = page_header.version().unwrap();
+    assert_eq!(version, WalVersion::V14);
     let long_page_header = match page_header {
         XLogPageHeader::Short(_) => panic!("Expected short page header"),
         XLogPageHeader::Long(xlog_long_page_header) => xlog_long_page_header,
@@ -110,7 +115,7 @@ fn test_page_with_two_records() {
     assert_eq!(long_page_header.xlp_seg_size, 0x100000);
     assert_eq!(long_page_header.xlp_xlog_blcksz, 0x2000);
 
-    let (i, record) = parse_xlog_record(i).unwrap();
+    let (i, record) = parse_xlog_record(DEFAULT_BLCKSZ, version, i).unwrap();
     assert_eq!(record.header.xl_tot_len, 59);
     assert_eq!(record.header.xl_rmid, RmgrId::Heap);
     assert_eq!(record.header.xl_xid, 744);
@@ -122,7 +127,7 @@ fn test_page_with_two_records() {
     assert_eq!(main_block.blk_id, 0xff);
     assert_eq!(main_block.data_len, 3);
 
-    let (i, record) = parse_xlog_record(i).unwrap();
+    let (i, record) = parse_xlog_record(DEFAULT_BLCKSZ, version, i).unwrap();
     assert_eq!(record.header.xl_rmid, RmgrId::Btree);
     assert_eq!(record.header.xl_xid, 744);
     assert_eq!(record.header.xl_crc, 2105440276);
@@ -0,0 +1,180 @@
+use std::path::PathBuf;
+
+mod common;
+
+use common::{block_record, write_page_header, XLOG_BLCKSZ};
+
+use wal_analyzer::xlog::lsn::XLogRecPtr;
+use wal_analyzer::xlog::page::{XLP_FIRST_IS_CONTRECORD, XLP_LONG_HEADER};
+use wal_analyzer::xlog::parallel::ParallelScan;
+use wal_analyzer::xlog::reader::{ReaderError, XLogReader};
+use wal_analyzer::xlog::record::XLogRecord;
+use wal_analyzer::xlog::source::{ArchiveDirectory, MemorySource};
+
+const WAL_SEG_SIZE: usize = common::WAL_SEG_SIZE as usize;
+
+/// Lay out records of the given block count and data length from the
+/// start of segment 1 over segments segments. Returns the segments and
+/// the LSN of each record.
+fn write_wal(records: &[(u8, u16)], segments: usize) -> (Vec<Vec<u8>>, Vec<XLogRecPtr>) {
+    let mut wal = vec![0u8; segments * WAL_SEG_SIZE];
+    let base = WAL_SEG_SIZE as u64;
+    for page_start in (0..wal.len()).step_by(XLOG_BLCKSZ) {
+        let xlp_info = if page_start % WAL_SEG_SIZE == 0 {
+            XLP_LONG_HEADER
+        } else {
+            0
+        };
+        write_page_header(
+            &mut wal[page_start..],
+            xlp_info,
+            base + page_start as u64,
+            0,
+        );
+    }
+
+    let mut lsns = Vec::new();
+    let mut prev = XLogRecPtr::INVALID;
+    let mut pos = 0;
+    for &(blocks, len) in records {
+        let data = vec![len as u8; len as usize];
+        let record = block_record(prev, &vec![data.as_slice(); blocks as usize]);
+        let mut written = 0;
+        while written < record.len() {
+            if pos % XLOG_BLCKSZ == 0 {
+                if written > 0 {
+                    let page = &mut wal[pos..];
+                    let xlp_info = u16::from_le_bytes([page[2], page[3]]) | XLP_FIRST_IS_CONTRECORD;
+                    page[2..4].copy_from_slice(&xlp_info.to_le_bytes());
+                    page[16..20].copy_from_slice(&((record.len() - written) as u32).to_le_bytes());
+                }
+                pos += if pos % WAL_SEG_SIZE == 0 { 40 } else { 24 };
+            }
+            if written == 0 {
+                prev = XLogRecPtr::new(base + pos as u64);
+                lsns.push(prev);
+            }
+            let n = (record.len() - written).min(XLOG_BLCKSZ - pos % XLOG_BLCKSZ);
+            wal[pos..pos + n].copy_from_slice(&record[written..written + n]);
+            pos += n;
+            written += n;
+        }
+        pos = pos.next_multiple_of(8);
+    }
+    (wal.chunks(WAL_SEG_SIZE).map(<[u8]>::to_vec).collect(), lsns)
+}
+
+/// Start of a record, whether it could be decoded or not
+fn record_lsn(record: Result<XLogRecord, ReaderError>) -> XLogRecPtr {
+    match record {
+        Ok(record) => record.lsn,
+        Err(ReaderError::ParseError(lsn, ..)) => lsn,
+        Err(e) => panic!("Unexpected error: {}", e),
+    }
+}
+
+fn memory_source(segments: &[Vec<u8>]) -> MemorySource {
+    let mut source = MemorySource::new();
+    for (i, segment) in segments.iter().enumerate() {
+        source.insert(&format!("00000001{:016X}", i + 1), segment.clone());
+    }
+    source
+}
+
+#[test]
+fn test_parallel_scan_boundary_records() {
+    // Records of various sizes straddle every segment boundary, and one of
+    // them covers the whole third segment
+    let mut records = Vec::new();
+    for i in 0..250 {
+        records.push((1, 3000 + i * 37));
+    }
+    records.push((22, 60000));
+    for i in 0..100 {
+        records.push((2, 500 + i * 11));
+    }
+    let (segments, lsns) = write_wal(&records, 4);
+    let last_segment = *lsns.last().unwrap();
+    assert_eq!(last_segment.as_u64() as usize / WAL_SEG_SIZE, 4);
+
+    let mut scan = ParallelScan::new(
+        Box::new(memory_source(&segments)),
+        "000000010000000000000001",
+        None,
+    )
+    .unwrap();
+    assert_eq!(scan.segment_count(), 4);
+    scan.set_workers(3);
+    let scanned = scan.filter_map(|record| Some(record_lsn(record))).unwrap();
+    assert_eq!(scanned, lsns);
+
+    // Per segment results
+    let counts = scan.scan(|records| records.count()).unwrap();
+    assert_eq!(counts.iter().sum::<usize>(), lsns.len());
+    assert_eq!(counts[2], 0);
+    let first_in_segment = |segno: u64| {
+        lsns.iter()
+            .filter(|lsn| lsn.as_u64() / WAL_SEG_SIZE as u64 == segno)
+            .count()
+    };
+    assert_eq!(counts[0], first_in_segment(1));
+    assert_eq!(counts[3], first_in_segment(4));
+}
+
+#[test]
+fn test_parallel_scan_end_segment() {
+    let records: Vec<_> = (0..400).map(|i| (1, 2000 + i * 13)).collect();
+    let (segments, lsns) = write_wal(&records, 3);
+    let scan = ParallelScan::new(
+        Box::new(memory_source(&segments)),
+        "000000010000000000000002",
+        Some("000000010000000000000002"),
+    )
+    .unwrap();
+    assert_eq!(scan.segment_count(), 1);
+    let scanned = scan.filter_map(|record| Some(record_lsn(record))).unwrap();
+    // The record straddling into segment 3 is still read
+    let expected: Vec<_> = lsns
+        .into_iter()
+        .filter(|lsn| lsn.as_u64() / WAL_SEG_SIZE as u64 == 2)
+        .collect();
+    assert_eq!(scanned, expected);
+}
+
+#[test]
+fn test_parallel_scan_matches_reader() {
+    let source = Box::new(ArchiveDirectory::new(PathBuf::from("assets")));
+    let scan = ParallelScan::new(source, "000000010000000000000014", None).unwrap();
+    let scanned = scan
+        .filter_map(|record| Some(record.map(|r| r.lsn).map_err(|e| e.to_string())))
+        .unwrap();
+    let reader =
+        XLogReader::new_from_filename(PathBuf::from("assets/000000010000000000000014")).unwrap();
+    let read: Vec<_> = reader
+        .map(|record| record.map(|r| r.lsn).map_err(|e| e.to_string()))
+        .collect();
+    assert_eq!(scanned, read);
+}
+
+#[test]
+fn test_parallel_scan_open_error() {
+    let records: Vec<_> = (0..400).map(|i| (1, 2000 + i * 13)).collect();
+    let (mut segments, _) = write_wal(&records, 3);
+    // The second segment's long header has no valid segment size
+    segments[1][32..36].copy_from_slice(&0u32.to_le_bytes());
+    let scan = ParallelScan::new(
+        Box::new(memory_source(&segments)),
+        "000000010000000000000001",
+        Some("000000010000000000000003"),
+    )
+    .unwrap();
+    let Err(e) = scan.scan(|records| records.count()) else {
+        panic!("Scan didn't fail");
+    };
+    let ReaderError::Open(name, _) = &e else {
+        panic!("Unexpected error: {:?}", e);
+    };
+    assert_eq!(name, "000000010000000000000002");
+    // The error opening the segment is kept
+    assert!(std::error::Error::source(&e).is_some());
+}
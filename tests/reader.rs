@@ -1,4 +1,15 @@
-use wal_analyzer::xlog::reader::{parse_filename, XLogFilePos};
+use std::path::PathBuf;
+use std::time::Duration;
+
+mod common;
+
+use common::{write_page_header, WAL_SEG_SIZE, XLOG_BLCKSZ};
+
+use wal_analyzer::xlog::lsn::{parse_filename, XLogFilePos, XLogRecPtr};
+use wal_analyzer::xlog::page::{WalVersion, XLP_FIRST_IS_CONTRECORD, XLP_LONG_HEADER};
+use wal_analyzer::xlog::reader::{ReaderError, XLogReader};
+use wal_analyzer::xlog::record::{compute_record_crc, XLOG_SWITCH};
+use wal_analyzer::xlog::validate::{EndOfWal, EndOfWalReason, ValidationError};
 
 #[cfg(test)]
 #[ctor::ctor]
@@ -20,6 +31,22 @@ fn test_parse_filename() {
             panic!("Unexpected output: {:?}", e)
         }
     }
+
+    // Trailing suffixes are ignored
+    assert_eq!(
+        parse_filename("000000010000000000000002.partial")
+            .unwrap()
+            .seg,
+        2
+    );
+    for input in [
+        "",
+        "00000001000000000000000",
+        "00000001000000000000000G",
+        "00000001000000000000000é",
+    ] {
+        assert!(parse_filename(input).is_err(), "{}", input);
+    }
 }
 
 #[test]
@@ -29,5 +56,784 @@ fn test_xlog_file_pos_to_recptr() {
     let log = 2;
     let seg = 0;
     let res = XLogFilePos { tli, log, seg }.get_xlog_rec_ptr(walsegsize);
-    assert_eq!(res, 33554432);
+    assert_eq!(res, XLogRecPtr::new(0x200000000));
+}
+
+#[test]
+fn test_xlog_file_pos_from_segno() {
+    let walsegsize = 16 * 1024 * 1024;
+    let file_pos = XLogFilePos::from_segno(1, 0x1ff, walsegsize);
+    assert_eq!(file_pos.to_string(), "0000000100000001000000FF");
+    assert_eq!(file_pos.get_segno(walsegsize), 0x1ff);
+
+    let parsed = parse_filename(&file_pos.to_string()).unwrap();
+    assert_eq!(parsed.get_segno(walsegsize), 0x1ff);
+}
+
+/// Heap insert record with a full page image, 232 bytes
+const FPW_RECORD: &[u8; 232] = b"\xe8\x00\x00\x00\xec\x02\x00\x00\x00\x01\x60\x01\x00\x00\x00\x00\x00\x0a\x00\x00\x7e\x34\x63\xfd\x00\x30\x0a\x00\xa8\x00\x28\x00\x05\x7f\x06\x00\x00\xb0\x32\x00\x00\x16\x40\x00\x00\x00\x00\x00\x00\xff\x03\x00\x00\x00\x00\x68\x00\x60\x01\x00\x00\x00\x00\x28\x00\x80\x1f\x00\x20\x04\x20\x00\x00\x00\x00\xe0\x9f\x38\x00\xc0\x9f\x38\x00\xa0\x9f\x38\x00\x80\x9f\x38\x00\xec\x02\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x04\x00\x04\x00\x01\x08\x18\x01\x01\x00\x00\x00\x00\x00\x00\x00\xeb\x02\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x03\x00\x04\x00\x01\x08\x18\x01\x01\x00\x00\x00\x00\x00\x00\x00\xea\x02\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x02\x00\x04\x00\x01\x08\x18\x01\x01\x00\x00\x00\x00\x00\x00\x00\xe8\x02\x00\x00\x00\x00\x00\x00\x03\x00\x00\x00\x00\x00\x00\x00\x01\x00\x04\x00\x01\x09\x18\x01\x01\x00\x00\x00\x00\x00\x00\x00\x04\x00\x01\x08\x18\x01\x01\x00\x00\x00\x04\x00\x08";
+
+#[test]
+fn test_read_record_across_pages() {
+    let record = FPW_RECORD;
+    let mut segment = vec![0u8; 2 * XLOG_BLCKSZ];
+
+    // First page starts with the tail of a record from a previous segment,
+    // covering everything up to the last 104 bytes of the page
+    let record_start = XLOG_BLCKSZ - 104;
+    let long_header_size = 40;
+    write_page_header(
+        &mut segment[..XLOG_BLCKSZ],
+        XLP_LONG_HEADER | XLP_FIRST_IS_CONTRECORD,
+        WAL_SEG_SIZE,
+        (record_start - long_header_size) as u32,
+    );
+    segment[record_start..XLOG_BLCKSZ].copy_from_slice(&record[..104]);
+
+    // Second page holds the record's remaining 128 bytes
+    let short_header_size = 24;
+    write_page_header(
+        &mut segment[XLOG_BLCKSZ..],
+        XLP_FIRST_IS_CONTRECORD,
+        WAL_SEG_SIZE + XLOG_BLCKSZ as u64,
+        128,
+    );
+    let second_page_data = XLOG_BLCKSZ + short_header_size;
+    segment[second_page_data..second_page_data + 128].copy_from_slice(&record[104..]);
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("000000010000000000000001");
+    std::fs::write(&path, &segment).unwrap();
+
+    let mut reader = XLogReader::new_from_filename(path).unwrap();
+    let res = reader.read_record_bytes();
+    assert!(res.is_ok(), "{:?}", res);
+    assert_eq!(res.unwrap().as_deref(), Some(record.as_slice()));
+    assert_eq!(
+        reader.read_rec_ptr(),
+        XLogRecPtr::new(WAL_SEG_SIZE + record_start as u64)
+    );
+    // Record ends after the second page header and the 128 remaining bytes
+    assert_eq!(
+        reader.end_rec_ptr(),
+        XLogRecPtr::new(WAL_SEG_SIZE + (XLOG_BLCKSZ + short_header_size + 128) as u64)
+    );
+
+    // Only zeroes are left after the record
+    let res = reader.read_record_bytes();
+    assert!(matches!(res, Ok(None)), "{:?}", res);
+}
+
+#[test]
+fn test_read_segment_records() {
+    let mut reader =
+        XLogReader::new_from_filename(PathBuf::from("assets/000000010000000000000014")).unwrap();
+    let mut num_records = 0;
+    let mut previous_lsn = XLogRecPtr::new(0x13fcc70);
+    while let Some(record) = reader.read_record_bytes().unwrap() {
+        let tot_len = u32::from_le_bytes(record[..4].try_into().unwrap());
+        assert_eq!(tot_len as usize, record.len());
+
+        // xl_prev points to the previous record's start
+        let xl_prev = XLogRecPtr::new(u64::from_le_bytes(record[8..16].try_into().unwrap()));
+        assert_eq!(xl_prev, previous_lsn);
+        assert_eq!(
+            reader.end_rec_ptr(),
+            reader.read_rec_ptr() + tot_len.next_multiple_of(8) as u64
+        );
+        previous_lsn = reader.read_rec_ptr();
+        num_records += 1;
+    }
+    assert_eq!(num_records, 26);
+    assert_eq!(previous_lsn.to_string(), "0/01400A80");
+}
+
+#[test]
+fn test_read_record_across_segments() {
+    let record = FPW_RECORD;
+    let short_header_size = 24;
+    let long_header_size = 40;
+    let dir = tempfile::tempdir().unwrap();
+
+    // The record starts at the end of the first segment's last page, only
+    // this page is read
+    let last_page = WAL_SEG_SIZE as usize - XLOG_BLCKSZ;
+    let record_start = WAL_SEG_SIZE as usize - 104;
+    let mut first_segment = vec![0u8; WAL_SEG_SIZE as usize];
+    write_page_header(&mut first_segment, XLP_LONG_HEADER, WAL_SEG_SIZE, 0);
+    write_page_header(
+        &mut first_segment[last_page..],
+        XLP_FIRST_IS_CONTRECORD,
+        WAL_SEG_SIZE + last_page as u64,
+        (record_start - last_page - short_header_size) as u32,
+    );
+    first_segment[record_start..].copy_from_slice(&record[..104]);
+    std::fs::write(dir.path().join("000000010000000000000001"), &first_segment).unwrap();
+
+    let mut second_segment = vec![0u8; XLOG_BLCKSZ];
+    write_page_header(
+        &mut second_segment,
+        XLP_LONG_HEADER | XLP_FIRST_IS_CONTRECORD,
+        2 * WAL_SEG_SIZE,
+        128,
+    );
+    second_segment[long_header_size..long_header_size + 128].copy_from_slice(&record[104..]);
+    std::fs::write(dir.path().join("000000010000000000000002"), &second_segment).unwrap();
+
+    let record_lsn = XLogRecPtr::new(WAL_SEG_SIZE + record_start as u64);
+    let mut reader =
+        XLogReader::new(dir.path().to_path_buf(), "000000010000000000000001", None).unwrap();
+    reader.seek(record_lsn).unwrap();
+    let res = reader.read_record_bytes();
+    assert_eq!(res.unwrap().as_deref(), Some(record.as_slice()));
+    assert_eq!(reader.read_rec_ptr(), record_lsn);
+    let res = reader.read_record_bytes();
+    assert!(matches!(res, Ok(None)), "{:?}", res);
+
+    // With an end segment, the record can't be completed
+    let mut reader = XLogReader::new(
+        dir.path().to_path_buf(),
+        "000000010000000000000001",
+        Some("000000010000000000000001"),
+    )
+    .unwrap();
+    reader.seek(record_lsn).unwrap();
+    let res = reader.read_record_bytes();
+    assert!(res.is_err(), "{:?}", res);
+}
+
+#[test]
+fn test_read_from_bytes() {
+    // A single page in the middle of a segment, with a short header
+    let mut page = vec![0u8; XLOG_BLCKSZ];
+    let page_ptr = WAL_SEG_SIZE + XLOG_BLCKSZ as u64;
+    write_page_header(&mut page, 0, page_ptr, 0);
+    page[24..24 + FPW_RECORD.len()].copy_from_slice(FPW_RECORD);
+
+    let start = XLogRecPtr::new(page_ptr);
+    let mut reader = XLogReader::from_bytes(&page, start, WAL_SEG_SIZE).unwrap();
+    let res = reader.read_record_bytes();
+    assert_eq!(res.unwrap().as_deref(), Some(FPW_RECORD.as_slice()));
+    assert_eq!(reader.read_rec_ptr(), start + 24);
+    let res = reader.read_record_bytes();
+    assert!(matches!(res, Ok(None)), "{:?}", res);
+
+    // The stream has to start on a page boundary
+    assert!(XLogReader::from_bytes(&page, start + 24, WAL_SEG_SIZE).is_err());
+}
+
+#[test]
+fn test_read_from_segment_reader() {
+    let f = std::fs::File::open("assets/000000010000000000000014").unwrap();
+    let mut reader = XLogReader::from_segment_reader(f).unwrap();
+    assert_eq!(reader.wal_seg_size(), WAL_SEG_SIZE);
+    assert_eq!(reader.by_ref().count(), 26);
+    assert_eq!(reader.read_rec_ptr().to_string(), "0/01400A80");
+}
+
+#[test]
+fn test_read_stream_across_segments() {
+    let record = FPW_RECORD;
+    let short_header_size = 24;
+    let long_header_size = 40;
+
+    // Same layout as test_read_record_across_segments, both segments
+    // being read from a single stream
+    let last_page = WAL_SEG_SIZE as usize - XLOG_BLCKSZ;
+    let record_start = WAL_SEG_SIZE as usize - 104;
+    let mut stream = vec![0u8; WAL_SEG_SIZE as usize + XLOG_BLCKSZ];
+    write_page_header(&mut stream, XLP_LONG_HEADER, WAL_SEG_SIZE, 0);
+    write_page_header(
+        &mut stream[last_page..],
+        XLP_FIRST_IS_CONTRECORD,
+        WAL_SEG_SIZE + last_page as u64,
+        (record_start - last_page - short_header_size) as u32,
+    );
+    stream[record_start..WAL_SEG_SIZE as usize].copy_from_slice(&record[..104]);
+    let second_segment = &mut stream[WAL_SEG_SIZE as usize..];
+    write_page_header(
+        second_segment,
+        XLP_LONG_HEADER | XLP_FIRST_IS_CONTRECORD,
+        2 * WAL_SEG_SIZE,
+        128,
+    );
+    second_segment[long_header_size..long_header_size + 128].copy_from_slice(&record[104..]);
+
+    let record_lsn = XLogRecPtr::new(WAL_SEG_SIZE + record_start as u64);
+    let mut reader = XLogReader::from_segment_reader(std::io::Cursor::new(stream)).unwrap();
+    reader.seek(record_lsn).unwrap();
+    let res = reader.read_record_bytes();
+    assert_eq!(res.unwrap().as_deref(), Some(record.as_slice()));
+    assert_eq!(reader.read_rec_ptr(), record_lsn);
+    assert_eq!(reader.current_tli(), 1);
+    let res = reader.read_record_bytes();
+    assert!(matches!(res, Ok(None)), "{:?}", res);
+
+    // Segments already read from the stream are gone
+    assert!(reader.seek("0/100000".parse().unwrap()).is_err());
+}
+
+#[test]
+fn test_seek_start_lsn() {
+    let start: XLogRecPtr = "0/1400100".parse().unwrap();
+    let mut reader = XLogReader::new_from_lsn(PathBuf::from("assets"), 1, start).unwrap();
+
+    // The start location is in the middle of a record, reading starts at the
+    // next one
+    reader.read_record_bytes().unwrap().unwrap();
+    assert_eq!(reader.read_rec_ptr(), "0/1400108".parse().unwrap());
+
+    // Seeking on a record boundary starts at this record
+    reader.seek("0/1400028".parse().unwrap()).unwrap();
+    reader.read_record_bytes().unwrap().unwrap();
+    assert_eq!(reader.read_rec_ptr(), "0/1400028".parse().unwrap());
+}
+
+#[test]
+fn test_stop_at_end_lsn() {
+    let mut reader =
+        XLogReader::new_from_filename(PathBuf::from("assets/000000010000000000000014")).unwrap();
+    reader.set_end_lsn("0/1400210".parse().unwrap());
+
+    let mut num_records = 0;
+    while reader.read_record_bytes().unwrap().is_some() {
+        num_records += 1;
+    }
+    // Last record ends exactly at the end location
+    assert_eq!(num_records, 5);
+    assert_eq!(reader.end_rec_ptr(), "0/1400210".parse().unwrap());
+}
+
+#[test]
+fn test_crc_mismatch() {
+    let mut segment = std::fs::read("assets/000000010000000000000014").unwrap();
+    // Corrupt the second record's payload
+    segment[0x68 + 30] ^= 0xff;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("000000010000000000000014");
+    std::fs::write(&path, &segment).unwrap();
+
+    let mut reader = XLogReader::new_from_filename(path).unwrap();
+    assert!(reader.read_record_bytes().unwrap().is_some());
+    let res = reader.read_record_bytes();
+    match res {
+        Err(ReaderError::CrcMismatch(lsn, expected, actual)) => {
+            assert_eq!(lsn, "0/1400068".parse().unwrap());
+            assert_eq!(expected, 0x7d7e7814);
+            assert_ne!(actual, expected);
+        }
+        e => panic!("Unexpected output: {:?}", e),
+    }
+    // Valid records follow, this is corruption and not the end of WAL
+    assert!(reader.end_of_wal().is_none());
+
+    // Reading can go on after the corrupted record
+    assert!(reader.read_record_bytes().unwrap().is_some());
+    assert_eq!(reader.read_rec_ptr(), "0/14000C8".parse().unwrap());
+}
+
+#[test]
+fn test_invalid_xl_prev() {
+    let mut segment = std::fs::read("assets/000000010000000000000014").unwrap();
+    // Third record's xl_prev points to the first record instead of the second
+    segment[0xc8 + 8] = 0x28;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("000000010000000000000014");
+    std::fs::write(&path, &segment).unwrap();
+
+    let mut reader = XLogReader::new_from_filename(path).unwrap();
+    assert!(reader.read_record_bytes().unwrap().is_some());
+    assert!(reader.read_record_bytes().unwrap().is_some());
+    match reader.read_record_bytes() {
+        Err(ReaderError::Validation(lsn, e @ ValidationError::InvalidXlPrev(expected, found))) => {
+            assert_eq!(lsn, "0/14000C8".parse().unwrap());
+            assert_eq!(expected, "0/1400068".parse().unwrap());
+            assert_eq!(found, "0/1400028".parse().unwrap());
+            assert!(e.is_end_of_wal());
+        }
+        e => panic!("Unexpected output: {:?}", e),
+    }
+    assert_eq!(
+        reader.end_of_wal(),
+        Some(&EndOfWal {
+            lsn: "0/14000C8".parse().unwrap(),
+            reason: EndOfWalReason::InvalidXlPrev(
+                "0/1400068".parse().unwrap(),
+                "0/1400028".parse().unwrap()
+            ),
+        })
+    );
+}
+
+#[test]
+fn test_end_of_wal_stale_record() {
+    // The record after the last one written was left by the segment's
+    // previous use, its CRC is valid but it points to an older record
+    let first_lsn = XLogRecPtr::new(WAL_SEG_SIZE + 40);
+    let stale_prev = XLogRecPtr::new(40);
+    let mut segment = segment_with_record(WAL_SEG_SIZE, FPW_RECORD, XLOG_BLCKSZ);
+    let stale_start = 40 + FPW_RECORD.len();
+    let stale = with_xl_prev(FPW_RECORD, stale_prev);
+    segment[stale_start..stale_start + stale.len()].copy_from_slice(&stale);
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("000000010000000000000001");
+    std::fs::write(&path, &segment).unwrap();
+
+    let mut reader = XLogReader::new_from_filename(path).unwrap();
+    let record = reader.next().unwrap().unwrap();
+    assert_eq!(record.lsn, first_lsn);
+    assert!(reader.next().is_none());
+    assert_eq!(
+        reader.end_of_wal(),
+        Some(&EndOfWal {
+            lsn: first_lsn + FPW_RECORD.len() as u64,
+            reason: EndOfWalReason::InvalidXlPrev(first_lsn, stale_prev),
+        })
+    );
+}
+
+/// Build a two pages segment where the first page is covered by the tail of
+/// a previous record and the second page has the provided address
+fn segment_with_second_page_addr(second_pageaddr: u64) -> Vec<u8> {
+    let mut segment = vec![0u8; 2 * XLOG_BLCKSZ];
+    write_page_header(
+        &mut segment[..XLOG_BLCKSZ],
+        XLP_LONG_HEADER | XLP_FIRST_IS_CONTRECORD,
+        WAL_SEG_SIZE,
+        (XLOG_BLCKSZ - 40) as u32,
+    );
+    write_page_header(&mut segment[XLOG_BLCKSZ..], 0, second_pageaddr, 0);
+    segment
+}
+
+#[test]
+fn test_unexpected_page_addr() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("000000010000000000000001");
+
+    // Page left from a recycled segment, this is the end of valid WAL
+    std::fs::write(&path, segment_with_second_page_addr(0x2000)).unwrap();
+    let mut reader = XLogReader::new_from_filename(path.clone()).unwrap();
+    match reader.read_record_bytes() {
+        Err(ReaderError::Validation(lsn, e)) => {
+            assert_eq!(lsn, XLogRecPtr::new(WAL_SEG_SIZE + 0x2000));
+            assert!(e.is_end_of_wal(), "{}", e);
+        }
+        e => panic!("Unexpected output: {:?}", e),
+    }
+    assert_eq!(
+        reader.end_of_wal().map(|end_of_wal| &end_of_wal.reason),
+        Some(&EndOfWalReason::PageAddrMismatch(
+            XLogRecPtr::new(WAL_SEG_SIZE + 0x2000),
+            XLogRecPtr::new(0x2000)
+        ))
+    );
+
+    // Page address ahead of its position is corruption
+    std::fs::write(&path, segment_with_second_page_addr(WAL_SEG_SIZE + 0x4000)).unwrap();
+    let mut reader = XLogReader::new_from_filename(path).unwrap();
+    match reader.read_record_bytes() {
+        Err(ReaderError::Validation(lsn, e)) => {
+            assert_eq!(lsn, XLogRecPtr::new(WAL_SEG_SIZE + 0x2000));
+            assert!(!e.is_end_of_wal(), "{}", e);
+        }
+        e => panic!("Unexpected output: {:?}", e),
+    }
+    assert!(reader.end_of_wal().is_none());
+}
+
+#[test]
+fn test_page_version_mismatch() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("000000010000000000000001");
+
+    // Second page written by PostgreSQL 16
+    let mut segment = segment_with_second_page_addr(WAL_SEG_SIZE + 0x2000);
+    segment[XLOG_BLCKSZ..XLOG_BLCKSZ + 2].copy_from_slice(&WalVersion::V16.magic().to_le_bytes());
+    std::fs::write(&path, segment).unwrap();
+    let mut reader = XLogReader::new_from_filename(path).unwrap();
+    match reader.read_record_bytes() {
+        Err(ReaderError::Validation(
+            lsn,
+            ValidationError::VersionMismatch(WalVersion::V14, WalVersion::V16),
+        )) => assert_eq!(lsn, XLogRecPtr::new(WAL_SEG_SIZE + 0x2000)),
+        e => panic!("Unexpected output: {:?}", e),
+    }
+    assert!(reader.end_of_wal().is_none());
+}
+
+#[test]
+fn test_end_of_wal_zero_length() {
+    let mut reader =
+        XLogReader::new_from_filename(PathBuf::from("assets/000000010000000000000014")).unwrap();
+    while reader.read_record_bytes().unwrap().is_some() {
+        assert!(reader.end_of_wal().is_none());
+    }
+    assert_eq!(
+        reader.end_of_wal(),
+        Some(&EndOfWal {
+            lsn: reader.end_rec_ptr(),
+            reason: EndOfWalReason::ZeroLength,
+        })
+    );
+}
+
+#[test]
+fn test_end_of_wal_bad_magic() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("000000010000000000000001");
+
+    // Second page was never written
+    let mut segment = segment_with_second_page_addr(WAL_SEG_SIZE + 0x2000);
+    segment[XLOG_BLCKSZ..].fill(0);
+    std::fs::write(&path, segment).unwrap();
+    let mut reader = XLogReader::new_from_filename(path).unwrap();
+    assert!(reader.read_record_bytes().is_err());
+    assert_eq!(
+        reader.end_of_wal(),
+        Some(&EndOfWal {
+            lsn: XLogRecPtr::new(WAL_SEG_SIZE),
+            reason: EndOfWalReason::BadMagic(0),
+        })
+    );
+}
+
+#[test]
+fn test_end_of_wal_crc_mismatch() {
+    // Torn write of the second and last record, only zeroes follow it
+    let first_lsn = XLogRecPtr::new(WAL_SEG_SIZE + 40);
+    let mut segment = segment_with_record(WAL_SEG_SIZE, FPW_RECORD, XLOG_BLCKSZ);
+    let second_start = 40 + FPW_RECORD.len();
+    let mut torn = with_xl_prev(FPW_RECORD, first_lsn);
+    torn[30] ^= 0xff;
+    segment[second_start..second_start + torn.len()].copy_from_slice(&torn);
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("000000010000000000000001");
+    std::fs::write(&path, &segment).unwrap();
+
+    let mut reader = XLogReader::new_from_filename(path).unwrap();
+    assert!(reader.next().unwrap().is_ok());
+    assert!(reader.next().is_none());
+    match reader.end_of_wal() {
+        Some(EndOfWal {
+            lsn,
+            reason: EndOfWalReason::CrcMismatch(..),
+        }) => assert_eq!(*lsn, first_lsn + FPW_RECORD.len() as u64),
+        e => panic!("Unexpected output: {:?}", e),
+    }
+}
+
+#[test]
+fn test_segment_size_from_page_header() {
+    // Truncated copy of a 1MB segment
+    let segment = std::fs::read("assets/000000010000000000000014").unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("000000010000000000000014");
+    std::fs::write(&path, &segment[..2 * XLOG_BLCKSZ]).unwrap();
+
+    let mut reader = XLogReader::new_from_filename(path).unwrap();
+    assert_eq!(reader.wal_seg_size(), 1024 * 1024);
+    assert_eq!(reader.xlog_blcksz(), XLOG_BLCKSZ);
+    reader.read_record_bytes().unwrap().unwrap();
+    assert_eq!(reader.read_rec_ptr(), "0/1400028".parse().unwrap());
+
+    // A file larger than its segment size isn't a segment
+    let mut segment = segment_with_second_page_addr(WAL_SEG_SIZE + 0x2000);
+    segment.resize(2 * WAL_SEG_SIZE as usize, 0);
+    let path = dir.path().join("000000010000000000000001");
+    std::fs::write(&path, &segment).unwrap();
+    assert!(XLogReader::new_from_filename(path).is_err());
+}
+
+#[test]
+fn test_iterate_records() {
+    let mut reader =
+        XLogReader::new_from_filename(PathBuf::from("assets/000000010000000000000014")).unwrap();
+    // Every record is returned, decoded or with its error
+    assert_eq!(reader.by_ref().count(), 26);
+    assert_eq!(
+        reader.end_of_wal().map(|end_of_wal| &end_of_wal.reason),
+        Some(&EndOfWalReason::ZeroLength)
+    );
+
+    let mut segment = std::fs::read("assets/000000010000000000000014").unwrap();
+    segment[0x68 + 30] ^= 0xff;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("000000010000000000000014");
+    std::fs::write(&path, &segment).unwrap();
+
+    // Iteration goes on after the corrupted record
+    let reader = XLogReader::new_from_filename(path.clone()).unwrap();
+    let crc_errors: Vec<XLogRecPtr> = reader
+        .filter_map(|res| match res {
+            Err(ReaderError::CrcMismatch(lsn, ..)) => Some(lsn),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(crc_errors, vec!["0/1400068".parse().unwrap()]);
+
+    // The corrupted record is returned as an error, it doesn't end the WAL
+    let mut reader = XLogReader::new_from_filename(path).unwrap();
+    reader.next().unwrap().unwrap();
+    assert!(matches!(
+        reader.next(),
+        Some(Err(ReaderError::CrcMismatch(..)))
+    ));
+    assert!(reader.end_of_wal().is_none());
+}
+
+#[test]
+fn test_iterate_stops_on_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("000000010000000000000001");
+
+    // Recycled page, iteration ends without error
+    std::fs::write(&path, segment_with_second_page_addr(0x2000)).unwrap();
+    let mut reader = XLogReader::new_from_filename(path.clone()).unwrap();
+    assert!(reader.next().is_none());
+    assert!(reader.end_of_wal().is_some());
+
+    // Corrupted page, the error is returned once
+    std::fs::write(&path, segment_with_second_page_addr(WAL_SEG_SIZE + 0x4000)).unwrap();
+    let mut reader = XLogReader::new_from_filename(path).unwrap();
+    match reader.next() {
+        Some(Err(ReaderError::Validation(lsn, _))) => {
+            assert_eq!(lsn, XLogRecPtr::new(WAL_SEG_SIZE + 0x2000))
+        }
+        e => panic!("Unexpected output: {:?}", e),
+    }
+    assert!(reader.next().is_none());
+    assert!(reader.end_of_wal().is_none());
+}
+
+#[test]
+fn test_follow_timeline_switch() {
+    let record = FPW_RECORD;
+    let short_header_size = 24;
+    let long_header_size = 40;
+    let dir = tempfile::tempdir().unwrap();
+
+    // Timeline 2 starts in the second segment
+    std::fs::write(
+        dir.path().join("00000002.history"),
+        format!(
+            "1\t{}\tno recovery target specified\n",
+            XLogRecPtr::new(2 * WAL_SEG_SIZE + 0x100)
+        ),
+    )
+    .unwrap();
+
+    let last_page = WAL_SEG_SIZE as usize - XLOG_BLCKSZ;
+    let record_start = WAL_SEG_SIZE as usize - 104;
+    let mut first_segment = vec![0u8; WAL_SEG_SIZE as usize];
+    write_page_header(&mut first_segment, XLP_LONG_HEADER, WAL_SEG_SIZE, 0);
+    write_page_header(
+        &mut first_segment[last_page..],
+        XLP_FIRST_IS_CONTRECORD,
+        WAL_SEG_SIZE + last_page as u64,
+        (record_start - last_page - short_header_size) as u32,
+    );
+    first_segment[record_start..].copy_from_slice(&record[..104]);
+    std::fs::write(dir.path().join("000000010000000000000001"), &first_segment).unwrap();
+
+    // The old timeline's second segment was recycled, only the new
+    // timeline's copy has the end of the record
+    let mut second_segment = vec![0u8; XLOG_BLCKSZ];
+    write_page_header(&mut second_segment, XLP_LONG_HEADER, 0x2000, 0);
+    std::fs::write(dir.path().join("000000010000000000000002"), &second_segment).unwrap();
+    write_page_header(
+        &mut second_segment,
+        XLP_LONG_HEADER | XLP_FIRST_IS_CONTRECORD,
+        2 * WAL_SEG_SIZE,
+        128,
+    );
+    second_segment[long_header_size..long_header_size + 128].copy_from_slice(&record[104..]);
+    std::fs::write(dir.path().join("000000020000000000000002"), &second_segment).unwrap();
+
+    let record_lsn = XLogRecPtr::new(WAL_SEG_SIZE + record_start as u64);
+    let mut reader =
+        XLogReader::new(dir.path().to_path_buf(), "000000010000000000000001", None).unwrap();
+    reader.seek(record_lsn).unwrap();
+    assert_eq!(reader.current_tli(), 1);
+    let res = reader.read_record_bytes();
+    assert_eq!(res.unwrap().as_deref(), Some(record.as_slice()));
+    assert_eq!(reader.current_tli(), 2);
+
+    // Starting from a location on timeline 2 opens timeline 1's segment
+    let reader = XLogReader::new_from_lsn(dir.path().to_path_buf(), 2, record_lsn).unwrap();
+    assert_eq!(reader.current_tli(), 1);
+}
+
+/// Copy of record with another xl_prev, its CRC is computed again
+fn with_xl_prev(record: &[u8], xl_prev: XLogRecPtr) -> Vec<u8> {
+    let mut record = record.to_vec();
+    record[8..16].copy_from_slice(&xl_prev.as_u64().to_le_bytes());
+    let crc = compute_record_crc(&record);
+    record[20..24].copy_from_slice(&crc.to_le_bytes());
+    record
+}
+
+/// XLOG_SWITCH record, it has no data
+fn switch_record(xl_prev: XLogRecPtr) -> Vec<u8> {
+    let mut record = vec![0u8; 24];
+    record[0..4].copy_from_slice(&24u32.to_le_bytes());
+    record[16] = XLOG_SWITCH;
+    with_xl_prev(&record, xl_prev)
+}
+
+/// Segment starting with a long page header followed by record
+fn segment_with_record(segment_ptr: u64, record: &[u8], len: usize) -> Vec<u8> {
+    let long_header_size = 40;
+    let mut segment = vec![0u8; len];
+    write_page_header(&mut segment, XLP_LONG_HEADER, segment_ptr, 0);
+    segment[long_header_size..long_header_size + record.len()].copy_from_slice(record);
+    segment
+}
+
+#[test]
+fn test_switch_record() {
+    let dir = tempfile::tempdir().unwrap();
+    let switch = switch_record(XLogRecPtr::INVALID);
+    let first_segment = segment_with_record(WAL_SEG_SIZE, &switch, WAL_SEG_SIZE as usize);
+    std::fs::write(dir.path().join("000000010000000000000001"), &first_segment).unwrap();
+    let record = with_xl_prev(FPW_RECORD, XLogRecPtr::new(WAL_SEG_SIZE + 40));
+    let second_segment = segment_with_record(2 * WAL_SEG_SIZE, &record, XLOG_BLCKSZ);
+    std::fs::write(dir.path().join("000000010000000000000002"), &second_segment).unwrap();
+
+    // The zeroes after the switch record aren't the end of WAL
+    let mut reader =
+        XLogReader::new(dir.path().to_path_buf(), "000000010000000000000001", None).unwrap();
+    assert_eq!(reader.read_record_bytes().unwrap(), Some(switch));
+    assert_eq!(reader.end_rec_ptr(), XLogRecPtr::new(2 * WAL_SEG_SIZE));
+    assert_eq!(reader.read_record_bytes().unwrap(), Some(record));
+    assert_eq!(
+        reader.read_rec_ptr(),
+        XLogRecPtr::new(2 * WAL_SEG_SIZE + 40)
+    );
+}
+
+#[test]
+fn test_follow() {
+    let dir = tempfile::tempdir().unwrap();
+    let first_path = dir.path().join("000000010000000000000001");
+    let switch = switch_record(XLogRecPtr::INVALID);
+    // Only the first page of the preallocated segment is written
+    let mut first_segment = vec![0u8; WAL_SEG_SIZE as usize];
+    write_page_header(&mut first_segment, XLP_LONG_HEADER, WAL_SEG_SIZE, 0);
+    std::fs::write(&first_path, &first_segment).unwrap();
+
+    let mut reader =
+        XLogReader::new(dir.path().to_path_buf(), "000000010000000000000001", None).unwrap();
+    reader.set_follow(Some(Duration::from_millis(10)));
+
+    let writer = {
+        let dir = dir.path().to_path_buf();
+        let switch = switch.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            first_segment[40..64].copy_from_slice(&switch);
+            std::fs::write(&first_path, &first_segment).unwrap();
+
+            // The next segment is created after the switch was read
+            std::thread::sleep(Duration::from_millis(50));
+            let record = with_xl_prev(FPW_RECORD, XLogRecPtr::new(WAL_SEG_SIZE + 40));
+            let second_segment = segment_with_record(2 * WAL_SEG_SIZE, &record, XLOG_BLCKSZ);
+            std::fs::write(dir.join("000000010000000000000002"), second_segment).unwrap();
+        })
+    };
+
+    // Reading waits for the records to be written
+    assert_eq!(reader.read_record_bytes().unwrap(), Some(switch));
+    let record = reader.read_record_bytes().unwrap().unwrap();
+    assert_eq!(record[24..], FPW_RECORD[24..]);
+    assert_eq!(
+        reader.read_rec_ptr(),
+        XLogRecPtr::new(2 * WAL_SEG_SIZE + 40)
+    );
+    writer.join().unwrap();
+
+    // An end location still stops the reader
+    reader.set_end_lsn(reader.end_rec_ptr());
+    assert!(matches!(reader.read_record_bytes(), Ok(None)));
+    assert_eq!(
+        reader.end_of_wal().unwrap().reason,
+        EndOfWalReason::EndLsnReached
+    );
+}
+
+#[test]
+fn test_follow_reports_corruption() {
+    let dir = tempfile::tempdir().unwrap();
+    let first_lsn = XLogRecPtr::new(WAL_SEG_SIZE + 40);
+    let second_lsn = first_lsn + FPW_RECORD.len() as u64;
+
+    // A bad CRC followed by a valid record isn't WAL being written
+    let mut segment = segment_with_record(WAL_SEG_SIZE, FPW_RECORD, XLOG_BLCKSZ);
+    let mut corrupted = with_xl_prev(FPW_RECORD, first_lsn);
+    corrupted[30] ^= 0xff;
+    let third = with_xl_prev(FPW_RECORD, second_lsn);
+    let second_start = 40 + FPW_RECORD.len();
+    let third_start = second_start + corrupted.len();
+    segment[second_start..third_start].copy_from_slice(&corrupted);
+    segment[third_start..third_start + third.len()].copy_from_slice(&third);
+    let path = dir.path().join("000000010000000000000001");
+    std::fs::write(&path, &segment).unwrap();
+    let mut reader = XLogReader::new_from_filename(path).unwrap();
+    reader.set_follow(Some(Duration::from_millis(10)));
+    assert!(reader.read_record_bytes().unwrap().is_some());
+    match reader.read_record_bytes() {
+        Err(ReaderError::CrcMismatch(lsn, ..)) => assert_eq!(lsn, second_lsn),
+        e => panic!("Unexpected output: {:?}", e),
+    }
+
+    // The record written while waiting still has its xl_prev checked
+    let second_path = dir.path().join("000000010000000000000002");
+    let switch = switch_record(XLogRecPtr::INVALID);
+    let segment = segment_with_record(2 * WAL_SEG_SIZE, &switch, WAL_SEG_SIZE as usize);
+    std::fs::write(&second_path, &segment).unwrap();
+    let mut reader =
+        XLogReader::new(dir.path().to_path_buf(), "000000010000000000000002", None).unwrap();
+    reader.set_follow(Some(Duration::from_millis(10)));
+    assert_eq!(reader.read_record_bytes().unwrap(), Some(switch));
+
+    let writer = {
+        let dir = dir.path().to_path_buf();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            // Points to the first record instead of the switch record
+            let record = with_xl_prev(FPW_RECORD, first_lsn);
+            let segment = segment_with_record(3 * WAL_SEG_SIZE, &record, XLOG_BLCKSZ);
+            std::fs::write(dir.join("000000010000000000000003"), segment).unwrap();
+        })
+    };
+    match reader.read_record_bytes() {
+        Err(ReaderError::Validation(lsn, ValidationError::InvalidXlPrev(expected, found))) => {
+            assert_eq!(lsn, XLogRecPtr::new(3 * WAL_SEG_SIZE + 40));
+            assert_eq!(expected, XLogRecPtr::new(2 * WAL_SEG_SIZE + 40));
+            assert_eq!(found, first_lsn);
+        }
+        e => panic!("Unexpected output: {:?}", e),
+    }
+    writer.join().unwrap();
+}
+
+#[test]
+fn test_invalid_record_length() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("000000010000000000000001");
+    // Garbage length, it's rejected before allocating the record
+    let mut record = FPW_RECORD.to_vec();
+    record[0..4].copy_from_slice(&0xfffffff0u32.to_le_bytes());
+    std::fs::write(
+        &path,
+        segment_with_record(WAL_SEG_SIZE, &record, XLOG_BLCKSZ),
+    )
+    .unwrap();
+    let mut reader = XLogReader::new_from_filename(path).unwrap();
+    match reader.read_record_bytes() {
+        Err(ReaderError::ParseError(lsn, _, _)) => {
+            assert_eq!(lsn, XLogRecPtr::new(WAL_SEG_SIZE + 40))
+        }
+        e => panic!("Unexpected output: {:?}", e),
+    }
 }
@@ -0,0 +1,91 @@
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use wal_analyzer::xlog::reader::XLogReader;
+use wal_analyzer::xlog::segment::{find_segment_file, Compression, SegmentFile, SEGMENT_SUFFIXES};
+
+const SEGMENT: &str = "assets/000000010000000000000014";
+
+fn compress(data: &[u8], compression: Compression) -> Vec<u8> {
+    match compression {
+        Compression::None => data.to_vec(),
+        Compression::Gzip => {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        }
+        Compression::Zstd => zstd::encode_all(data, 0).unwrap(),
+        Compression::Lz4 => {
+            let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        }
+    }
+}
+
+fn write_segment(dir: &Path, file_name: &str, compression: Compression) -> PathBuf {
+    let data = std::fs::read(SEGMENT).unwrap();
+    let path = dir.join(file_name);
+    std::fs::write(&path, compress(&data, compression)).unwrap();
+    path
+}
+
+#[test]
+fn test_read_compressed_segments() {
+    let expected = XLogReader::new_from_filename(PathBuf::from(SEGMENT))
+        .unwrap()
+        .count();
+    for (suffix, compression) in [
+        (".gz", Compression::Gzip),
+        (".zst", Compression::Zstd),
+        (".lz4", Compression::Lz4),
+        (".partial", Compression::None),
+        (".partial.zst", Compression::Zstd),
+    ] {
+        let dir = tempfile::tempdir().unwrap();
+        let file_name = format!("000000010000000000000014{}", suffix);
+        let path = write_segment(dir.path(), &file_name, compression);
+
+        let f = SegmentFile::open(&path).unwrap();
+        assert_eq!(f.compression(), compression);
+        let reader = XLogReader::new_from_filename(path).unwrap();
+        assert_eq!(reader.count(), expected, "{}", file_name);
+    }
+}
+
+#[test]
+fn test_seek_compressed_segment() {
+    let dir = tempfile::tempdir().unwrap();
+    write_segment(dir.path(), "000000010000000000000014.gz", Compression::Gzip);
+    assert_eq!(
+        find_segment_file(dir.path(), "000000010000000000000014", &SEGMENT_SUFFIXES).unwrap(),
+        dir.path().join("000000010000000000000014.gz")
+    );
+
+    let start = "0/1400100".parse().unwrap();
+    let mut reader = XLogReader::new_from_lsn(dir.path().to_path_buf(), 1, start).unwrap();
+    reader.read_record_bytes().unwrap().unwrap();
+    assert_eq!(reader.read_rec_ptr(), "0/1400108".parse().unwrap());
+
+    // Going backward reopens the file
+    reader.seek("0/1400028".parse().unwrap()).unwrap();
+    reader.read_record_bytes().unwrap().unwrap();
+    assert_eq!(reader.read_rec_ptr(), "0/1400028".parse().unwrap());
+}
+
+#[test]
+fn test_compression_mismatch() {
+    let dir = tempfile::tempdir().unwrap();
+    // Extension says gzip but content is zstd
+    let path = write_segment(dir.path(), "000000010000000000000014.gz", Compression::Zstd);
+    assert!(SegmentFile::open(&path).is_err());
+
+    // Compression is detected from the content without extension
+    let path = write_segment(dir.path(), "000000010000000000000014", Compression::Lz4);
+    let mut f = SegmentFile::open(&path).unwrap();
+    assert_eq!(f.compression(), Compression::Lz4);
+    let mut data = Vec::new();
+    f.read_to_end(&mut data).unwrap();
+    assert_eq!(data, std::fs::read(SEGMENT).unwrap());
+}
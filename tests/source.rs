@@ -0,0 +1,84 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use wal_analyzer::xlog::reader::XLogReader;
+use wal_analyzer::xlog::source::{
+    ArchiveDirectory, MemorySource, RestoreCommand, SegmentSource, WalDirectory,
+};
+
+const SEGMENT_DIR: &str = "assets";
+const SEGMENT_NAME: &str = "000000010000000000000014";
+
+fn expected_count() -> usize {
+    XLogReader::new_from_filename(PathBuf::from(SEGMENT_DIR).join(SEGMENT_NAME))
+        .unwrap()
+        .count()
+}
+
+#[test]
+fn test_memory_source() {
+    let mut source = MemorySource::new();
+    let data = std::fs::read(PathBuf::from(SEGMENT_DIR).join(SEGMENT_NAME)).unwrap();
+    source.insert(SEGMENT_NAME, data);
+    source.insert("00000001.history", Vec::new());
+    assert_eq!(source.list().unwrap(), vec![SEGMENT_NAME.to_string()]);
+    assert_eq!(source.list_segments(1024 * 1024).unwrap(), vec![(1, 0x14)]);
+
+    let reader = XLogReader::from_source(Box::new(source), SEGMENT_NAME, None).unwrap();
+    assert_eq!(reader.count(), expected_count());
+}
+
+#[test]
+fn test_memory_source_at_lsn() {
+    let mut source = MemorySource::new();
+    let data = std::fs::read(PathBuf::from(SEGMENT_DIR).join(SEGMENT_NAME)).unwrap();
+    source.insert(SEGMENT_NAME, data);
+
+    let start = "0/1400100".parse().unwrap();
+    let mut reader = XLogReader::from_source_at_lsn(Box::new(source), 1, start).unwrap();
+    reader.read_record_bytes().unwrap().unwrap();
+    assert_eq!(reader.read_rec_ptr(), "0/1400108".parse().unwrap());
+}
+
+#[test]
+fn test_restore_command() {
+    let archive = std::fs::canonicalize(SEGMENT_DIR).unwrap();
+    let command = format!("cp {}/%f %p", archive.display());
+    let source = RestoreCommand::new(command).unwrap();
+    assert!(source.list().unwrap().is_empty());
+    // A failing command means the file isn't available
+    let err = source.open("00000002.history").err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+
+    let reader = XLogReader::from_source(Box::new(source), SEGMENT_NAME, None).unwrap();
+    assert_eq!(reader.count(), expected_count());
+}
+
+#[test]
+fn test_wal_directory_suffixes() {
+    let dir = tempfile::tempdir().unwrap();
+    let data = std::fs::read(PathBuf::from(SEGMENT_DIR).join(SEGMENT_NAME)).unwrap();
+    std::fs::write(dir.path().join("000000010000000000000014.partial"), &data).unwrap();
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&data).unwrap();
+    std::fs::write(
+        dir.path().join("000000010000000000000015.gz"),
+        encoder.finish().unwrap(),
+    )
+    .unwrap();
+    // Not a segment, with a multibyte character across byte 24
+    std::fs::write(dir.path().join("00000001000000000000001é"), b"").unwrap();
+
+    // pg_wal doesn't hold compressed segments
+    let source = WalDirectory::new(dir.path().to_path_buf());
+    assert_eq!(
+        source.list().unwrap(),
+        vec!["000000010000000000000014.partial".to_string()]
+    );
+    assert!(source.open(SEGMENT_NAME).is_ok());
+    assert!(source.open("000000010000000000000015").is_err());
+
+    let source = ArchiveDirectory::new(dir.path().to_path_buf());
+    assert_eq!(source.list().unwrap().len(), 2);
+    assert!(source.open("000000010000000000000015").is_ok());
+}
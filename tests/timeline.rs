@@ -0,0 +1,82 @@
+use wal_analyzer::xlog::lsn::XLogRecPtr;
+use wal_analyzer::xlog::source::ArchiveDirectory;
+use wal_analyzer::xlog::timeline::{
+    find_newest_timeline, parse_timeline_history, timeline_of_segment, InvalidTimelineHistory,
+    TimeLineHistoryEntry,
+};
+
+const HISTORY: &str = "1\t0/3000158\tno recovery target specified\n\n\
+                       # comment\n\
+                       2\t0/5000000\tbefore 2024-01-01 00:00:00+00\n";
+
+#[test]
+fn test_parse_timeline_history() {
+    let history = parse_timeline_history(HISTORY, 3).unwrap();
+    assert_eq!(
+        history,
+        vec![
+            TimeLineHistoryEntry {
+                tli: 3,
+                begin: "0/5000000".parse().unwrap(),
+                end: XLogRecPtr::INVALID,
+            },
+            TimeLineHistoryEntry {
+                tli: 2,
+                begin: "0/3000158".parse().unwrap(),
+                end: "0/5000000".parse().unwrap(),
+            },
+            TimeLineHistoryEntry {
+                tli: 1,
+                begin: XLogRecPtr::INVALID,
+                end: "0/3000158".parse().unwrap(),
+            },
+        ]
+    );
+
+    // Without history file, the timeline has no parent
+    let history = parse_timeline_history("", 1).unwrap();
+    assert_eq!(history.len(), 1);
+}
+
+#[test]
+fn test_invalid_timeline_history() {
+    let res = parse_timeline_history("2\t0/3000158\n1\t0/5000000\n", 3);
+    assert!(matches!(
+        res,
+        Err(InvalidTimelineHistory::OutOfSequence(1, 2))
+    ));
+
+    let res = parse_timeline_history(HISTORY, 2);
+    assert!(matches!(res, Err(InvalidTimelineHistory::NotAncestor(2))));
+
+    let res = parse_timeline_history("1\n", 2);
+    assert!(matches!(res, Err(InvalidTimelineHistory::Syntax(_))));
+}
+
+#[test]
+fn test_timeline_of_segment() {
+    let wal_seg_size = 16 * 1024 * 1024;
+    let history = parse_timeline_history(HISTORY, 3).unwrap();
+    assert_eq!(timeline_of_segment(&history, 2, wal_seg_size), Some(1));
+    // The segment with the switch point is read from the new timeline
+    assert_eq!(timeline_of_segment(&history, 3, wal_seg_size), Some(2));
+    assert_eq!(timeline_of_segment(&history, 4, wal_seg_size), Some(2));
+    assert_eq!(timeline_of_segment(&history, 5, wal_seg_size), Some(3));
+}
+
+#[test]
+fn test_find_newest_timeline() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("00000002.history"), "1\t0/3000158\t\n").unwrap();
+    std::fs::write(dir.path().join("00000003.history"), HISTORY).unwrap();
+    // Timeline 4 forked from timeline 2 on another branch
+    std::fs::write(dir.path().join("00000004.history"), "2\t0/4000000\t\n").unwrap();
+
+    let source = ArchiveDirectory::new(dir.path().to_path_buf());
+
+    assert_eq!(find_newest_timeline(&source, 1).unwrap(), 3);
+    assert_eq!(find_newest_timeline(&source, 3).unwrap(), 3);
+    // Probing stops at the first missing history file
+    std::fs::remove_file(dir.path().join("00000002.history")).unwrap();
+    assert_eq!(find_newest_timeline(&source, 1).unwrap(), 1);
+}